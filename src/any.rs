@@ -0,0 +1,48 @@
+use lazy_static::lazy_static;
+use serde_yaml::{Mapping, Value};
+
+use crate::{options::FieldOptions, parse::ParseErr, rule::{Rule, RuleEvalResult, RuleEvalSuccess}, valstr};
+
+/// A field that can hold anything at all, for when only the key matters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnyConstraint<'a> {
+    pub field_name: &'a Value,
+    pub default: Option<&'a Value>,
+    pub options: FieldOptions,
+}
+
+impl<'a> AnyConstraint<'a> {
+    pub fn default(field_name: &Value) -> AnyConstraint<'_> {
+        AnyConstraint { field_name, default: None, options: FieldOptions::default() }
+    }
+}
+
+pub fn build<'a>(field_name: &'a Value, config: &'a Mapping, path: &[&'a Value]) -> Result<AnyConstraint<'a>, ParseErr<'a>> {
+    lazy_static! {
+        static ref DEFAULT: Value = valstr!("default");
+    }
+    Ok(AnyConstraint {
+        field_name,
+        default: config.get(&DEFAULT),
+        options: FieldOptions::parse(config, path)?,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnyRule<'a> {
+    pub field_name: &'a Value,
+    pub default: Option<&'a Value>,
+    pub options: FieldOptions,
+}
+
+impl<'a> From<AnyConstraint<'a>> for Rule<'a> {
+    fn from(c: AnyConstraint<'a>) -> Self {
+        Rule::Any(AnyRule { field_name: c.field_name, default: c.default, options: c.options })
+    }
+}
+
+impl<'a> AnyRule<'a> {
+    pub fn eval(&self, _value: &'a Value, path: &[&'a Value]) -> RuleEvalResult<'a> {
+        RuleEvalSuccess::new(true, path).into()
+    }
+}
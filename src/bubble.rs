@@ -27,6 +27,13 @@ impl<T> Bubble<T> {
         }
     }
 
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        match self {
+            Bubble::Single(t) => std::slice::from_ref(t).iter(),
+            Bubble::Multi(vec) => vec.iter(),
+        }
+    }
+
     pub fn all(&self, test: fn(&T) -> bool) -> bool {
         match self {
             Bubble::Single(t) => test(t),
@@ -2,12 +2,16 @@ use lazy_static::lazy_static;
 
 use serde_yaml::{Mapping, Value};
 
-use crate::{obj::{self, ObjectConstraint}, parse::{PEType, ParseErr, YamlParseResult}, str::{self, StringConstraint}, valstr, value_ref::DefaultFetchErr};
+use crate::{any::{self, AnyConstraint}, obj::{self, ObjectConstraint}, options::FieldOptions, parse::{PEType, ParseErr, YamlParseResult}, quantity::{self, QuantityConstraint, Unit}, str::{self, StringConstraint}, temporal::{self, Kind, TemporalConstraint}, valstr, value_ref::DefaultFetchErr, when::{self, ConditionalConstraint}};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Constraint<'a> {
     Str(StringConstraint<'a>),
-    Obj(ObjectConstraint<'a>)
+    Obj(ObjectConstraint<'a>),
+    Qty(QuantityConstraint<'a>),
+    Time(TemporalConstraint<'a>),
+    Any(AnyConstraint<'a>),
+    When(ConditionalConstraint<'a>),
 }
 
 impl<'a> From<Constraint<'a>> for YamlParseResult<'a> {
@@ -41,7 +45,7 @@ impl<'a> Constraint<'a> {
                 ParseErr::new(&path, PEType::Unsupported).into()
             },
             Value::Mapping(m) => {
-                Constraint::for_mapping(field_name, m, &path)
+                Constraint::for_mapping(field_name, m, parent_path)
             }
         }
     }
@@ -50,6 +54,53 @@ impl<'a> Constraint<'a> {
         match self {
             Constraint::Str(c) => c.field_name,
             Constraint::Obj(c) => c.field_name,
+            Constraint::Qty(c) => c.field_name,
+            Constraint::Time(c) => c.field_name,
+            Constraint::Any(c) => c.field_name,
+            Constraint::When(c) => c.field_name(),
+        }
+    }
+
+    pub fn default(&self) -> Option<&'a Value> {
+        match self {
+            Constraint::Str(c) => c.default,
+            Constraint::Obj(c) => c.default,
+            Constraint::Qty(c) => c.default,
+            Constraint::Time(c) => c.default,
+            Constraint::Any(c) => c.default,
+            Constraint::When(c) => c.base.default(),
+        }
+    }
+
+    pub fn options(&self) -> &FieldOptions {
+        match self {
+            Constraint::Str(c) => &c.options,
+            Constraint::Obj(c) => &c.options,
+            Constraint::Qty(c) => &c.options,
+            Constraint::Time(c) => &c.options,
+            Constraint::Any(c) => &c.options,
+            Constraint::When(c) => c.base.options(),
+        }
+    }
+
+    /// The `type` the field was given in the spec
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Constraint::Str(_) => "string",
+            Constraint::Obj(_) => "object",
+            Constraint::Qty(c) => c.unit.name(),
+            Constraint::Time(c) => c.kind.name(),
+            Constraint::Any(_) => "any",
+            Constraint::When(c) => c.base.type_name(),
+        }
+    }
+
+    /// The constraint a conditional falls back on when none of its clauses
+    /// apply, which is also where its default comes from
+    pub fn unconditional(&self) -> &Constraint<'a> {
+        match self {
+            Constraint::When(c) => c.base.unconditional(),
+            _ => self,
         }
     }
 
@@ -57,35 +108,88 @@ impl<'a> Constraint<'a> {
         match field_type {
             "string" => Constraint::Str(StringConstraint::default(field_name)).into(),
             "object" => Constraint::Obj(ObjectConstraint::default(field_name)).into(),
+            "duration" => Constraint::Qty(QuantityConstraint::default(field_name, Unit::Duration)).into(),
+            "bytesize" => Constraint::Qty(QuantityConstraint::default(field_name, Unit::ByteSize)).into(),
+            "datetime" => Constraint::Time(TemporalConstraint::default(field_name, Kind::DateTime)).into(),
+            "date" => Constraint::Time(TemporalConstraint::default(field_name, Kind::Date)).into(),
+            "time" => Constraint::Time(TemporalConstraint::default(field_name, Kind::Time)).into(),
+            "any" => Constraint::Any(AnyConstraint::default(field_name)).into(),
             _ => ParseErr::new(path, PEType::UnknownType(field_type)).into(),
         }
     }
 
-    fn for_mapping(field_name: &'a Value, config: &'a Mapping, path: &[&'a Value]) -> YamlParseResult<'a> {
+    fn for_mapping(field_name: &'a Value, config: &'a Mapping, parent_path: &[&'a Value]) -> YamlParseResult<'a> {
         lazy_static! {
             static ref TYPE: Value = valstr!("type");
+            static ref WHEN: Value = valstr!("when");
         }
         if let Some(Value::String(field_type)) = config.get(&TYPE) {
-            match field_type.as_str() {
-                "string" => match str::build(field_name, config, path) {
-                    Ok(constr) => Constraint::Str(constr).into(),
-                    Err(e) => e.into()
-                },
-                "object" => obj::build(field_name, config, path),
-                _ => ParseErr::new(path, PEType::UnknownType(field_type)).into(),
+            let base = Constraint::for_type(field_type, config, field_name, parent_path);
+            match config.get(&WHEN) {
+                Some(when) => when::build(field_type, when, base, field_name, parent_path),
+                None => base,
             }
         } else {
-            ParseErr::new(path, PEType::InvalidTypeInfo(field_name)).into()
+            let mut path = parent_path.to_vec();
+            path.push(field_name);
+            ParseErr::new(&path, PEType::InvalidTypeInfo(field_name)).into()
+        }
+    }
+
+    pub fn for_type(field_type: &'a str, config: &'a Mapping, field_name: &'a Value, parent_path: &[&'a Value]) -> YamlParseResult<'a> {
+        let mut path = parent_path.to_vec();
+        path.push(field_name);
+        match field_type {
+            "string" => match str::build(field_name, config, &path) {
+                Ok(constr) => Constraint::Str(constr).into(),
+                Err(e) => e.into()
+            },
+            "object" => obj::build(field_name, config, parent_path),
+            "duration" | "bytesize" => {
+                let unit = if field_type == "duration" { Unit::Duration } else { Unit::ByteSize };
+                match quantity::build(field_name, unit, config, &path) {
+                    Ok(constr) => Constraint::Qty(constr).into(),
+                    Err(e) => e.into()
+                }
+            }
+            "datetime" | "date" | "time" => {
+                let kind = match field_type {
+                    "datetime" => Kind::DateTime,
+                    "date" => Kind::Date,
+                    _ => Kind::Time,
+                };
+                match temporal::build(field_name, kind, config, &path) {
+                    Ok(constr) => Constraint::Time(constr).into(),
+                    Err(e) => e.into()
+                }
+            }
+            "any" => match any::build(field_name, config, &path) {
+                Ok(constr) => Constraint::Any(constr).into(),
+                Err(e) => e.into()
+            },
+            _ => ParseErr::new(&path, PEType::UnknownType(field_type)).into(),
+        }
+    }
+
+    /// Like `fetch`, but for paths that don't live as long as the spec
+    pub fn find(&self, path: &[&Value]) -> Option<&Constraint<'a>> {
+        match path.split_first() {
+            None => Some(self),
+            Some((key, rest)) => match self.unconditional() {
+                Constraint::Obj(o) => o.field(key)?.find(rest),
+                _ => None,
+            },
         }
     }
 
     pub fn fetch(&self, path: &[&'a Value]) -> Result<&Constraint<'a>, DefaultFetchErr<'a>> {
         match &self {
-            Constraint::Str(_) => Err(DefaultFetchErr::IncorrectType {
+            Constraint::Str(_) | Constraint::Qty(_) | Constraint::Time(_) | Constraint::Any(_) => Err(DefaultFetchErr::IncorrectType {
                 residual_path: path.to_vec(),
-                constr: self.clone(),
+                constr: Box::new(self.clone()),
             }),
             Constraint::Obj(o) => o.constraint(path),
+            Constraint::When(c) => c.base.fetch(path),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,418 @@
+use std::fmt;
+
+use lazy_static::lazy_static;
+use serde_yaml::{Mapping, Value};
+
+use crate::{constraint::Constraint, document_constraint, obj::{ObjConstr, ObjectConstraint}, options::{Severity, display_value}, parse::ParseErr, quantity::QtyConstr, str::{StrConstr, WrappedRegex}, valstr, value_ref::ValueRef, version};
+
+lazy_static! {
+    static ref ROOT: Value = valstr!(".");
+}
+
+/// One way a field changed between two versions of a spec
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind {
+    /// `required` is whether documents without it now fail, i.e. it's
+    /// required and has no default
+    Added { required: bool },
+    /// `closed` is whether its object now rejects the key
+    Removed { closed: bool },
+    /// the old key is still accepted through `renamed_from`
+    Renamed { from: Value },
+    TypeChanged { from: &'static str, to: &'static str },
+    BecameRequired { default: bool },
+    BecameOptional,
+    /// values that used to be allowed but aren't anymore
+    AllowedNarrowed(Vec<String>),
+    AllowedWidened(Vec<String>),
+    DisallowedAdded(Vec<String>),
+    DisallowedRemoved(Vec<String>),
+    /// whether the new regex accepts less is a guess, and `None` when there
+    /// isn't one
+    RegexChanged { from: String, to: String, tighter: Option<bool> },
+    DefaultAdded,
+    /// `required` is whether documents that relied on the default now fail
+    DefaultRemoved { required: bool },
+    DefaultChanged,
+    SeverityChanged { from: Severity, to: Severity },
+    /// the object stopped accepting keys it has no field for
+    Closed,
+    Opened,
+    /// some other part of what the field accepts changed, which can't be
+    /// told apart from tightening it
+    Changed(&'static str),
+    /// part of what the field accepts was dropped
+    Loosened(&'static str),
+}
+
+impl ChangeKind {
+    /// Whether a document that was valid against the old spec could be
+    /// invalid against the new one
+    pub fn is_breaking(&self) -> bool {
+        match self {
+            ChangeKind::Added { required } => *required,
+            ChangeKind::Removed { closed } => *closed,
+            ChangeKind::TypeChanged { .. } | ChangeKind::AllowedNarrowed(_) | ChangeKind::DisallowedAdded(_)
+            | ChangeKind::Closed | ChangeKind::Changed(_) => true,
+            ChangeKind::BecameRequired { default } => !default,
+            ChangeKind::RegexChanged { tighter, .. } => *tighter != Some(false),
+            ChangeKind::DefaultRemoved { required } => *required,
+            ChangeKind::SeverityChanged { from, to } => *to == Severity::Error && *from != Severity::Error,
+            ChangeKind::Renamed { .. } | ChangeKind::BecameOptional | ChangeKind::AllowedWidened(_) | ChangeKind::DisallowedRemoved(_)
+            | ChangeKind::DefaultAdded | ChangeKind::DefaultChanged | ChangeKind::Opened | ChangeKind::Loosened(_) => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change<'a> {
+    /// the field's path in the new spec, or the old one if it was removed,
+    /// made of field names only
+    pub path: Vec<&'a Value>,
+    pub kind: ChangeKind,
+}
+
+impl Change<'_> {
+    pub fn is_breaking(&self) -> bool {
+        self.kind.is_breaking()
+    }
+}
+
+impl fmt::Display for Change<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path: Vec<_> = self.path.iter().map(|v| display_value(v)).collect();
+        write!(f, "{}: ", path.join("."))?;
+        match &self.kind {
+            ChangeKind::Added { required: true } => write!(f, "added as a required field"),
+            ChangeKind::Added { required: false } => write!(f, "added"),
+            ChangeKind::Removed { closed: true } => write!(f, "removed, and its key is now rejected"),
+            ChangeKind::Removed { closed: false } => write!(f, "removed"),
+            ChangeKind::Renamed { from } => write!(f, "renamed from {}", display_value(from)),
+            ChangeKind::TypeChanged { from, to } => write!(f, "changed from {} to {}", from, to),
+            ChangeKind::BecameRequired { .. } => write!(f, "became required"),
+            ChangeKind::BecameOptional => write!(f, "became optional"),
+            ChangeKind::AllowedNarrowed(values) => write!(f, "no longer allows {}", values.join(", ")),
+            ChangeKind::AllowedWidened(values) => write!(f, "now allows {}", values.join(", ")),
+            ChangeKind::DisallowedAdded(values) => write!(f, "now disallows {}", values.join(", ")),
+            ChangeKind::DisallowedRemoved(values) => write!(f, "no longer disallows {}", values.join(", ")),
+            ChangeKind::RegexChanged { from, to, tighter } => {
+                let guess = match tighter {
+                    Some(true) => "probably tighter",
+                    Some(false) => "probably looser",
+                    None => "can't tell whether it's tighter",
+                };
+                write!(f, "regex changed from {} to {} ({})", from, to, guess)
+            }
+            ChangeKind::DefaultAdded => write!(f, "default added"),
+            ChangeKind::DefaultRemoved { .. } => write!(f, "default removed"),
+            ChangeKind::DefaultChanged => write!(f, "default changed"),
+            ChangeKind::SeverityChanged { from, to } => write!(f, "severity changed from {} to {}", from, to),
+            ChangeKind::Closed => write!(f, "no longer accepts additional keys"),
+            ChangeKind::Opened => write!(f, "now accepts additional keys"),
+            ChangeKind::Changed(what) => write!(f, "{} changed", what),
+            ChangeKind::Loosened(what) => write!(f, "{} loosened", what),
+        }?;
+        if self.is_breaking() {
+            write!(f, " (breaking)")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diff<'a> {
+    pub old_version: Option<&'a Value>,
+    pub new_version: Option<&'a Value>,
+    pub changes: Vec<Change<'a>>,
+}
+
+impl Diff<'_> {
+    pub fn is_breaking(&self) -> bool {
+        self.changes.iter().any(Change::is_breaking)
+    }
+
+    /// Whether the change is breaking but both specs have the same major
+    /// version. Specs without a version header can't tell.
+    pub fn needs_major_version(&self) -> bool {
+        match (self.old_version.and_then(major), self.new_version.and_then(major)) {
+            (Some(old), Some(new)) => self.is_breaking() && old == new,
+            _ => false,
+        }
+    }
+}
+
+fn major(version: &Value) -> Option<u64> {
+    match version {
+        Value::Number(n) => n.as_u64().or_else(|| n.as_f64().map(|f| f as u64)),
+        Value::String(s) => s.split('.').next()?.parse().ok(),
+        _ => None,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DiffErr<'a> {
+    Old(Vec<ParseErr<'a>>),
+    New(Vec<ParseErr<'a>>),
+}
+
+/// Compares two versions of a spec field by field and classifies every change
+/// by whether documents that passed the old spec could fail the new one.
+/// Changes that can't be told apart from a tightening count as breaking.
+pub fn diff<'a>(old_spec: &'a Mapping, new_spec: &'a Mapping) -> Result<Diff<'a>, DiffErr<'a>> {
+    let old = document_constraint(old_spec, &ROOT).map_err(DiffErr::Old)?;
+    let new = document_constraint(new_spec, &ROOT).map_err(DiffErr::New)?;
+    let mut changes = vec![];
+    compare(&old, &new, &[], &mut changes);
+    Ok(Diff { old_version: version(old_spec), new_version: version(new_spec), changes })
+}
+
+fn compare<'a>(old: &Constraint<'a>, new: &Constraint<'a>, path: &[&'a Value], changes: &mut Vec<Change<'a>>) {
+    let mut push = |kind| changes.push(Change { path: path.to_vec(), kind });
+    if old.type_name() != new.type_name() {
+        return push(ChangeKind::TypeChanged { from: old.type_name(), to: new.type_name() });
+    }
+
+    // the document itself is always there
+    if !path.is_empty() {
+        let (was, is) = (old.options(), new.options());
+        match (was.required, is.required) {
+            (false, true) => push(ChangeKind::BecameRequired { default: new.default().is_some() }),
+            (true, false) => push(ChangeKind::BecameOptional),
+            _ => {}
+        }
+        if was.severity != is.severity {
+            push(ChangeKind::SeverityChanged { from: was.severity, to: is.severity });
+        }
+        match (old.default(), new.default()) {
+            (None, Some(_)) => push(ChangeKind::DefaultAdded),
+            (Some(_), None) => push(ChangeKind::DefaultRemoved { required: is.required }),
+            (Some(a), Some(b)) if a != b => push(ChangeKind::DefaultChanged),
+            _ => {}
+        }
+    }
+    match (old, new) {
+        (Constraint::When(_), _) | (_, Constraint::When(_)) => {
+            let clauses = |c: &Constraint<'a>| match c {
+                Constraint::When(w) => w.clauses.clone(),
+                _ => vec![],
+            };
+            if clauses(old) != clauses(new) {
+                push(ChangeKind::Changed("the conditions"));
+            }
+        }
+        _ => {}
+    }
+
+    match (old.unconditional(), new.unconditional()) {
+        (Constraint::Str(a), Constraint::Str(b)) => {
+            if a.normalize != b.normalize {
+                push(ChangeKind::Changed("normalization"));
+            }
+            compare_str(&a.constr, &b.constr, &mut push);
+            if a.checks != b.checks {
+                match b.checks.iter().all(|check| a.checks.contains(check)) {
+                    true => push(ChangeKind::Loosened("the extra checks")),
+                    false => push(ChangeKind::Changed("the extra checks")),
+                }
+            }
+        }
+        (Constraint::Qty(a), Constraint::Qty(b)) => match (&a.constr, &b.constr) {
+            (QtyConstr::Allowed(x), QtyConstr::Allowed(y)) => lists(x, y, ChangeKind::AllowedNarrowed, ChangeKind::AllowedWidened, &mut push),
+            (QtyConstr::Disallowed(x), QtyConstr::Disallowed(y)) => lists(x, y, ChangeKind::DisallowedRemoved, ChangeKind::DisallowedAdded, &mut push),
+            (x, y) if x == y => {}
+            (_, QtyConstr::Any) => push(ChangeKind::Loosened("the constraint")),
+            _ => push(ChangeKind::Changed("the constraint")),
+        },
+        (Constraint::Time(a), Constraint::Time(b)) => {
+            if (&a.after, &a.before) != (&b.after, &b.before) {
+                match (b.after.is_none() || a.after == b.after) && (b.before.is_none() || a.before == b.before) {
+                    true => push(ChangeKind::Loosened("the bounds")),
+                    false => push(ChangeKind::Changed("the bounds")),
+                }
+            }
+            if a.timezone != b.timezone {
+                push(ChangeKind::Changed("the timezone requirement"));
+            }
+        }
+        (Constraint::Obj(a), Constraint::Obj(b)) => compare_obj(a, b, path, changes),
+        _ => {}
+    }
+}
+
+fn compare_str(old: &StrConstr, new: &StrConstr, push: &mut impl FnMut(ChangeKind)) {
+    match (old, new) {
+        (StrConstr::Allowed(x), StrConstr::Allowed(y)) => lists(x, y, ChangeKind::AllowedNarrowed, ChangeKind::AllowedWidened, push),
+        (StrConstr::Disallowed(x), StrConstr::Disallowed(y)) => lists(x, y, ChangeKind::DisallowedRemoved, ChangeKind::DisallowedAdded, push),
+        (StrConstr::Regex(x), StrConstr::Regex(y)) if x != y => push(ChangeKind::RegexChanged {
+            from: x.pattern.clone(),
+            to: y.pattern.clone(),
+            tighter: tighter(x, y),
+        }),
+        (x, y) if x == y => {}
+        (_, StrConstr::Any) => push(ChangeKind::Loosened("the constraint")),
+        _ => push(ChangeKind::Changed("the constraint")),
+    }
+}
+
+fn describe<T: Clone + Into<Value>>(value: &ValueRef<T>) -> String {
+    match value {
+        ValueRef::Literal(v) => display_value(&(*v).clone().into()),
+        ValueRef::AbsolutePath(path) => format!("[{}]", path.iter().map(|v| display_value(v)).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+/// Reports the values only the old list has, then those only the new one has
+fn lists<T: PartialEq + Clone + Into<Value>>(old: &[ValueRef<T>], new: &[ValueRef<T>], dropped: fn(Vec<String>) -> ChangeKind, added: fn(Vec<String>) -> ChangeKind, push: &mut impl FnMut(ChangeKind)) {
+    let only = |a: &[ValueRef<T>], b: &[ValueRef<T>]| a.iter().filter(|v| !b.contains(v)).map(describe).collect::<Vec<_>>();
+    let (gone, new) = (only(old, new), only(new, old));
+    if !gone.is_empty() {
+        push(dropped(gone));
+    }
+    if !new.is_empty() {
+        push(added(new));
+    }
+}
+
+/// A guess at whether the new regex accepts less than the old one. Anchoring
+/// a pattern, matching it in full or wrapping more around an unanchored one
+/// are taken as tightening, and the opposites, or adding an alternative, as
+/// loosening.
+fn tighter(old: &WrappedRegex, new: &WrappedRegex) -> Option<bool> {
+    let mut signs = vec![];
+    if old.options.full != new.options.full {
+        signs.push(new.options.full);
+    }
+    if old.options.case_insensitive != new.options.case_insensitive {
+        signs.push(!new.options.case_insensitive);
+    }
+    let (a, b) = (old.pattern.as_str(), new.pattern.as_str());
+    if a != b {
+        let core = |p: &str| p.trim_start_matches('^').trim_end_matches('$').to_string();
+        let anchors = |p: &str| p.len() - core(p).len();
+        let (core_a, core_b) = (core(a), core(b));
+        if core_a == core_b {
+            signs.push(anchors(b) > anchors(a));
+        } else if alternatives(b).iter().any(|alt| *alt == a || core(alt) == core_a) {
+            signs.push(false);
+        } else if anchors(a) + anchors(b) > 0 || old.options.full || new.options.full {
+            // more around the core only narrows a pattern that can match anywhere
+            return None;
+        } else if core_b.contains(&core_a) {
+            signs.push(true);
+        } else if core_a.contains(&core_b) {
+            signs.push(false);
+        } else {
+            return None;
+        }
+    }
+    match signs.split_first() {
+        Some((first, rest)) if rest.iter().all(|s| s == first) => Some(*first),
+        Some(_) => None,
+        None => Some(false),
+    }
+}
+
+/// The top-level branches of a pattern like `a|b(c|d)`
+fn alternatives(pattern: &str) -> Vec<&str> {
+    let (mut branches, mut start, mut depth, mut escaped) = (vec![], 0, 0, false);
+    for (i, c) in pattern.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            '|' if depth == 0 => {
+                branches.push(&pattern[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    branches.push(&pattern[start..]);
+    branches
+}
+
+fn compare_obj<'a>(old: &ObjectConstraint<'a>, new: &ObjectConstraint<'a>, path: &[&'a Value], changes: &mut Vec<Change<'a>>) {
+    let mut push = |kind| changes.push(Change { path: path.to_vec(), kind });
+    match (old.additional_keys, new.additional_keys) {
+        (true, false) => push(ChangeKind::Closed),
+        (false, true) => push(ChangeKind::Opened),
+        _ => {}
+    }
+    match (&old.constr, &new.constr) {
+        (ObjConstr::Discriminated { key: a, variants: x, .. }, ObjConstr::Discriminated { key: b, variants: y, .. }) => {
+            let tags = |v: &[(&'a Value, _)]| v.iter().map(|(tag, _)| *tag).collect::<Vec<_>>();
+            let (was, is) = (tags(x), tags(y));
+            if a != b || was.iter().any(|tag| !is.contains(tag)) {
+                push(ChangeKind::Changed("the variants"));
+            } else if is.len() > was.len() {
+                push(ChangeKind::Loosened("the variants"));
+            }
+        }
+        (ObjConstr::Discriminated { .. }, _) | (_, ObjConstr::Discriminated { .. }) => push(ChangeKind::Changed("the variants")),
+        _ => {}
+    }
+    for (what, dropped, added) in [
+        ("the key relations", old.relations.iter().any(|r| !new.relations.contains(r)), new.relations.iter().any(|r| !old.relations.contains(r))),
+        ("the assertions", old.assertions.iter().any(|a| !new.assertions.contains(a)), new.assertions.iter().any(|a| !old.assertions.contains(a))),
+    ] {
+        match (dropped, added) {
+            (_, true) => push(ChangeKind::Changed(what)),
+            (true, false) => push(ChangeKind::Loosened(what)),
+            _ => {}
+        }
+    }
+
+    let (was, is) = (old.fields(), new.fields());
+    let field_path = |key| {
+        let mut field_path = path.to_vec();
+        field_path.push(key);
+        field_path
+    };
+    let mut renamed = vec![];
+    for (key, before) in &was {
+        match is.iter().find(|(k, _)| k == key) {
+            Some((_, after)) => compare(before, after, &field_path(key), changes),
+            None => match is.iter().find(|(_, c)| c.options().renamed_from.contains(key)) {
+                Some((new_key, after)) => {
+                    renamed.push(*new_key);
+                    changes.push(Change { path: field_path(new_key), kind: ChangeKind::Renamed { from: (*key).clone() } });
+                    compare(before, after, &field_path(new_key), changes);
+                }
+                None => changes.push(Change { path: field_path(key), kind: ChangeKind::Removed { closed: !new.additional_keys } }),
+            },
+        }
+    }
+    for (key, after) in &is {
+        if !was.iter().any(|(k, _)| k == key) && !renamed.contains(key) {
+            let required = after.options().required && after.default().is_none();
+            changes.push(Change { path: field_path(key), kind: ChangeKind::Added { required } });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regex_change(old: &str, new: &str) -> Option<bool> {
+        let spec = |pattern: &str| serde_yaml::from_str::<Mapping>(&format!("name:\n  type: string\n  regex: '{}'\n", pattern)).unwrap();
+        let (old, new) = (spec(old), spec(new));
+        match diff(&old, &new).unwrap().changes.as_slice() {
+            [Change { kind: ChangeKind::RegexChanged { tighter, .. }, .. }] => *tighter,
+            changes => panic!("{:?}", changes),
+        }
+    }
+
+    #[test]
+    fn regex_heuristics() {
+        assert_eq!(Some(true), regex_change("[a-z]+", "^[a-z]+$"));
+        assert_eq!(Some(false), regex_change("^v\\d+$", "^v\\d+$|^latest$"));
+        assert_eq!(Some(true), regex_change("\\d+", "\\d+-rc"));
+        assert_eq!(None, regex_change("[a-z]+", "\\w{3}"));
+        // anchored, the shorter pattern isn't the looser one
+        assert_eq!(None, regex_change("^[a-z]+$", "^[a-z]$"));
+        assert_eq!(None, regex_change("^\\d+-rc$", "^\\d+$"));
+        assert_eq!(vec!["a", "b(c|d)", "[|]"], alternatives("a|b(c|d)|[|]"));
+    }
+}
@@ -0,0 +1,434 @@
+use std::collections::{HashMap, HashSet};
+
+use lazy_static::lazy_static;
+use serde_yaml::{Mapping, Number, Value};
+
+use crate::{constraint::Constraint, document_constraint, format::Format, obj::{KeyRelation, ObjConstr, ObjectConstraint}, options::{Severity, display_value}, parse::ParseErr, quantity::{QtyConstr, QuantityConstraint}, str::{LengthUnit, NormalForm, RegexOptions, StrConstr, StringConstraint}, temporal::{TemporalConstraint, Timezone}, valstr, value_ref::ValueRef, when::{ConditionalConstraint, Test}};
+
+lazy_static! {
+    static ref ROOT: Value = valstr!(".");
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocFormat {
+    Markdown,
+    Html,
+}
+
+/// A piece of a constraint's description. Only the renderer knows how to
+/// quote code or link to another field's row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Span {
+    Text(String),
+    Code(String),
+    /// a reference to another field, by its field names
+    Link(Vec<String>),
+}
+
+/// One row of the field reference
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row<'a> {
+    pub path: Vec<&'a Value>,
+    pub type_name: &'static str,
+    /// whether documents have to set it, i.e. it's required and has no default
+    pub required: bool,
+    pub default: Option<&'a Value>,
+    /// everything the field has to satisfy, one entry per constraint
+    pub rules: Vec<Vec<Span>>,
+    pub description: Option<String>,
+}
+
+/// Every field in the spec, depth first. Fields are in key order, and the
+/// fields of a variant come after the ones every variant shares.
+pub fn reference(spec: &Mapping) -> Result<Vec<Row<'_>>, Vec<ParseErr<'_>>> {
+    let document = document_constraint(spec, &ROOT)?;
+    let mut rows = vec![];
+    if let Constraint::Obj(obj) = &document {
+        fields(obj, &[], &mut rows);
+    }
+    Ok(rows)
+}
+
+/// The field reference as a table, with references to other fields linking to
+/// their rows
+pub fn render(spec: &Mapping, format: DocFormat) -> Result<String, Vec<ParseErr<'_>>> {
+    let rows = reference(spec)?;
+    Ok(match format {
+        DocFormat::Markdown => markdown(&rows),
+        DocFormat::Html => html(&rows),
+    })
+}
+
+fn fields<'a>(obj: &ObjectConstraint<'a>, path: &[&'a Value], rows: &mut Vec<Row<'a>>) {
+    let sorted = |fields: &HashMap<&'a Value, Constraint<'a>>| {
+        let mut fields: Vec<_> = fields.values().cloned().collect();
+        fields.sort_by_key(|c| display_value(c.field_name()));
+        fields
+    };
+    match &obj.constr {
+        ObjConstr::Fields(common) => sorted(common).iter().for_each(|c| field(c, path, None, rows)),
+        ObjConstr::Discriminated { key, fields, variants } => {
+            sorted(fields).iter().for_each(|c| field(c, path, None, rows));
+            for (tag, variant) in variants {
+                sorted(variant).iter().for_each(|c| field(c, path, Some((*key, *tag)), rows));
+            }
+        }
+        ObjConstr::Any => {}
+    }
+}
+
+/// `variant` is the discriminator key and the tag that brings the field in
+fn field<'a>(constraint: &Constraint<'a>, parent: &[&'a Value], variant: Option<(&'a Value, &'a Value)>, rows: &mut Vec<Row<'a>>) {
+    let mut path = parent.to_vec();
+    path.push(constraint.field_name());
+    let options = constraint.options();
+
+    let mut rules = vec![];
+    if let Some((key, tag)) = variant {
+        rules.push(vec![text("only when "), code(key), text(" is "), code(tag)]);
+    }
+    describe(constraint, &mut rules);
+    match options.severity {
+        Severity::Error => {}
+        Severity::Warning => rules.push(vec![text("failures are only warnings")]),
+        Severity::Info => rules.push(vec![text("failures are only informational")]),
+    }
+    if let Some(hint) = &options.deprecated {
+        rules.push(vec![text(&format!("deprecated: {}", hint))]);
+    }
+    if !options.renamed_from.is_empty() {
+        rules.push(listed("formerly ", options.renamed_from.iter().map(code).collect()));
+    }
+    for (old, new) in &options.deprecated_values {
+        rules.push(vec![code(old), text(" is deprecated, use "), code(new)]);
+    }
+
+    rows.push(Row {
+        path: path.clone(),
+        type_name: constraint.type_name(),
+        required: options.required && constraint.default().is_none(),
+        default: constraint.default(),
+        rules,
+        description: options.description.clone(),
+    });
+    if let Constraint::Obj(obj) = constraint.unconditional() {
+        fields(obj, &path, rows);
+    }
+}
+
+fn describe(constraint: &Constraint, rules: &mut Vec<Vec<Span>>) {
+    match constraint {
+        Constraint::Str(c) => describe_str(c, rules),
+        Constraint::Qty(c) => describe_qty(c, rules),
+        Constraint::Time(c) => describe_time(c, rules),
+        Constraint::Obj(c) => describe_obj(c, rules),
+        Constraint::Any(_) => {}
+        Constraint::When(c) => describe_when(c, rules),
+    }
+}
+
+fn describe_str(c: &StringConstraint, rules: &mut Vec<Vec<Span>>) {
+    let string = |s: &String| s.clone();
+    for constr in c.constrs() {
+        match constr {
+            StrConstr::Allowed(values) => rules.push(listed("one of ", values.iter().map(|v| operand(v, string)).collect())),
+            StrConstr::Disallowed(values) => rules.push(listed("none of ", values.iter().map(|v| operand(v, string)).collect())),
+            StrConstr::Regex(regex) => {
+                let mut rule = vec![text("matches "), Span::Code(regex.pattern.clone())];
+                let options = RegexOptions { size_limit: None, ..regex.options };
+                if options != RegexOptions::default() {
+                    rule.push(text(&format!(" ({})", options)));
+                }
+                rules.push(rule);
+            }
+            StrConstr::Equals(v) => rules.push(vec![text("equal to "), operand(v, string)]),
+            StrConstr::NotEquals(v) => rules.push(vec![text("not equal to "), operand(v, string)]),
+            StrConstr::Length { min, max, unit } => {
+                let number = |n: &Number| n.to_string();
+                let unit = match unit {
+                    LengthUnit::Chars => " characters",
+                    LengthUnit::Graphemes => " graphemes",
+                };
+                match (min, max) {
+                    (Some(min), Some(max)) => rules.push(vec![text("between "), operand(min, number), text(" and "), operand(max, number), text(unit)]),
+                    (Some(min), None) => rules.push(vec![text("at least "), operand(min, number), text(unit)]),
+                    (None, Some(max)) => rules.push(vec![text("at most "), operand(max, number), text(unit)]),
+                    (None, None) => {}
+                }
+            }
+            StrConstr::StartsWith(v) => rules.push(vec![text("starts with "), operand(v, string)]),
+            StrConstr::EndsWith(v) => rules.push(vec![text("ends with "), operand(v, string)]),
+            StrConstr::Contains(v) => rules.push(vec![text("contains "), operand(v, string)]),
+            StrConstr::Format(format) => {
+                let mut rule = vec![text(&format!("a valid {}", format.name()))];
+                if let Format::Uri { schemes: Some(schemes) } | Format::Url { schemes: Some(schemes) } = format {
+                    rule.extend(listed(" with the scheme ", schemes.iter().map(|s| Span::Code(s.to_string())).collect()));
+                }
+                rules.push(rule);
+            }
+            StrConstr::Any => {}
+        }
+    }
+    let normalize = &c.normalize;
+    let mut steps = vec![];
+    if normalize.trim {
+        steps.push("trimmed");
+    }
+    match normalize.form {
+        Some(NormalForm::Nfc) => steps.push("NFC normalized"),
+        Some(NormalForm::Nfkc) => steps.push("NFKC normalized"),
+        None => {}
+    }
+    if normalize.case_insensitive {
+        steps.push("compared ignoring case");
+    }
+    if !steps.is_empty() {
+        rules.push(vec![text(&steps.join(", "))]);
+    }
+}
+
+fn describe_qty(c: &QuantityConstraint, rules: &mut Vec<Vec<Span>>) {
+    let value = |v| operand(v, display_value);
+    let rule = match &c.constr {
+        QtyConstr::Allowed(values) => listed("one of ", values.iter().map(value).collect()),
+        QtyConstr::Disallowed(values) => listed("none of ", values.iter().map(value).collect()),
+        QtyConstr::Range { min: Some(min), max: Some(max) } => vec![text("between "), value(min), text(" and "), value(max)],
+        QtyConstr::Range { min: Some(min), max: None } => vec![text("at least "), value(min)],
+        QtyConstr::Range { min: None, max: Some(max) } => vec![text("at most "), value(max)],
+        QtyConstr::Equals(v) => vec![text("equal to "), value(v)],
+        QtyConstr::NotEquals(v) => vec![text("not equal to "), value(v)],
+        QtyConstr::GreaterThan(v) => vec![text("more than "), value(v)],
+        QtyConstr::GreaterThanEq(v) => vec![text("at least "), value(v)],
+        QtyConstr::LessThan(v) => vec![text("less than "), value(v)],
+        QtyConstr::LessThanEq(v) => vec![text("at most "), value(v)],
+        QtyConstr::Range { min: None, max: None } | QtyConstr::Any => return,
+    };
+    rules.push(rule);
+}
+
+fn describe_time(c: &TemporalConstraint, rules: &mut Vec<Vec<Span>>) {
+    if let Some(after) = &c.after {
+        rules.push(vec![text("after "), operand(after, display_value)]);
+    }
+    if let Some(before) = &c.before {
+        rules.push(vec![text("before "), operand(before, display_value)]);
+    }
+    match c.timezone {
+        Timezone::Any => {}
+        Timezone::Required => rules.push(vec![text("has to give its UTC offset")]),
+        Timezone::Forbidden => rules.push(vec![text("can't give a UTC offset")]),
+        Timezone::Utc => rules.push(vec![text("has to be in UTC")]),
+    }
+}
+
+fn describe_obj(c: &ObjectConstraint, rules: &mut Vec<Vec<Span>>) {
+    if let ObjConstr::Discriminated { key, variants, .. } = &c.constr {
+        let mut rule = vec![code(key), text(" picks the variant")];
+        rule.extend(listed(": ", variants.iter().map(|(tag, _)| code(tag)).collect()));
+        rules.push(rule);
+    }
+    if !c.additional_keys {
+        rules.push(vec![text("no other keys")]);
+    }
+    for relation in &c.relations {
+        match relation {
+            KeyRelation::Exclusive(keys) => rules.push(listed("at most one of ", keys.iter().map(|k| code(k)).collect())),
+            KeyRelation::OneRequired(keys) => rules.push(listed("at least one of ", keys.iter().map(|k| code(k)).collect())),
+            KeyRelation::DependsOn { key, requires } => {
+                let mut rule = vec![code(key), text(" also needs ")];
+                rule.extend(listed("", requires.iter().map(|k| code(k)).collect()));
+                rules.push(rule);
+            }
+        }
+    }
+    for assertion in &c.assertions {
+        rules.push(vec![text("asserts "), Span::Code(String::from(assertion.source))]);
+    }
+}
+
+/// The base constraint applies unless a clause says otherwise
+fn describe_when(c: &ConditionalConstraint, rules: &mut Vec<Vec<Span>>) {
+    describe(&c.base, rules);
+    let required = c.base.options().required;
+    let branch = |rule: &mut Vec<Span>, constraint: &Constraint| {
+        let mut branch = vec![];
+        if constraint.options().required != required {
+            branch.push(vec![text(if constraint.options().required { "required" } else { "optional" })]);
+        }
+        describe(constraint, &mut branch);
+        match branch.is_empty() {
+            true => rule.push(text("anything")),
+            false => rule.extend(joined(branch, "; ")),
+        }
+    };
+    for clause in &c.clauses {
+        let condition = &clause.condition;
+        let mut rule = vec![text("when "), operand(&condition.field, display_value)];
+        match &condition.test {
+            Test::Eq(v) => rule.extend(vec![text(" is "), code(v)]),
+            Test::Neq(v) => rule.extend(vec![text(" isn't "), code(v)]),
+            Test::In(values) => rule.extend(listed(" is one of ", values.iter().map(|v| code(v)).collect())),
+            Test::Present(true) => rule.push(text(" is set")),
+            Test::Present(false) => rule.push(text(" isn't set")),
+        }
+        rule.push(text(": "));
+        branch(&mut rule, &clause.then);
+        if let Some(otherwise) = &clause.otherwise {
+            rule.push(text(", otherwise: "));
+            branch(&mut rule, otherwise);
+        }
+        rules.push(rule);
+    }
+}
+
+fn text(s: &str) -> Span {
+    Span::Text(String::from(s))
+}
+
+fn code(value: &Value) -> Span {
+    Span::Code(inline(value))
+}
+
+fn operand<T>(value: &ValueRef<T>, literal: fn(&T) -> String) -> Span {
+    match value {
+        ValueRef::Literal(v) => Span::Code(literal(v)),
+        ValueRef::AbsolutePath(path) => Span::Link(path.iter().map(|v| display_value(v)).collect()),
+    }
+}
+
+fn listed(prefix: &str, items: Vec<Span>) -> Vec<Span> {
+    let mut spans = vec![text(prefix)];
+    spans.extend(joined(items.into_iter().map(|item| vec![item]).collect(), ", "));
+    spans
+}
+
+fn joined(parts: Vec<Vec<Span>>, separator: &str) -> Vec<Span> {
+    let mut spans = vec![];
+    for (i, part) in parts.into_iter().enumerate() {
+        if i > 0 {
+            spans.push(text(separator));
+        }
+        spans.extend(part);
+    }
+    spans
+}
+
+/// A value on one line, since it has to fit in a table cell
+fn inline(value: &Value) -> String {
+    match value {
+        Value::Sequence(seq) => format!("[{}]", seq.iter().map(inline).collect::<Vec<_>>().join(", ")),
+        Value::Mapping(m) => {
+            let entries: Vec<_> = m.iter().map(|(k, v)| format!("{}: {}", inline(k), inline(v))).collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        other => display_value(other),
+    }
+}
+
+fn dotted(names: &[String]) -> String {
+    names.join(".")
+}
+
+fn anchor(names: &[String]) -> String {
+    names.iter()
+        .map(|name| name.to_lowercase().chars().map(|c| if c.is_alphanumeric() || c == '_' { c } else { '-' }).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn names(path: &[&Value]) -> Vec<String> {
+    path.iter().map(|v| display_value(v)).collect()
+}
+
+const HEADINGS: [&str; 6] = ["Field", "Type", "Required", "Default", "Constraints", "Description"];
+
+/// Cells for each row, with a field's anchor only on the first row that has
+/// its path, since a key can show up in more than one variant
+fn cells(rows: &[Row], field: impl Fn(&[String], bool) -> String, span: impl Fn(&Span) -> String, escape: impl Fn(&str) -> String) -> Vec<[String; 6]> {
+    let mut anchored = HashSet::new();
+    rows.iter()
+        .map(|row| {
+            let names = names(&row.path);
+            let first = anchored.insert(names.clone());
+            let rules: Vec<_> = row.rules.iter().map(|rule| rule.iter().map(&span).collect::<String>()).collect();
+            [
+                field(&names, first),
+                String::from(row.type_name),
+                String::from(if row.required { "yes" } else { "no" }),
+                row.default.map(|d| span(&code(d))).unwrap_or_default(),
+                rules.join("<br>"),
+                row.description.as_deref().map(|d| escape(&d.split_whitespace().collect::<Vec<_>>().join(" "))).unwrap_or_default(),
+            ]
+        })
+        .collect()
+}
+
+fn markdown(rows: &[Row]) -> String {
+    let escape = |s: &str| s.replace('|', "\\|").replace('<', "&lt;");
+    let code = |s: &str| match s.contains('`') {
+        true => format!("`` {} ``", s.replace('|', "\\|")),
+        false => format!("`{}`", s.replace('|', "\\|")),
+    };
+    let span = |span: &Span| match span {
+        Span::Text(t) => escape(t),
+        Span::Code(c) => code(c),
+        Span::Link(names) => format!("[{}](#{})", code(&dotted(names)), anchor(names)),
+    };
+    let field = |names: &[String], first: bool| match first {
+        true => format!("<a id=\"{}\"></a>{}", anchor(names), code(&dotted(names))),
+        false => code(&dotted(names)),
+    };
+
+    let line = |cells: &[String]| {
+        let cells: Vec<_> = cells.iter().map(|c| if c.is_empty() { String::from(" ") } else { format!(" {} ", c) }).collect();
+        format!("|{}|\n", cells.join("|"))
+    };
+    let mut out = line(&HEADINGS.map(String::from));
+    out.push_str(&line(&[(); 6].map(|_| String::from("---"))));
+    for cells in cells(rows, field, span, escape) {
+        out.push_str(&line(&cells));
+    }
+    out
+}
+
+fn html(rows: &[Row]) -> String {
+    let escape = |s: &str| s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+    let span = |span: &Span| match span {
+        Span::Text(t) => escape(t),
+        Span::Code(c) => format!("<code>{}</code>", escape(c)),
+        Span::Link(names) => format!("<a href=\"#{}\"><code>{}</code></a>", anchor(names), escape(&dotted(names))),
+    };
+    let field = |names: &[String], first: bool| match first {
+        true => format!("<code id=\"{}\">{}</code>", anchor(names), escape(&dotted(names))),
+        false => format!("<code>{}</code>", escape(&dotted(names))),
+    };
+
+    let mut out = String::from("<table>\n  <thead>\n    <tr>");
+    HEADINGS.iter().for_each(|h| out.push_str(&format!("<th>{}</th>", h)));
+    out.push_str("</tr>\n  </thead>\n  <tbody>\n");
+    for cells in cells(rows, field, span, escape) {
+        out.push_str("    <tr>");
+        cells.iter().for_each(|c| out.push_str(&format!("<td>{}</td>", c)));
+        out.push_str("</tr>\n");
+    }
+    out.push_str("  </tbody>\n</table>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn references_link_to_their_rows() {
+        let spec: Mapping = serde_yaml::from_str("
+min: { type: duration, description: 'the shortest | wait' }
+max: { type: duration, gte: [min] }
+").unwrap();
+        let markdown = render(&spec, DocFormat::Markdown).unwrap();
+        assert!(markdown.contains("| <a id=\"max\"></a>`max` | duration | yes | | at least [`min`](#min) | |"), "{}", markdown);
+        assert!(markdown.contains("| the shortest \\| wait |"), "{}", markdown);
+        let html = render(&spec, DocFormat::Html).unwrap();
+        assert!(html.contains("<td>at least <a href=\"#min\"><code>min</code></a></td>"), "{}", html);
+    }
+}
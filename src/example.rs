@@ -0,0 +1,448 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use regex_syntax::{ParserBuilder, hir::{Class, Hir, HirKind, Literal, RepetitionKind, RepetitionRange}};
+use serde_yaml::{Mapping, Number, Value};
+
+use crate::{constraint::Constraint, document_constraint, format::Format, obj::{KeyRelation, ObjConstr, ObjectConstraint}, options::display_value, parse::ParseErr, quantity::{QtyConstr, QuantityConstraint, Unit}, str::{StrConstr, StringConstraint, WrappedRegex}, temporal::{Kind, TemporalConstraint, Timezone}, valstr, value_ref::{ValueRef, lookup}};
+
+lazy_static! {
+    static ref ROOT: Value = valstr!(".");
+}
+
+/// How much of the spec an example fills in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fill {
+    /// only the fields a document can't do without, i.e. required ones
+    /// without a default
+    Minimal,
+    /// every field that isn't deprecated
+    Full,
+}
+
+/// A document generated from a spec, both as a value and as YAML with each
+/// field's `description:` as a comment above it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Example {
+    pub value: Value,
+    pub text: String,
+}
+
+/// References are resolved against the example itself, which takes another
+/// pass for every field that refers to one that refers to another
+const PASSES: usize = 8;
+
+/// Generates an example document from a spec. Fields get their default, the
+/// first allowed value, a string made to match their regex, the value (or
+/// capture group) an `eq` refers to, or failing all of those, a placeholder
+/// that satisfies the field's other constraints. Only the first variant of a
+/// discriminated object is filled in, and assertions aren't taken into
+/// account.
+pub fn example(spec: &Mapping, fill: Fill) -> Result<Example, Vec<ParseErr<'_>>> {
+    let document = document_constraint(spec, &ROOT)?;
+    let obj = match &document {
+        Constraint::Obj(obj) => obj,
+        _ => unreachable!("a document is always an object"),
+    };
+    let mut value = Value::Mapping(Mapping::new());
+    let mut entries = vec![];
+    for _ in 0..PASSES {
+        let generator = Generator { fill, doc: &value, context: &document };
+        entries = generator.fields(obj);
+        let next = to_value(&entries);
+        if next == value {
+            break;
+        }
+        value = next;
+    }
+    let mut text = String::new();
+    match entries.is_empty() {
+        true => text.push_str("{}\n"),
+        false => write(&entries, 0, &mut text),
+    }
+    Ok(Example { value, text })
+}
+
+struct Entry {
+    key: Value,
+    node: Node,
+    description: Option<String>,
+}
+
+enum Node {
+    Value(Value),
+    Fields(Vec<Entry>),
+}
+
+fn to_value(entries: &[Entry]) -> Value {
+    Value::Mapping(entries.iter()
+        .map(|e| match &e.node {
+            Node::Value(v) => (e.key.clone(), v.clone()),
+            Node::Fields(fields) => (e.key.clone(), to_value(fields)),
+        })
+        .collect())
+}
+
+struct Generator<'a> {
+    fill: Fill,
+    /// the example as the previous pass left it
+    doc: &'a Value,
+    context: &'a Constraint<'a>,
+}
+
+impl<'a> Generator<'a> {
+    fn fields(&self, obj: &ObjectConstraint<'a>) -> Vec<Entry> {
+        let sorted = |fields: &HashMap<&'a Value, Constraint<'a>>| {
+            let mut fields: Vec<_> = fields.values().cloned().collect();
+            fields.sort_by_key(|c| display_value(c.field_name()));
+            fields
+        };
+        let (fields, tag) = match &obj.constr {
+            ObjConstr::Fields(fields) => (sorted(fields), None),
+            ObjConstr::Discriminated { key, fields, variants } => {
+                let mut fields = sorted(fields);
+                if let Some((_, variant)) = variants.first() {
+                    fields.extend(sorted(variant));
+                    fields.sort_by_key(|c| display_value(c.field_name()));
+                }
+                (fields, variants.first().map(|(tag, _)| (*key, *tag)))
+            }
+            ObjConstr::Any => (vec![], None),
+        };
+        let fields: Vec<_> = fields.into_iter().map(|c| self.select(c)).collect();
+
+        let mut included: Vec<_> = fields.iter().map(|c| self.wanted(c)).collect();
+        let index = |key: &Value| fields.iter().position(|c| c.field_name() == key);
+        for relation in &obj.relations {
+            match relation {
+                KeyRelation::OneRequired(keys) if !keys.iter().filter_map(|k| index(k)).any(|i| included[i]) => {
+                    if let Some(i) = keys.iter().find_map(|k| index(k)) {
+                        included[i] = true;
+                    }
+                }
+                KeyRelation::Exclusive(keys) => {
+                    let present: Vec<_> = keys.iter().filter_map(|k| index(k)).filter(|i| included[*i]).collect();
+                    present.into_iter().skip(1).for_each(|i| included[i] = false);
+                }
+                _ => {}
+            }
+        }
+        for relation in &obj.relations {
+            if let KeyRelation::DependsOn { key, requires } = relation {
+                if index(key).is_some_and(|i| included[i]) {
+                    requires.iter().filter_map(|k| index(k)).for_each(|i| included[i] = true);
+                }
+            }
+        }
+
+        let mut entries: Vec<_> = fields.iter().zip(included)
+            .filter(|(_, included)| *included)
+            .map(|(c, _)| Entry {
+                key: c.field_name().clone(),
+                node: self.node(c),
+                description: c.options().description.clone(),
+            })
+            .collect();
+        // the discriminator has to name the variant whose fields are filled in
+        if let Some((key, tag)) = tag {
+            match entries.iter_mut().find(|e| e.key == *key) {
+                Some(entry) => entry.node = Node::Value(tag.clone()),
+                None => entries.insert(0, Entry { key: key.clone(), node: Node::Value(tag.clone()), description: None }),
+            }
+        }
+        entries
+    }
+
+    /// The constraint that applies to the example as it stands
+    fn select(&self, constraint: Constraint<'a>) -> Constraint<'a> {
+        match constraint {
+            Constraint::When(c) => self.select(c.select(self.doc, self.context)),
+            other => other,
+        }
+    }
+
+    fn wanted(&self, constraint: &Constraint) -> bool {
+        let options = constraint.options();
+        match self.fill {
+            Fill::Minimal => options.required && constraint.default().is_none(),
+            Fill::Full => options.deprecated.is_none(),
+        }
+    }
+
+    fn node(&self, constraint: &Constraint<'a>) -> Node {
+        match constraint {
+            Constraint::Obj(obj) => Node::Fields(self.fields(obj)),
+            _ => match constraint.default() {
+                Some(default) => Node::Value(default.clone()),
+                None => Node::Value(self.sample(constraint)),
+            },
+        }
+    }
+
+    fn sample(&self, constraint: &Constraint<'a>) -> Value {
+        match constraint {
+            Constraint::Str(c) => Value::String(self.string(c)),
+            Constraint::Qty(c) => self.quantity(c),
+            Constraint::Time(c) => self.temporal(c),
+            _ => valstr!("example"),
+        }
+    }
+
+    fn resolve<T>(&self, value: &ValueRef<'a, T>, literal: fn(&T) -> Value) -> Option<Value> {
+        match value {
+            ValueRef::Literal(v) => Some(literal(v)),
+            ValueRef::AbsolutePath(path) => lookup(path, self.doc, self.context).cloned(),
+        }
+    }
+
+    fn string(&self, c: &StringConstraint<'a>) -> String {
+        let resolve = |v: &ValueRef<'a, String>| match v {
+            // a capture group is picked out of the regex sample once a pass has made one
+            ValueRef::AbsolutePath(path) if path.last().and_then(|p| p.as_str()).is_some_and(|p| p.starts_with('#')) => {
+                v.resolve_str(self.doc, self.context).ok().map(String::from)
+            }
+            _ => self.resolve(v, |s| Value::String(s.clone())).map(|v| display_value(&v)),
+        };
+        let avoiding = |excluded: Vec<String>| ["example", "sample", "placeholder"].iter()
+            .find(|s| !excluded.iter().any(|e| c.normalize.apply(e) == c.normalize.apply(s)))
+            .map_or_else(|| format!("{}-1", excluded.join("-")), |s| s.to_string());
+        let fit = |s: String, min: &Option<ValueRef<'a, Number>>, max: &Option<ValueRef<'a, Number>>| {
+            let bound = |n: &Option<ValueRef<'a, Number>>| n.as_ref()
+                .and_then(|n| self.resolve(n, |n| Value::Number(n.clone())))
+                .and_then(|n| n.as_u64())
+                .map(|n| n as usize);
+            let (min, max) = (bound(min).unwrap_or(0), bound(max).unwrap_or(usize::MAX));
+            let mut s: String = s.chars().take(max).collect();
+            while s.chars().count() < min {
+                s.push('x');
+            }
+            s
+        };
+        let placeholder = String::from("example");
+        let mut s = match &c.constr {
+            StrConstr::Allowed(values) => values.iter()
+                .filter_map(resolve)
+                .find(|v| c.options.replacement(&Value::String(v.clone())).is_none())
+                .unwrap_or(placeholder),
+            StrConstr::Disallowed(values) => avoiding(values.iter().filter_map(resolve).collect()),
+            StrConstr::Regex(regex) => matching(regex).unwrap_or(placeholder),
+            StrConstr::Equals(v) => resolve(v).unwrap_or(placeholder),
+            StrConstr::NotEquals(v) => avoiding(resolve(v).into_iter().collect()),
+            StrConstr::Length { min, max, .. } => fit(placeholder, min, max),
+            StrConstr::StartsWith(v) => format!("{}{}", resolve(v).unwrap_or_default(), placeholder),
+            StrConstr::EndsWith(v) => format!("{}{}", placeholder, resolve(v).unwrap_or_default()),
+            StrConstr::Contains(v) => format!("{}-{}", placeholder, resolve(v).unwrap_or_default()),
+            StrConstr::Format(format) => formatted(format),
+            StrConstr::Any => placeholder,
+        };
+        // the checks on top of that are met one by one, with the length last
+        // so that it has the final say
+        let (lengths, others): (Vec<_>, Vec<_>) = c.checks.iter().partition(|check| matches!(check, StrConstr::Length { .. }));
+        for check in others.into_iter().chain(lengths) {
+            s = match check {
+                StrConstr::Length { min, max, .. } => fit(s, min, max),
+                StrConstr::StartsWith(v) => match resolve(v).unwrap_or_default() {
+                    prefix if s.starts_with(&prefix) => s,
+                    prefix => format!("{}{}", prefix, s),
+                },
+                StrConstr::EndsWith(v) => match resolve(v).unwrap_or_default() {
+                    suffix if s.ends_with(&suffix) => s,
+                    suffix => format!("{}{}", s, suffix),
+                },
+                StrConstr::Contains(v) => match resolve(v).unwrap_or_default() {
+                    part if s.contains(&part) => s,
+                    part => format!("{}-{}", s, part),
+                },
+                StrConstr::Format(format) if format.check(&s).is_err() => formatted(format),
+                _ => s,
+            };
+        }
+        s
+    }
+
+    fn quantity(&self, c: &QuantityConstraint<'a>) -> Value {
+        let resolve = |v: &ValueRef<'a, Value>| self.resolve(v, Value::clone);
+        let amount = |v: &Value| c.unit.amount(v).ok();
+        let avoiding = |excluded: Vec<Value>| {
+            let excluded: Vec<_> = excluded.iter().filter_map(amount).collect();
+            let candidates: &[&str] = match c.unit {
+                Unit::Duration => &["30s", "5m", "1h"],
+                Unit::ByteSize => &["64MiB", "1GiB", "512KiB"],
+            };
+            candidates.iter()
+                .map(|s| valstr!(*s))
+                .find(|v| amount(v).is_some_and(|a| !excluded.contains(&a)))
+                .unwrap_or_else(|| valstr!(candidates[0]))
+        };
+        let base_units = |n: f64| Value::Number(Number::from(n.max(0.0) as u64));
+        let sample = match &c.constr {
+            QtyConstr::Allowed(values) => values.iter().find_map(resolve),
+            QtyConstr::Disallowed(values) => Some(avoiding(values.iter().filter_map(resolve).collect())),
+            QtyConstr::Range { min, max } => min.as_ref().or(max.as_ref()).and_then(resolve),
+            QtyConstr::Equals(v) | QtyConstr::GreaterThanEq(v) | QtyConstr::LessThanEq(v) => resolve(v),
+            QtyConstr::NotEquals(v) => Some(avoiding(resolve(v).into_iter().collect())),
+            QtyConstr::GreaterThan(v) => resolve(v).as_ref().and_then(amount).map(|a| base_units(a.floor() + 1.0)),
+            QtyConstr::LessThan(v) => resolve(v).as_ref().and_then(amount).map(|a| base_units((a / 2.0).floor())),
+            QtyConstr::Any => None,
+        };
+        sample.unwrap_or_else(|| avoiding(vec![]))
+    }
+
+    /// The first of a few candidates that fits the timezone setting and the
+    /// bounds
+    fn temporal(&self, c: &TemporalConstraint<'a>) -> Value {
+        let candidates: &[&str] = match c.kind {
+            Kind::DateTime => &["2024-03-01T12:00:00", "2000-01-01T00:00:00", "2099-12-31T23:59:59"],
+            Kind::Date => &["2024-03-01", "2000-01-01", "2099-12-31"],
+            Kind::Time => &["12:00:00", "00:00:01", "23:59:59"],
+        };
+        let bound = |v: &Option<ValueRef<'a, Value>>| v.as_ref()
+            .and_then(|v| self.resolve(v, Value::clone))
+            .and_then(|v| c.kind.value(&v, Timezone::Any).ok());
+        let (after, before) = (bound(&c.after), bound(&c.before));
+        let suffix = match (c.kind, c.timezone) {
+            (Kind::Date, _) | (_, Timezone::Forbidden) => "",
+            _ => "Z",
+        };
+        candidates.iter()
+            .map(|s| format!("{}{}", s, suffix))
+            .find(|s| match c.kind.parse(s, c.timezone) {
+                Ok(instant) => after.map_or(true, |a| instant > a) && before.map_or(true, |b| instant < b),
+                Err(_) => false,
+            })
+            .map_or_else(|| Value::String(format!("{}{}", candidates[0], suffix)), Value::String)
+    }
+}
+
+fn formatted(format: &Format) -> String {
+    let sample = match format {
+        Format::Email => "user@example.com",
+        Format::Uri { schemes: Some(schemes) } | Format::Url { schemes: Some(schemes) } if !schemes.is_empty() => {
+            return format!("{}://example.com", schemes[0]);
+        }
+        Format::Uri { .. } | Format::Url { .. } => "https://example.com",
+        Format::Hostname => "example.com",
+        Format::Ipv4 => "192.0.2.1",
+        Format::Ipv6 => "2001:db8::1",
+        Format::Cidr => "192.0.2.0/24",
+        Format::Uuid => "123e4567-e89b-12d3-a456-426614174000",
+        Format::Semver => "1.0.0",
+        Format::Base64 => "ZXhhbXBsZQ==",
+    };
+    String::from(sample)
+}
+
+/// A string the regex matches, built by taking the first branch of every
+/// alternation and the fewest repetitions (but at least one) of everything
+fn matching(regex: &WrappedRegex) -> Option<String> {
+    let hir = ParserBuilder::new()
+        .case_insensitive(regex.options.case_insensitive)
+        .multi_line(regex.options.multiline)
+        .dot_matches_new_line(regex.options.dot_all)
+        .build()
+        .parse(&regex.pattern)
+        .ok()?;
+    let mut sample = String::new();
+    generate(&hir, 0, &mut sample)?;
+    Some(sample).filter(|s| regex.is_match(s))
+}
+
+/// `nth` is which repetition of the enclosing `*`, `+` or `{n}` this is
+fn generate(hir: &Hir, nth: usize, out: &mut String) -> Option<()> {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Anchor(_) | HirKind::WordBoundary(_) => {}
+        HirKind::Literal(Literal::Unicode(c)) => out.push(*c),
+        HirKind::Literal(Literal::Byte(b)) => out.push(char::from(*b)),
+        HirKind::Class(class) => out.push(pick(class, nth)?),
+        HirKind::Repetition(repetition) => {
+            let times = match &repetition.kind {
+                RepetitionKind::ZeroOrOne | RepetitionKind::ZeroOrMore | RepetitionKind::OneOrMore => 1,
+                RepetitionKind::Range(RepetitionRange::Exactly(n)) => *n,
+                RepetitionKind::Range(RepetitionRange::AtLeast(n)) => (*n).max(1),
+                RepetitionKind::Range(RepetitionRange::Bounded(min, max)) => (*min).max(1).min(*max),
+            };
+            for i in 0..times as usize {
+                generate(&repetition.hir, i, out)?;
+            }
+        }
+        HirKind::Group(group) => generate(&group.hir, nth, out)?,
+        HirKind::Concat(hirs) => hirs.iter().try_for_each(|h| generate(h, nth, out))?,
+        HirKind::Alternation(hirs) => generate(hirs.first()?, nth, out)?,
+    }
+    Some(())
+}
+
+/// Readable characters are preferred, and vary with the repetition so that
+/// `[a-z]{3}` doesn't come out as `aaa`
+fn pick(class: &Class, nth: usize) -> Option<char> {
+    const PREFERRED: [&str; 3] = ["example", "1234567890", "EXAMPLE"];
+    let contains = |c: char| match class {
+        Class::Unicode(class) => class.iter().any(|r| r.start() <= c && c <= r.end()),
+        Class::Bytes(class) => c.is_ascii() && class.iter().any(|r| r.start() <= c as u8 && c as u8 <= r.end()),
+    };
+    let preferred = PREFERRED.iter().find_map(|set| {
+        let chars: Vec<_> = set.chars().collect();
+        (0..chars.len()).map(|i| chars[(nth + i) % chars.len()]).find(|c| contains(*c))
+    });
+    preferred.or_else(|| match class {
+        Class::Unicode(class) => class.iter().next().map(|r| r.start()),
+        Class::Bytes(class) => class.iter().next().map(|r| char::from(r.start())),
+    })
+}
+
+fn scalar(value: &Value) -> String {
+    serde_yaml::to_string(value)
+        .map(|s| s.trim_start_matches("---").trim().to_string())
+        .unwrap_or_default()
+}
+
+fn write(entries: &[Entry], indent: usize, out: &mut String) {
+    let pad = " ".repeat(indent);
+    for entry in entries {
+        for line in entry.description.iter().flat_map(|d| d.trim_end().lines()) {
+            match line.trim_end() {
+                "" => out.push_str(&format!("{}#\n", pad)),
+                line => out.push_str(&format!("{}# {}\n", pad, line)),
+            }
+        }
+        let key = scalar(&entry.key);
+        match &entry.node {
+            Node::Fields(fields) if !fields.is_empty() => {
+                out.push_str(&format!("{}{}:\n", pad, key));
+                write(fields, indent + 2, out);
+            }
+            Node::Fields(_) => out.push_str(&format!("{}{}: {{}}\n", pad, key)),
+            Node::Value(value @ Value::Mapping(m)) if !m.is_empty() => block(&pad, &key, value, out),
+            Node::Value(value @ Value::Sequence(s)) if !s.is_empty() => block(&pad, &key, value, out),
+            Node::Value(value) => out.push_str(&format!("{}{}: {}\n", pad, key, scalar(value))),
+        }
+    }
+}
+
+fn block(pad: &str, key: &str, value: &Value, out: &mut String) {
+    out.push_str(&format!("{}{}:\n", pad, key));
+    for line in scalar(value).lines() {
+        out.push_str(&format!("{}  {}\n", pad, line));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(pattern: &str) -> Option<String> {
+        let spec: Mapping = serde_yaml::from_str(&format!("name:\n  type: string\n  regex: '{}'\n", pattern)).unwrap();
+        let example = example(&spec, Fill::Minimal).unwrap();
+        example.value.get("name").and_then(Value::as_str).map(String::from)
+    }
+
+    #[test]
+    fn regex_samples_match() {
+        assert_eq!(Some("exa-1"), sample("^[a-z]{3}-\\d+$").as_deref());
+        assert_eq!(Some("v1"), sample("^v\\d+$|^latest$").as_deref());
+        assert_eq!(Some("x.pem"), sample("^x\\.(pem|crt)$").as_deref());
+        // nothing is both a word boundary and not one, but the field still
+        // gets a value
+        assert_eq!(Some("example"), sample("\\b\\B").as_deref());
+    }
+}
@@ -0,0 +1,568 @@
+use std::{borrow::Cow, fmt};
+
+use serde_yaml::{Mapping, Sequence, Value};
+
+use crate::{constraint::Constraint, value_ref};
+
+/// A small expression language for invariants that span several fields, e.g.
+/// `min_replicas <= max_replicas` or `len(name) + len(namespace) < 63`.
+///
+/// Paths like `admin.port` are relative to the object that declares the
+/// assertion, and paths starting with `$.` are relative to the document root.
+/// Keys may contain dashes, so subtraction needs spaces around the `-`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Path { absolute: bool, segments: Vec<String> },
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Or, And,
+    Eq, Neq, Lt, LtEq, Gt, GtEq,
+    Add, Sub, Mul, Div, Rem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Func {
+    Len,
+    Exists,
+    Lower,
+    Upper,
+}
+
+impl Func {
+    fn lookup(name: &str) -> Option<Func> {
+        match name {
+            "len" => Some(Func::Len),
+            "exists" => Some(Func::Exists),
+            "lower" => Some(Func::Lower),
+            "upper" => Some(Func::Upper),
+            _ => None,
+        }
+    }
+}
+
+/// Syntax errors, with the byte offset into the expression where they occur
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprErr {
+    UnexpectedChar(usize, char),
+    UnterminatedString(usize),
+    UnexpectedToken(usize),
+    UnexpectedEnd,
+    UnknownFunction(usize, String),
+    Arity { func: Func, expected: usize, found: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalErr {
+    /// an operator or function was applied to values it doesn't work on
+    Type { op: String, found: Vec<&'static str> },
+    DivideByZero,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Str(String),
+    Ident(String),
+    Dollar,
+    Dot,
+    Comma,
+    LParen,
+    RParen,
+    Op(&'static str),
+}
+
+impl fmt::Display for ExprErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExprErr::UnexpectedChar(pos, c) => write!(f, "unexpected {:?} at {}", c, pos),
+            ExprErr::UnterminatedString(pos) => write!(f, "the string at {} is never closed", pos),
+            ExprErr::UnexpectedToken(pos) => write!(f, "unexpected token at {}", pos),
+            ExprErr::UnexpectedEnd => write!(f, "the expression ends too early"),
+            ExprErr::UnknownFunction(pos, name) => write!(f, "unknown function {} at {}", name, pos),
+            ExprErr::Arity { func, expected, found } => {
+                write!(f, "{} takes {} argument(s), not {}", format!("{:?}", func).to_lowercase(), expected, found)
+            }
+        }
+    }
+}
+
+impl fmt::Display for EvalErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalErr::Type { op, found } => write!(f, "{} doesn't work on {}", op, found.join(" and ")),
+            EvalErr::DivideByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+fn tokenize(src: &str) -> Result<Vec<(usize, Token)>, ExprErr> {
+    const OPS: [&str; 15] = ["==", "!=", "<=", ">=", "&&", "||", "<", ">", "+", "-", "*", "/", "%", "!", "="];
+    let mut tokens = vec![];
+    let mut chars = src.char_indices().peekable();
+    while let Some(&(pos, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            let mut end = pos;
+            while let Some(&(i, d)) = chars.peek() {
+                if !(d.is_ascii_digit() || d == '.' || d == '_') {
+                    break;
+                }
+                end = i + d.len_utf8();
+                chars.next();
+            }
+            let text = src[pos..end].replace('_', "");
+            let num = text.parse().map_err(|_| ExprErr::UnexpectedChar(pos, c))?;
+            tokens.push((pos, Token::Num(num)));
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped)) => s.push(escaped),
+                        None => return Err(ExprErr::UnterminatedString(pos)),
+                    },
+                    Some((_, q)) if q == c => break,
+                    Some((_, other)) => s.push(other),
+                    None => return Err(ExprErr::UnterminatedString(pos)),
+                }
+            }
+            tokens.push((pos, Token::Str(s)));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = pos;
+            while let Some(&(i, d)) = chars.peek() {
+                if !(d.is_alphanumeric() || d == '_' || d == '-') {
+                    break;
+                }
+                end = i + d.len_utf8();
+                chars.next();
+            }
+            tokens.push((pos, Token::Ident(src[pos..end].to_string())));
+        } else {
+            let single = match c {
+                '$' => Some(Token::Dollar),
+                '.' => Some(Token::Dot),
+                ',' => Some(Token::Comma),
+                '(' => Some(Token::LParen),
+                ')' => Some(Token::RParen),
+                _ => None,
+            };
+            if let Some(token) = single {
+                chars.next();
+                tokens.push((pos, token));
+                continue;
+            }
+            let op = OPS.iter()
+                .find(|op| src[pos..].starts_with(*op))
+                .ok_or(ExprErr::UnexpectedChar(pos, c))?;
+            // a lone `=` is almost certainly a typo for `==`
+            if *op == "=" {
+                return Err(ExprErr::UnexpectedChar(pos, c));
+            }
+            for _ in 0..op.len() {
+                chars.next();
+            }
+            tokens.push((pos, Token::Op(op)));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn next(&mut self) -> Result<(usize, Token), ExprErr> {
+        let token = self.tokens.get(self.pos).cloned().ok_or(ExprErr::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ExprErr> {
+        match self.next()? {
+            (_, token) if token == expected => Ok(()),
+            (pos, _) => Err(ExprErr::UnexpectedToken(pos)),
+        }
+    }
+
+    /// Matches an operator (or its keyword spelling) and consumes it
+    fn op(&mut self, ops: &[(&str, BinOp)]) -> Option<BinOp> {
+        let op = match self.peek() {
+            Some(Token::Op(op)) => ops.iter().find(|(s, _)| s == op),
+            Some(Token::Ident(kw)) => ops.iter().find(|(s, _)| s == kw),
+            _ => None,
+        };
+        let op = op.map(|(_, op)| *op);
+        if op.is_some() {
+            self.pos += 1;
+        }
+        op
+    }
+
+    fn binary(&mut self, ops: &[(&str, BinOp)], operand: fn(&mut Parser) -> Result<Expr, ExprErr>) -> Result<Expr, ExprErr> {
+        let mut lhs = operand(self)?;
+        while let Some(op) = self.op(ops) {
+            let rhs = operand(self)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<Expr, ExprErr> {
+        self.binary(&[("||", BinOp::Or), ("or", BinOp::Or)], Parser::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, ExprErr> {
+        self.binary(&[("&&", BinOp::And), ("and", BinOp::And)], Parser::not)
+    }
+
+    fn not(&mut self) -> Result<Expr, ExprErr> {
+        match self.peek() {
+            Some(Token::Op("!")) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.not()?)))
+            }
+            Some(Token::Ident(kw)) if kw == "not" => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.not()?)))
+            }
+            _ => self.comparison(),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ExprErr> {
+        let lhs = self.sum()?;
+        let ops = [
+            ("==", BinOp::Eq), ("!=", BinOp::Neq),
+            ("<=", BinOp::LtEq), (">=", BinOp::GtEq),
+            ("<", BinOp::Lt), (">", BinOp::Gt),
+        ];
+        match self.op(&ops) {
+            Some(op) => Ok(Expr::Binary(op, Box::new(lhs), Box::new(self.sum()?))),
+            None => Ok(lhs),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, ExprErr> {
+        self.binary(&[("+", BinOp::Add), ("-", BinOp::Sub)], Parser::term)
+    }
+
+    fn term(&mut self) -> Result<Expr, ExprErr> {
+        self.binary(&[("*", BinOp::Mul), ("/", BinOp::Div), ("%", BinOp::Rem)], Parser::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ExprErr> {
+        if let Some(Token::Op("-")) = self.peek() {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, ExprErr> {
+        match self.next()? {
+            (_, Token::Num(n)) => Ok(Expr::Num(n)),
+            (_, Token::Str(s)) => Ok(Expr::Str(s)),
+            (_, Token::LParen) => {
+                let inner = self.or()?;
+                self.expect(Token::RParen)?;
+                Ok(inner)
+            }
+            (_, Token::Dollar) => {
+                self.expect(Token::Dot)?;
+                Ok(Expr::Path { absolute: true, segments: self.segments()? })
+            }
+            (pos, Token::Ident(ident)) => match ident.as_str() {
+                "true" => Ok(Expr::Bool(true)),
+                "false" => Ok(Expr::Bool(false)),
+                "null" => Ok(Expr::Null),
+                _ if self.peek() == Some(&Token::LParen) => self.call(pos, ident),
+                _ => {
+                    self.pos -= 1;
+                    Ok(Expr::Path { absolute: false, segments: self.segments()? })
+                }
+            },
+            (pos, _) => Err(ExprErr::UnexpectedToken(pos)),
+        }
+    }
+
+    fn segments(&mut self) -> Result<Vec<String>, ExprErr> {
+        let mut segments = vec![];
+        loop {
+            match self.next()? {
+                (_, Token::Ident(s)) => segments.push(s),
+                (pos, _) => return Err(ExprErr::UnexpectedToken(pos)),
+            }
+            if self.peek() != Some(&Token::Dot) {
+                return Ok(segments);
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn call(&mut self, pos: usize, name: String) -> Result<Expr, ExprErr> {
+        let func = Func::lookup(&name).ok_or(ExprErr::UnknownFunction(pos, name))?;
+        self.expect(Token::LParen)?;
+        let mut args = vec![];
+        if self.peek() != Some(&Token::RParen) {
+            args.push(self.or()?);
+            while self.peek() == Some(&Token::Comma) {
+                self.pos += 1;
+                args.push(self.or()?);
+            }
+        }
+        self.expect(Token::RParen)?;
+        if args.len() != 1 {
+            return Err(ExprErr::Arity { func, expected: 1, found: args.len() });
+        }
+        if func == Func::Exists && !matches!(args[0], Expr::Path { .. }) {
+            return Err(ExprErr::UnexpectedToken(pos));
+        }
+        Ok(Expr::Call(func, args))
+    }
+}
+
+pub fn parse(src: &str) -> Result<Expr, ExprErr> {
+    let mut parser = Parser { tokens: tokenize(src)?, pos: 0 };
+    let expr = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        Some((pos, _)) => Err(ExprErr::UnexpectedToken(*pos)),
+        None => Ok(expr),
+    }
+}
+
+/// The values an expression works with. Values that come from the document are
+/// borrowed, and anything computed along the way is owned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Val<'a> {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(Cow<'a, str>),
+    Seq(&'a Sequence),
+    Map(&'a Mapping),
+}
+
+impl<'a> From<&'a Value> for Val<'a> {
+    fn from(value: &'a Value) -> Self {
+        match value {
+            Value::Null => Val::Null,
+            Value::Bool(b) => Val::Bool(*b),
+            Value::Number(n) => n.as_f64().map(Val::Num).unwrap_or(Val::Null),
+            Value::String(s) => Val::Str(Cow::Borrowed(s)),
+            Value::Sequence(seq) => Val::Seq(seq),
+            Value::Mapping(m) => Val::Map(m),
+        }
+    }
+}
+
+impl Val<'_> {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Val::Null => "null",
+            Val::Bool(_) => "bool",
+            Val::Num(_) => "number",
+            Val::Str(_) => "string",
+            Val::Seq(_) => "sequence",
+            Val::Map(_) => "mapping",
+        }
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            BinOp::Or => "||", BinOp::And => "&&",
+            BinOp::Eq => "==", BinOp::Neq => "!=",
+            BinOp::Lt => "<", BinOp::LtEq => "<=", BinOp::Gt => ">", BinOp::GtEq => ">=",
+            BinOp::Add => "+", BinOp::Sub => "-", BinOp::Mul => "*", BinOp::Div => "/", BinOp::Rem => "%",
+        };
+        f.write_str(op)
+    }
+}
+
+fn mismatch<T>(op: impl ToString, found: &[&Val]) -> Result<T, EvalErr> {
+    Err(EvalErr::Type { op: op.to_string(), found: found.iter().map(|v| v.type_name()).collect() })
+}
+
+impl Expr {
+    /// Evaluates the expression, with `lookup` resolving paths to values. A
+    /// path that can't be resolved is `null`.
+    pub fn eval<'a>(&self, lookup: &dyn Fn(bool, &[String]) -> Option<&'a Value>) -> Result<Val<'a>, EvalErr> {
+        match self {
+            Expr::Null => Ok(Val::Null),
+            Expr::Bool(b) => Ok(Val::Bool(*b)),
+            Expr::Num(n) => Ok(Val::Num(*n)),
+            Expr::Str(s) => Ok(Val::Str(Cow::Owned(s.clone()))),
+            Expr::Path { absolute, segments } => Ok(lookup(*absolute, segments).map(Val::from).unwrap_or(Val::Null)),
+            Expr::Not(inner) => match inner.eval(lookup)? {
+                Val::Bool(b) => Ok(Val::Bool(!b)),
+                other => mismatch("!", &[&other]),
+            },
+            Expr::Neg(inner) => match inner.eval(lookup)? {
+                Val::Num(n) => Ok(Val::Num(-n)),
+                other => mismatch("-", &[&other]),
+            },
+            Expr::Binary(op @ (BinOp::And | BinOp::Or), lhs, rhs) => {
+                // short circuit, so that `exists(a) && a > 1` works
+                match (op, lhs.eval(lookup)?) {
+                    (BinOp::And, Val::Bool(false)) => Ok(Val::Bool(false)),
+                    (BinOp::Or, Val::Bool(true)) => Ok(Val::Bool(true)),
+                    (_, Val::Bool(_)) => match rhs.eval(lookup)? {
+                        Val::Bool(b) => Ok(Val::Bool(b)),
+                        other => mismatch(op, &[&Val::Bool(false), &other]),
+                    },
+                    (_, other) => mismatch(op, &[&other]),
+                }
+            }
+            Expr::Binary(op, lhs, rhs) => binary(*op, lhs.eval(lookup)?, rhs.eval(lookup)?),
+            Expr::Call(func, args) => call(*func, &args[0], lookup),
+        }
+    }
+}
+
+fn binary<'a>(op: BinOp, lhs: Val<'a>, rhs: Val<'a>) -> Result<Val<'a>, EvalErr> {
+    use std::cmp::Ordering;
+    let ordering = match (&lhs, &rhs) {
+        (Val::Num(a), Val::Num(b)) => a.partial_cmp(b),
+        (Val::Str(a), Val::Str(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+    match op {
+        BinOp::Eq => Ok(Val::Bool(lhs == rhs)),
+        BinOp::Neq => Ok(Val::Bool(lhs != rhs)),
+        BinOp::Lt | BinOp::LtEq | BinOp::Gt | BinOp::GtEq => match ordering {
+            Some(ord) => Ok(Val::Bool(match op {
+                BinOp::Lt => ord == Ordering::Less,
+                BinOp::LtEq => ord != Ordering::Greater,
+                BinOp::Gt => ord == Ordering::Greater,
+                _ => ord != Ordering::Less,
+            })),
+            None => mismatch(op, &[&lhs, &rhs]),
+        },
+        _ => match (lhs, rhs) {
+            (Val::Str(a), Val::Str(b)) if op == BinOp::Add => Ok(Val::Str(Cow::Owned(format!("{}{}", a, b)))),
+            (Val::Num(_), Val::Num(b)) if b == 0.0 && matches!(op, BinOp::Div | BinOp::Rem) => Err(EvalErr::DivideByZero),
+            (Val::Num(a), Val::Num(b)) => Ok(Val::Num(match op {
+                BinOp::Add => a + b,
+                BinOp::Sub => a - b,
+                BinOp::Mul => a * b,
+                BinOp::Div => a / b,
+                _ => a % b,
+            })),
+            (lhs, rhs) => mismatch(op, &[&lhs, &rhs]),
+        },
+    }
+}
+
+fn call<'a>(func: Func, arg: &Expr, lookup: &dyn Fn(bool, &[String]) -> Option<&'a Value>) -> Result<Val<'a>, EvalErr> {
+    let name = format!("{:?}", func).to_lowercase();
+    if let (Func::Exists, Expr::Path { absolute, segments }) = (func, arg) {
+        return Ok(Val::Bool(lookup(*absolute, segments).is_some()));
+    }
+    match (func, arg.eval(lookup)?) {
+        (Func::Len, Val::Str(s)) => Ok(Val::Num(s.chars().count() as f64)),
+        (Func::Len, Val::Seq(seq)) => Ok(Val::Num(seq.len() as f64)),
+        (Func::Len, Val::Map(m)) => Ok(Val::Num(m.len() as f64)),
+        (Func::Lower, Val::Str(s)) => Ok(Val::Str(Cow::Owned(s.to_lowercase()))),
+        (Func::Upper, Val::Str(s)) => Ok(Val::Str(Cow::Owned(s.to_uppercase()))),
+        (_, other) => mismatch(name, &[&other]),
+    }
+}
+
+/// An assertion as it was written in the spec, along with the path of the
+/// object that declared it (which relative paths start from)
+#[derive(Debug, Clone)]
+pub struct Assertion<'a> {
+    pub source: &'a str,
+    pub expr: Expr,
+    pub base: Vec<&'a Value>,
+}
+
+// the expression is derived entirely from the source, which is much easier to
+// compare than a tree full of floats
+impl PartialEq for Assertion<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source && self.base == other.base
+    }
+}
+
+impl Eq for Assertion<'_> {}
+
+impl<'a> Assertion<'a> {
+    pub fn new(source: &'a str, base: &[&'a Value]) -> Result<Assertion<'a>, ExprErr> {
+        Ok(Assertion { source, expr: parse(source)?, base: base.to_vec() })
+    }
+
+    pub fn check(&self, root: &'a Value, context: &Constraint<'a>) -> Result<bool, EvalErr> {
+        let lookup = |absolute: bool, segments: &[String]| {
+            let keys: Vec<_> = segments.iter().map(|s| Value::String(s.clone())).collect();
+            let base = if absolute { &[][..] } else { &self.base[..] };
+            let path: Vec<&Value> = base.iter().copied().chain(keys.iter()).collect();
+            value_ref::lookup(&path, root, context)
+        };
+        match self.expr.eval(&lookup)? {
+            Val::Bool(b) => Ok(b),
+            other => mismatch("assert", &[&other]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval<'a>(src: &str, doc: &'a Value) -> Result<Val<'a>, EvalErr> {
+        let lookup = |_: bool, path: &[String]| path.iter()
+            .try_fold(doc, |curr, key| curr.get(key.as_str()));
+        parse(src).unwrap().eval(&lookup)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(parse("1 + 2 * 3 == 7 && !false"), Ok(Expr::Binary(BinOp::And,
+            Box::new(Expr::Binary(BinOp::Eq,
+                Box::new(Expr::Binary(BinOp::Add,
+                    Box::new(Expr::Num(1.0)),
+                    Box::new(Expr::Binary(BinOp::Mul, Box::new(Expr::Num(2.0)), Box::new(Expr::Num(3.0)))))),
+                Box::new(Expr::Num(7.0)))),
+            Box::new(Expr::Not(Box::new(Expr::Bool(false)))))));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(parse("a = b"), Err(ExprErr::UnexpectedChar(2, '=')));
+        assert_eq!(parse("len(a, b)"), Err(ExprErr::Arity { func: Func::Len, expected: 1, found: 2 }));
+        assert_eq!(parse("size(a)"), Err(ExprErr::UnknownFunction(0, String::from("size"))));
+        assert_eq!(parse("(a < b"), Err(ExprErr::UnexpectedEnd));
+        assert_eq!(parse("a < b c"), Err(ExprErr::UnexpectedToken(6)));
+    }
+
+    #[test]
+    fn evaluate_against_document() {
+        let raw = "{ name: web, namespace: prod, port: 80, admin: { port: 80 }, min: 2, max: 5 }";
+        let doc = &serde_yaml::from_str(raw).unwrap();
+        assert_eq!(eval("min <= max", doc), Ok(Val::Bool(true)));
+        assert_eq!(eval("len(name) + len(namespace) < 63", doc), Ok(Val::Bool(true)));
+        assert_eq!(eval("port != admin.port", doc), Ok(Val::Bool(false)));
+        assert_eq!(eval("exists(missing) && missing > 1", doc), Ok(Val::Bool(false)));
+        assert_eq!(eval("name < 3", doc), Err(EvalErr::Type { op: String::from("<"), found: vec!["string", "number"] }));
+    }
+}
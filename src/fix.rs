@@ -0,0 +1,363 @@
+use std::fmt;
+
+use serde_yaml::{Mapping, Value};
+
+use crate::{constraint::Constraint, document_constraint, obj::{ObjConstr, ObjectConstraint}, options::display_value, parse::ParseErr, str::{StrConstr, StringConstraint}, value_ref::ValueRef};
+
+/// An edit the spec says how to make
+#[derive(Debug, Clone, PartialEq)]
+pub enum FixKind {
+    /// an old key from `renamed_from` gets its current name
+    RenameKey { from: Value, to: Value },
+    /// a value from `deprecated_values`, or one that only differs from an
+    /// `allowed` value by case
+    ReplaceValue { from: Value, to: Value },
+    /// a missing field is written out with its default
+    InsertDefault(Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    /// relative to the document, and for renames it ends in the old key
+    pub path: Vec<Value>,
+    pub kind: FixKind,
+}
+
+impl fmt::Display for Fix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path: Vec<_> = self.path.iter().map(display_value).collect();
+        match &self.kind {
+            FixKind::RenameKey { to, .. } => write!(f, "{}: renamed to {}", path.join("."), display_value(to)),
+            FixKind::ReplaceValue { from, to } => {
+                write!(f, "{}: replaced {} with {}", path.join("."), display_value(from), display_value(to))
+            }
+            FixKind::InsertDefault(value) => write!(f, "{}: added with default {}", path.join("."), display_value(value)),
+        }
+    }
+}
+
+/// The repaired text, along with what was done to it. Fixes that were found
+/// but couldn't be made to the text (e.g. inside flow-style collections) are
+/// left for the author.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fixed {
+    pub text: String,
+    pub applied: Vec<Fix>,
+    pub skipped: Vec<Fix>,
+}
+
+#[derive(Debug)]
+pub enum FixErr<'a> {
+    GrammarParseErr(Vec<ParseErr<'a>>),
+    Input(serde_yaml::Error),
+}
+
+/// Rewrites `input` to repair the violations that `spec` declares a fix for.
+/// The text is edited line by line instead of being re-serialized, so
+/// comments, key order and quoting are kept.
+pub fn fix<'a>(spec: &'a Mapping, input: &str, name: &'a Value) -> Result<Fixed, FixErr<'a>> {
+    let constraint = document_constraint(spec, name).map_err(FixErr::GrammarParseErr)?;
+    let document: Value = serde_yaml::from_str(input).map_err(FixErr::Input)?;
+    let mut fixes = vec![];
+    if let (Constraint::Obj(root), Value::Mapping(mapping)) = (&constraint, &document) {
+        find_fixes(root, mapping, &document, &constraint, &[], &mut fixes);
+    }
+
+    let newline = match input.contains("\r\n") {
+        true => "\r\n",
+        false => "\n",
+    };
+    let mut lines: Vec<String> = input.lines().map(String::from).collect();
+    let (applied, skipped) = fixes.into_iter().partition(|fix| apply(&mut lines, fix).is_some());
+    let mut text = lines.join(newline);
+    if input.ends_with('\n') {
+        text.push_str(newline);
+    }
+    Ok(Fixed { text, applied, skipped })
+}
+
+fn find_fixes<'l>(constraint: &ObjectConstraint<'l>, input: &'l Mapping, root: &'l Value, context: &Constraint<'l>, path: &[Value], fixes: &mut Vec<Fix>) {
+    let mut fields: Vec<_> = match &constraint.constr {
+        ObjConstr::Fields(fields) => fields.iter().collect(),
+        ObjConstr::Discriminated { key, fields, variants } => {
+            let selected = input.get(key).and_then(|tag| variants.iter().find(|(t, _)| *t == tag));
+            fields.iter().chain(selected.into_iter().flat_map(|(_, v)| v.iter())).collect()
+        }
+        ObjConstr::Any => vec![],
+    };
+    // fields are kept in a HashMap, so sort them to make the edits predictable
+    fields.sort_by_key(|(key, _)| display_value(key));
+
+    for (key, field) in fields {
+        let field = match field {
+            Constraint::When(cc) => cc.clone().select(root, context),
+            other => other.clone(),
+        };
+        let mut field_path = path.to_vec();
+        field_path.push((*key).clone());
+        // renaming is only safe while the new key isn't there yet
+        let renamed = match input.contains_key(key) {
+            true => None,
+            false => input.iter().find(|(k, _)| field.options().renamed_from.contains(k)),
+        };
+        if let Some((old, _)) = renamed {
+            let mut old_path = path.to_vec();
+            old_path.push(old.clone());
+            fixes.push(Fix { path: old_path, kind: FixKind::RenameKey { from: old.clone(), to: (*key).clone() } });
+        }
+        match (input.get(key).or_else(|| renamed.map(|(_, v)| v)), field.default()) {
+            (Some(value), _) => value_fixes(&field, value, root, context, &field_path, fixes),
+            (None, Some(default)) => fixes.push(Fix { path: field_path, kind: FixKind::InsertDefault(default.clone()) }),
+            (None, None) => {}
+        }
+    }
+}
+
+fn value_fixes<'l>(field: &Constraint<'l>, value: &'l Value, root: &'l Value, context: &Constraint<'l>, path: &[Value], fixes: &mut Vec<Fix>) {
+    if let Some(to) = field.options().replacement(value) {
+        fixes.push(Fix { path: path.to_vec(), kind: FixKind::ReplaceValue { from: value.clone(), to: to.clone() } });
+        return;
+    }
+    match (field, value) {
+        (Constraint::Obj(o), Value::Mapping(m)) => find_fixes(o, m, root, context, path, fixes),
+        (Constraint::Str(s), Value::String(found)) => {
+            if let Some(to) = allowed_spelling(s, found) {
+                fixes.push(Fix { path: path.to_vec(), kind: FixKind::ReplaceValue { from: value.clone(), to } });
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The one `allowed` literal that `found` matches if case is ignored
+fn allowed_spelling(constraint: &StringConstraint, found: &str) -> Option<Value> {
+    let allowed = match &constraint.constr {
+        // case-insensitive fields already accept any spelling
+        StrConstr::Allowed(_) if constraint.normalize.case_insensitive => return None,
+        StrConstr::Allowed(allowed) => allowed,
+        _ => return None,
+    };
+    let literals: Vec<_> = allowed.iter().filter_map(|v| match v {
+        ValueRef::Literal(s) => Some(*s),
+        ValueRef::AbsolutePath(_) => None,
+    }).collect();
+    if literals.iter().any(|s| *s == found) {
+        return None;
+    }
+    let found = found.to_lowercase();
+    let mut matches = literals.into_iter().filter(|s| s.to_lowercase() == found);
+    match (matches.next(), matches.next()) {
+        (Some(s), None) => Some(Value::String(s.clone())),
+        _ => None,
+    }
+}
+
+/// A `key: ...` line of a block mapping
+struct Entry {
+    indent: usize,
+    /// where the `:` after the key is
+    colon: usize,
+    key: Value,
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_content(line: &str) -> bool {
+    let trimmed = line.trim();
+    !trimmed.is_empty() && !trimmed.starts_with('#')
+}
+
+fn entry(line: &str) -> Option<Entry> {
+    let indent = indent(line);
+    let content = &line[indent..];
+    let key_len = match content.chars().next()? {
+        q @ ('"' | '\'') => content[1..].find(q)? + 2,
+        '#' | '-' | '[' | '{' => return None,
+        _ => content.find(": ").or_else(|| content.strip_suffix(':').map(str::len))?,
+    };
+    if !content[key_len..].starts_with(':') {
+        return None;
+    }
+    let key = serde_yaml::from_str(&content[..key_len]).ok()?;
+    Some(Entry { indent, colon: indent + key_len, key })
+}
+
+/// The `...` marker that ends a document, which nothing can be added after
+fn is_document_end(line: &str) -> bool {
+    line.trim_end() == "..." || line.starts_with("... #")
+}
+
+/// Where the lines nested under line `i` stop
+fn block_end(lines: &[String], i: usize) -> usize {
+    let parent = indent(&lines[i]);
+    (i + 1..lines.len())
+        .find(|&j| is_content(&lines[j]) && indent(&lines[j]) <= parent)
+        .unwrap_or(lines.len())
+}
+
+/// The line that holds the entry at `path`, if it's written in block style
+fn locate(lines: &[String], path: &[Value]) -> Option<usize> {
+    let (mut start, mut end, mut found) = (0, lines.len(), None);
+    for key in path {
+        let child_indent = lines[start..end].iter().find(|l| is_content(l)).map(|l| indent(l))?;
+        let i = (start..end).find(|&i| matches!(entry(&lines[i]), Some(e) if e.indent == child_indent && &e.key == key))?;
+        found = Some(i);
+        start = i + 1;
+        end = block_end(lines, i);
+    }
+    found
+}
+
+/// The byte range of the scalar written after an entry's `:`, without any
+/// trailing comment
+fn value_span(line: &str, colon: usize) -> Option<(usize, usize)> {
+    let rest = &line[colon + 1..];
+    let start = colon + 1 + (rest.len() - rest.trim_start().len());
+    let value = &line[start..];
+    let len = match value.chars().next()? {
+        '"' => value[1..].char_indices()
+            .find(|&(i, c)| c == '"' && !value[1..=i].ends_with('\\'))
+            .map(|(i, _)| i + 2)?,
+        '\'' => value[1..].find('\'')? + 2,
+        '#' | '|' | '>' | '[' | '{' | '&' | '*' | '!' => return None,
+        _ => value.find(" #").unwrap_or(value.len()),
+    };
+    Some((start, start + value[..len].trim_end().len()))
+}
+
+/// How a scalar is written, keeping the quoting style of the text it replaces
+fn render(value: &Value, quote: Option<char>) -> Option<String> {
+    let s = match value {
+        Value::String(s) => s,
+        Value::Mapping(_) | Value::Sequence(_) => return None,
+        other => return Some(display_value(other)),
+    };
+    let plain = !s.contains('\n') && serde_yaml::from_str::<Value>(s).ok().as_ref() == Some(value);
+    Some(match quote {
+        Some('\'') => format!("'{}'", s.replace('\'', "''")),
+        None if plain => s.clone(),
+        _ => format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")),
+    })
+}
+
+/// Makes the fix to the text, or returns `None` if the text isn't laid out in
+/// a way that can be edited safely
+fn apply(lines: &mut Vec<String>, fix: &Fix) -> Option<()> {
+    match &fix.kind {
+        FixKind::RenameKey { to, .. } => {
+            let i = locate(lines, &fix.path)?;
+            let e = entry(&lines[i])?;
+            let key = render(to, None)?;
+            lines[i].replace_range(e.indent..e.colon, &key);
+        }
+        FixKind::ReplaceValue { from, to } => {
+            let i = locate(lines, &fix.path)?;
+            let e = entry(&lines[i])?;
+            let (start, end) = value_span(&lines[i], e.colon)?;
+            let written = &lines[i][start..end];
+            if serde_yaml::from_str::<Value>(written).ok().as_ref() != Some(from) {
+                return None;
+            }
+            let value = render(to, written.chars().next().filter(|c| matches!(c, '"' | '\'')))?;
+            lines[i].replace_range(start..end, &value);
+        }
+        FixKind::InsertDefault(value) => {
+            let (key, parent) = fix.path.split_last()?;
+            let (start, end, fallback_indent) = match parent.is_empty() {
+                true => (0, lines.iter().position(|l| is_document_end(l)).unwrap_or(lines.len()), 0),
+                false => {
+                    let i = locate(lines, parent)?;
+                    let e = entry(&lines[i])?;
+                    // the parent has to be a block mapping for a line to be added to it
+                    if value_span(&lines[i], e.colon).is_some() || lines[i][e.colon + 1..].trim_start().starts_with(['{', '[']) {
+                        return None;
+                    }
+                    (i, block_end(lines, i), e.indent + 2)
+                }
+            };
+            let children = lines[start..end].iter().skip(if parent.is_empty() { 0 } else { 1 });
+            let child_indent = children.clone().find(|l| is_content(l)).map(|l| indent(l)).unwrap_or(fallback_indent);
+            let at = (start..end).rev().find(|&j| is_content(&lines[j])).map(|j| j + 1).unwrap_or(start);
+            let pad = " ".repeat(child_indent);
+            let key = render(key, None)?;
+            let new_lines = match render(value, None) {
+                Some(scalar) => vec![format!("{}{}: {}", pad, key, scalar)],
+                None => {
+                    let nested = serde_yaml::to_string(value).ok()?;
+                    let nested = nested.trim_start_matches("---").trim_start_matches('\n');
+                    std::iter::once(format!("{}{}:", pad, key))
+                        .chain(nested.lines().map(|l| format!("{}  {}", pad, l)))
+                        .collect()
+                }
+            };
+            lines.splice(at..at, new_lines);
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::valstr;
+
+    fn lines(raw: &str) -> Vec<String> {
+        raw.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn locate_nested_entries() {
+        let lines = lines(concat!(
+            "# leading comment\n",
+            "parent:\n",
+            "  child: 1\n",
+            "\n",
+            "  nested:\n",
+            "    \"quoted key\": yes # trailing\n",
+            "other: 2\n",
+        ));
+        assert_eq!(Some(1), locate(&lines, &[valstr!("parent")]));
+        assert_eq!(Some(5), locate(&lines, &[valstr!("parent"), valstr!("nested"), valstr!("quoted key")]));
+        assert_eq!(Some(6), locate(&lines, &[valstr!("other")]));
+        assert_eq!(None, locate(&lines, &[valstr!("child")]));
+        assert_eq!(Some((18, 21)), value_span(&lines[5], 16));
+    }
+
+    #[test]
+    fn edits_keep_comments_and_quotes() {
+        let mut text = lines(concat!(
+            "app:\n",
+            "  mode: 'Fast' # was picked by hand\n",
+            "  old_name: x\n",
+            "# trailing comment\n",
+        ));
+        let app = valstr!("app");
+        let replace = Fix { path: vec![app.clone(), valstr!("mode")], kind: FixKind::ReplaceValue { from: valstr!("Fast"), to: valstr!("fast") } };
+        let rename = Fix { path: vec![app.clone(), valstr!("old_name")], kind: FixKind::RenameKey { from: valstr!("old_name"), to: valstr!("name") } };
+        let insert = Fix { path: vec![app.clone(), valstr!("port")], kind: FixKind::InsertDefault(valstr!("8080")) };
+        assert!(apply(&mut text, &replace).is_some());
+        assert!(apply(&mut text, &rename).is_some());
+        assert!(apply(&mut text, &insert).is_some());
+        assert_eq!(lines(concat!(
+            "app:\n",
+            "  mode: 'fast' # was picked by hand\n",
+            "  name: x\n",
+            "  port: \"8080\"\n",
+            "# trailing comment\n",
+        )), text);
+        // flow style is left alone
+        let mut flow = lines("app: {mode: Fast}\n");
+        assert!(apply(&mut flow, &replace).is_none());
+        assert!(apply(&mut flow, &insert).is_none());
+    }
+
+    #[test]
+    fn top_level_insert_stays_inside_the_document() {
+        let mut text = lines("---\nmode: Fast\n...\n");
+        let insert = Fix { path: vec![valstr!("port")], kind: FixKind::InsertDefault(valstr!("80")) };
+        assert!(apply(&mut text, &insert).is_some());
+        assert_eq!(lines("---\nmode: Fast\nport: \"80\"\n...\n"), text);
+    }
+}
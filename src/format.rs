@@ -0,0 +1,397 @@
+use std::{fmt, net::Ipv6Addr};
+
+/// Well-known string formats that would otherwise need an unreadable regex.
+/// Each check explains exactly which part of the value is wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Format<'a> {
+    Email,
+    /// any `scheme:rest` URI
+    Uri { schemes: Option<Vec<&'a String>> },
+    /// a URI that also has to have a `//host` authority
+    Url { schemes: Option<Vec<&'a String>> },
+    Hostname,
+    Ipv4,
+    Ipv6,
+    Cidr,
+    Uuid,
+    Semver,
+    Base64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatErr {
+    Empty,
+    MissingAt,
+    LocalPartTooLong(usize),
+    InvalidLocalPart(char),
+    InvalidDomain(Box<FormatErr>),
+    MissingScheme,
+    InvalidScheme(String),
+    SchemeNotAllowed { scheme: String, allowed: Vec<String> },
+    MissingHost,
+    InvalidUriChar(char),
+    TooLong(usize),
+    EmptyLabel,
+    LabelTooLong(String),
+    InvalidLabelChar { label: String, c: char },
+    LabelHyphen(String),
+    OctetCount(usize),
+    InvalidOctet(String),
+    MultipleCompressions,
+    GroupCount(usize),
+    InvalidGroup(String),
+    InvalidIpv6,
+    MissingPrefixLength,
+    InvalidAddress(Box<FormatErr>),
+    PrefixLength { prefix: String, max: u8 },
+    UuidLength(usize),
+    InvalidUuidChar { position: usize, c: char },
+    SemverParts(usize),
+    InvalidVersionNumber(String),
+    InvalidIdentifier(String),
+    Base64Length(usize),
+    InvalidBase64Char { position: usize, c: char },
+    Base64Padding,
+}
+
+impl fmt::Display for FormatErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatErr::Empty => write!(f, "value is empty"),
+            FormatErr::MissingAt => write!(f, "missing an `@` between the local part and the domain"),
+            FormatErr::LocalPartTooLong(len) => write!(f, "local part is {} characters, the limit is 64", len),
+            FormatErr::InvalidLocalPart(c) => write!(f, "local part contains {:?}", c),
+            FormatErr::InvalidDomain(err) => write!(f, "invalid domain: {}", err),
+            FormatErr::MissingScheme => write!(f, "missing a scheme like `https:`"),
+            FormatErr::InvalidScheme(scheme) => write!(f, "{:?} is not a valid scheme", scheme),
+            FormatErr::SchemeNotAllowed { scheme, allowed } => {
+                write!(f, "scheme {:?} is not one of {}", scheme, allowed.join(", "))
+            }
+            FormatErr::MissingHost => write!(f, "missing a `//host` after the scheme"),
+            FormatErr::InvalidUriChar(c) => write!(f, "{:?} is not allowed in a URI", c),
+            FormatErr::TooLong(len) => write!(f, "hostname is {} characters, the limit is 253", len),
+            FormatErr::EmptyLabel => write!(f, "hostname has an empty label"),
+            FormatErr::LabelTooLong(label) => write!(f, "label {:?} is longer than 63 characters", label),
+            FormatErr::InvalidLabelChar { label, c } => write!(f, "label {:?} contains {:?}", label, c),
+            FormatErr::LabelHyphen(label) => write!(f, "label {:?} starts or ends with a hyphen", label),
+            FormatErr::OctetCount(n) => write!(f, "expected 4 octets, found {}", n),
+            FormatErr::InvalidOctet(octet) => write!(f, "{:?} is not an octet between 0 and 255", octet),
+            FormatErr::MultipleCompressions => write!(f, "`::` can only appear once"),
+            FormatErr::GroupCount(n) => write!(f, "expected 8 groups, found {}", n),
+            FormatErr::InvalidGroup(group) => write!(f, "{:?} is not a group of 1 to 4 hex digits", group),
+            FormatErr::InvalidIpv6 => write!(f, "not a valid IPv6 address"),
+            FormatErr::MissingPrefixLength => write!(f, "missing a `/prefix-length`"),
+            FormatErr::InvalidAddress(err) => write!(f, "invalid address: {}", err),
+            FormatErr::PrefixLength { prefix, max } => write!(f, "prefix length {:?} is not between 0 and {}", prefix, max),
+            FormatErr::UuidLength(len) => write!(f, "expected 36 characters, found {}", len),
+            FormatErr::InvalidUuidChar { position, c } => write!(f, "unexpected {:?} at position {}", c, position),
+            FormatErr::SemverParts(n) => write!(f, "expected MAJOR.MINOR.PATCH, found {} part(s)", n),
+            FormatErr::InvalidVersionNumber(n) => write!(f, "{:?} is not a number without leading zeros", n),
+            FormatErr::InvalidIdentifier(id) => write!(f, "{:?} is not a valid pre-release or build identifier", id),
+            FormatErr::Base64Length(len) => write!(f, "length {} is not a multiple of 4", len),
+            FormatErr::InvalidBase64Char { position, c } => write!(f, "unexpected {:?} at position {}", c, position),
+            FormatErr::Base64Padding => write!(f, "`=` padding can only appear at the end"),
+        }
+    }
+}
+
+impl<'a> Format<'a> {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Email => "email",
+            Format::Uri { .. } => "uri",
+            Format::Url { .. } => "url",
+            Format::Hostname => "hostname",
+            Format::Ipv4 => "ipv4",
+            Format::Ipv6 => "ipv6",
+            Format::Cidr => "cidr",
+            Format::Uuid => "uuid",
+            Format::Semver => "semver",
+            Format::Base64 => "base64",
+        }
+    }
+
+    pub fn from_name(name: &str, schemes: Option<Vec<&'a String>>) -> Option<Format<'a>> {
+        match name {
+            "email" => Some(Format::Email),
+            "uri" => Some(Format::Uri { schemes }),
+            "url" => Some(Format::Url { schemes }),
+            "hostname" => Some(Format::Hostname),
+            "ipv4" => Some(Format::Ipv4),
+            "ipv6" => Some(Format::Ipv6),
+            "cidr" => Some(Format::Cidr),
+            "uuid" => Some(Format::Uuid),
+            "semver" => Some(Format::Semver),
+            "base64" => Some(Format::Base64),
+            _ => None,
+        }
+    }
+
+    pub fn check(&self, s: &str) -> Result<(), FormatErr> {
+        if s.is_empty() {
+            return Err(FormatErr::Empty);
+        }
+        match self {
+            Format::Email => email(s),
+            Format::Uri { schemes } => uri(s, schemes.as_deref(), false),
+            Format::Url { schemes } => uri(s, schemes.as_deref(), true),
+            Format::Hostname => hostname(s),
+            Format::Ipv4 => ipv4(s),
+            Format::Ipv6 => ipv6(s),
+            Format::Cidr => cidr(s),
+            Format::Uuid => uuid(s),
+            Format::Semver => semver(s),
+            Format::Base64 => base64(s),
+        }
+    }
+}
+
+fn email(s: &str) -> Result<(), FormatErr> {
+    let (local, domain) = s.rsplit_once('@').ok_or(FormatErr::MissingAt)?;
+    if local.is_empty() {
+        return Err(FormatErr::Empty);
+    }
+    if local.len() > 64 {
+        return Err(FormatErr::LocalPartTooLong(local.len()));
+    }
+    const SPECIAL: &str = "!#$%&'*+-/=?^_`{|}~.";
+    if let Some(c) = local.chars().find(|c| !c.is_ascii_alphanumeric() && !SPECIAL.contains(*c)) {
+        return Err(FormatErr::InvalidLocalPart(c));
+    }
+    if local.starts_with('.') || local.ends_with('.') || local.contains("..") {
+        return Err(FormatErr::InvalidLocalPart('.'));
+    }
+    hostname(domain).map_err(|e| FormatErr::InvalidDomain(Box::new(e)))
+}
+
+fn uri(s: &str, schemes: Option<&[&String]>, needs_host: bool) -> Result<(), FormatErr> {
+    let (scheme, rest) = s.split_once(':').ok_or(FormatErr::MissingScheme)?;
+    let valid_scheme = scheme.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+    if !valid_scheme {
+        return Err(FormatErr::InvalidScheme(scheme.to_string()));
+    }
+    if let Some(allowed) = schemes {
+        if !allowed.iter().any(|a| a.eq_ignore_ascii_case(scheme)) {
+            let allowed = allowed.iter().map(|a| a.to_string()).collect();
+            return Err(FormatErr::SchemeNotAllowed { scheme: scheme.to_string(), allowed });
+        }
+    }
+    if let Some(c) = rest.chars().find(|c| c.is_whitespace() || c.is_control() || "<>\"{}|\\^`".contains(*c)) {
+        return Err(FormatErr::InvalidUriChar(c));
+    }
+    if needs_host {
+        let authority = rest.strip_prefix("//").ok_or(FormatErr::MissingHost)?;
+        let authority = authority.split(['/', '?', '#']).next().unwrap_or("");
+        let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+        if host.is_empty() {
+            return Err(FormatErr::MissingHost);
+        }
+    }
+    Ok(())
+}
+
+fn hostname(s: &str) -> Result<(), FormatErr> {
+    // a single trailing dot marks a fully qualified name
+    let s = s.strip_suffix('.').unwrap_or(s);
+    if s.len() > 253 {
+        return Err(FormatErr::TooLong(s.len()));
+    }
+    for label in s.split('.') {
+        if label.is_empty() {
+            return Err(FormatErr::EmptyLabel);
+        }
+        if label.len() > 63 {
+            return Err(FormatErr::LabelTooLong(label.to_string()));
+        }
+        if let Some(c) = label.chars().find(|c| !c.is_ascii_alphanumeric() && *c != '-') {
+            return Err(FormatErr::InvalidLabelChar { label: label.to_string(), c });
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(FormatErr::LabelHyphen(label.to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn ipv4(s: &str) -> Result<(), FormatErr> {
+    let octets: Vec<_> = s.split('.').collect();
+    if octets.len() != 4 {
+        return Err(FormatErr::OctetCount(octets.len()));
+    }
+    for octet in octets {
+        let numeric = !octet.is_empty() && octet.len() <= 3 && octet.chars().all(|c| c.is_ascii_digit());
+        let leading_zero = octet.len() > 1 && octet.starts_with('0');
+        if !numeric || leading_zero || octet.parse::<u16>().map_or(true, |n| n > 255) {
+            return Err(FormatErr::InvalidOctet(octet.to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn ipv6(s: &str) -> Result<(), FormatErr> {
+    if s.parse::<Ipv6Addr>().is_ok() {
+        return Ok(());
+    }
+    // the address didn't parse, so work out why
+    if s.matches("::").count() > 1 {
+        return Err(FormatErr::MultipleCompressions);
+    }
+    let groups: Vec<_> = s.split(':').filter(|g| !g.is_empty()).collect();
+    // an embedded IPv4 address counts for two groups
+    let (groups, ipv4_tail) = match groups.split_last() {
+        Some((last, init)) if last.contains('.') => (init.to_vec(), Some(*last)),
+        _ => (groups, None),
+    };
+    if let Some(tail) = ipv4_tail {
+        ipv4(tail).map_err(|e| FormatErr::InvalidAddress(Box::new(e)))?;
+    }
+    if let Some(group) = groups.iter().find(|g| g.len() > 4 || !g.chars().all(|c| c.is_ascii_hexdigit())) {
+        return Err(FormatErr::InvalidGroup(group.to_string()));
+    }
+    let count = groups.len() + if ipv4_tail.is_some() { 2 } else { 0 };
+    if count != 8 && !s.contains("::") || count > 8 {
+        return Err(FormatErr::GroupCount(count));
+    }
+    Err(FormatErr::InvalidIpv6)
+}
+
+fn cidr(s: &str) -> Result<(), FormatErr> {
+    let (addr, prefix) = s.rsplit_once('/').ok_or(FormatErr::MissingPrefixLength)?;
+    let max = if addr.contains(':') {
+        ipv6(addr).map_err(|e| FormatErr::InvalidAddress(Box::new(e)))?;
+        128
+    } else {
+        ipv4(addr).map_err(|e| FormatErr::InvalidAddress(Box::new(e)))?;
+        32
+    };
+    match prefix.parse::<u8>() {
+        Ok(n) if n <= max && (prefix == "0" || !prefix.starts_with('0')) => Ok(()),
+        _ => Err(FormatErr::PrefixLength { prefix: prefix.to_string(), max }),
+    }
+}
+
+fn uuid(s: &str) -> Result<(), FormatErr> {
+    let len = s.chars().count();
+    if len != 36 {
+        return Err(FormatErr::UuidLength(len));
+    }
+    for (position, c) in s.chars().enumerate() {
+        let hyphen = matches!(position, 8 | 13 | 18 | 23);
+        if hyphen != (c == '-') || !hyphen && !c.is_ascii_hexdigit() {
+            return Err(FormatErr::InvalidUuidChar { position, c });
+        }
+    }
+    Ok(())
+}
+
+fn semver(s: &str) -> Result<(), FormatErr> {
+    let (rest, build) = match s.split_once('+') {
+        Some((rest, build)) => (rest, Some(build)),
+        None => (s, None),
+    };
+    let (core, pre) = match rest.split_once('-') {
+        Some((core, pre)) => (core, Some(pre)),
+        None => (rest, None),
+    };
+    let parts: Vec<_> = core.split('.').collect();
+    if parts.len() != 3 {
+        return Err(FormatErr::SemverParts(parts.len()));
+    }
+    let number = |n: &str| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()) && (n == "0" || !n.starts_with('0'));
+    if let Some(part) = parts.iter().find(|p| !number(p)) {
+        return Err(FormatErr::InvalidVersionNumber(part.to_string()));
+    }
+    let identifiers = |ids: &str, numeric_rule: bool| {
+        for id in ids.split('.') {
+            let valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+            let leading_zero = numeric_rule && id.len() > 1 && id.starts_with('0') && id.chars().all(|c| c.is_ascii_digit());
+            if !valid || leading_zero {
+                return Err(FormatErr::InvalidIdentifier(id.to_string()));
+            }
+        }
+        Ok(())
+    };
+    if let Some(pre) = pre {
+        identifiers(pre, true)?;
+    }
+    if let Some(build) = build {
+        identifiers(build, false)?;
+    }
+    Ok(())
+}
+
+fn base64(s: &str) -> Result<(), FormatErr> {
+    let body = s.trim_end_matches('=');
+    for (position, c) in body.chars().enumerate() {
+        if c == '=' {
+            return Err(FormatErr::Base64Padding);
+        }
+        if !c.is_ascii_alphanumeric() && c != '+' && c != '/' {
+            return Err(FormatErr::InvalidBase64Char { position, c });
+        }
+    }
+    if s.len() - body.len() > 2 {
+        return Err(FormatErr::Base64Padding);
+    }
+    if s.len() % 4 != 0 {
+        return Err(FormatErr::Base64Length(s.len()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses() {
+        assert_eq!(Format::Ipv4.check("10.0.0.255"), Ok(()));
+        assert_eq!(Format::Ipv4.check("10.0.0"), Err(FormatErr::OctetCount(3)));
+        assert_eq!(Format::Ipv4.check("10.0.0.256"), Err(FormatErr::InvalidOctet(String::from("256"))));
+        assert_eq!(Format::Ipv6.check("fe80::1"), Ok(()));
+        assert_eq!(Format::Ipv6.check("fe80::1::2"), Err(FormatErr::MultipleCompressions));
+        assert_eq!(Format::Ipv6.check("fe80::1g"), Err(FormatErr::InvalidGroup(String::from("1g"))));
+        assert_eq!(Format::Cidr.check("10.0.0.0/8"), Ok(()));
+        assert_eq!(Format::Cidr.check("10.0.0.0/33"), Err(FormatErr::PrefixLength { prefix: String::from("33"), max: 32 }));
+        assert_eq!(Format::Cidr.check("10.0.0.0"), Err(FormatErr::MissingPrefixLength));
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Format::Hostname.check("api.example.com"), Ok(()));
+        assert_eq!(Format::Hostname.check("api..com"), Err(FormatErr::EmptyLabel));
+        assert_eq!(Format::Hostname.check("-api.com"), Err(FormatErr::LabelHyphen(String::from("-api"))));
+        assert_eq!(Format::Email.check("ops+alerts@example.com"), Ok(()));
+        assert_eq!(Format::Email.check("ops.example.com"), Err(FormatErr::MissingAt));
+        assert_eq!(Format::Email.check("ops@exa_mple.com"), Err(FormatErr::InvalidDomain(Box::new(
+            FormatErr::InvalidLabelChar { label: String::from("exa_mple"), c: '_' }
+        ))));
+    }
+
+    #[test]
+    fn uris() {
+        let https = String::from("https");
+        let url = Format::Url { schemes: Some(vec![&https]) };
+        assert_eq!(url.check("https://example.com/path?q=1"), Ok(()));
+        assert_eq!(url.check("http://example.com"), Err(FormatErr::SchemeNotAllowed {
+            scheme: String::from("http"),
+            allowed: vec![https.clone()],
+        }));
+        assert_eq!(url.check("https:/example.com"), Err(FormatErr::MissingHost));
+        assert_eq!(Format::Uri { schemes: None }.check("mailto:ops@example.com"), Ok(()));
+        assert_eq!(Format::Uri { schemes: None }.check("example.com"), Err(FormatErr::MissingScheme));
+    }
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(Format::Uuid.check("123e4567-e89b-12d3-a456-426614174000"), Ok(()));
+        assert_eq!(Format::Uuid.check("123e4567-e89b-12d3-a456-42661417400z"), Err(FormatErr::InvalidUuidChar { position: 35, c: 'z' }));
+        assert_eq!(Format::Semver.check("1.2.3-rc.1+build.5"), Ok(()));
+        assert_eq!(Format::Semver.check("1.2"), Err(FormatErr::SemverParts(2)));
+        assert_eq!(Format::Semver.check("1.02.3"), Err(FormatErr::InvalidVersionNumber(String::from("02"))));
+        assert_eq!(Format::Base64.check("aGVsbG8="), Ok(()));
+        assert_eq!(Format::Base64.check("aGVsbG8"), Err(FormatErr::Base64Length(7)));
+        assert_eq!(Format::Base64.check("aGV=sbG8"), Err(FormatErr::Base64Padding));
+    }
+}
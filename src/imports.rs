@@ -0,0 +1,473 @@
+use std::{collections::HashMap, fmt, fs, io, path::{Path, PathBuf}, slice};
+
+use lazy_static::lazy_static;
+use serde_yaml::{Mapping, Value};
+
+use crate::{definitions, options::display_value, valstr};
+
+lazy_static! {
+    static ref IMPORTS: Value = valstr!("imports");
+    static ref FROM: Value = valstr!("from");
+    static ref AS: Value = valstr!("as");
+    static ref EXTENDS: Value = valstr!("extends");
+    static ref TYPE: Value = valstr!("type");
+    static ref OBJECT: Value = valstr!("object");
+    static ref FIELDS: Value = valstr!("fields");
+    static ref DISCRIMINATOR: Value = valstr!("discriminator");
+    static ref VARIANTS: Value = valstr!("variants");
+}
+
+/// Spec files that can be imported by name instead of by path
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    names: HashMap<String, PathBuf>,
+}
+
+impl Registry {
+    pub fn register(&mut self, name: &str, path: impl Into<PathBuf>) {
+        self.names.insert(String::from(name), path.into());
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportErrKind {
+    Io(io::Error),
+    Yaml(serde_yaml::Error),
+    /// `imports` has to be a list of paths and names, or of `from` / `as` pairs
+    InvalidImports(Value),
+    /// only files on the local filesystem can be imported
+    Remote(String),
+    UnknownName(String),
+    /// the files that import each other, starting and ending with the same one
+    ImportCycle(Vec<PathBuf>),
+    /// two imports define the same top-level field, and the spec doesn't
+    /// settle it by defining the field itself
+    Conflict(PathBuf),
+    /// `extends` has to be a path to a field, like a reference
+    InvalidExtends(Value),
+    DanglingExtends(Vec<Value>),
+    /// only objects can extend or be extended
+    NotAnObject(Vec<Value>),
+    /// definitions that end up extending themselves, in the order they extend
+    /// each other
+    ExtendsCycle(Vec<Vec<Value>>),
+}
+
+/// A problem putting a spec together, in the file it was found in. The path
+/// is made of field names, like a `ParseErr`'s.
+#[derive(Debug)]
+pub struct ImportErr {
+    pub file: PathBuf,
+    pub path: Vec<Value>,
+    pub kind: ImportErrKind,
+}
+
+impl ImportErr {
+    fn new(file: &Path, path: &[Value], kind: ImportErrKind) -> ImportErr {
+        ImportErr { file: file.to_path_buf(), path: path.to_vec(), kind }
+    }
+}
+
+fn join(path: &[Value]) -> String {
+    path.iter().map(display_value).collect::<Vec<_>>().join(".")
+}
+
+impl fmt::Display for ImportErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.file.display())?;
+        if !self.path.is_empty() {
+            write!(f, "{}: ", join(&self.path))?;
+        }
+        match &self.kind {
+            ImportErrKind::Io(e) => write!(f, "{}", e),
+            ImportErrKind::Yaml(e) => write!(f, "{}", e),
+            ImportErrKind::InvalidImports(v) => write!(f, "{} isn't a path, a name or a `from` / `as` pair", display_value(v)),
+            ImportErrKind::Remote(source) => write!(f, "{} isn't on the local filesystem", source),
+            ImportErrKind::UnknownName(name) => write!(f, "no spec is registered as {}", name),
+            ImportErrKind::ImportCycle(files) => {
+                let files: Vec<_> = files.iter().map(|file| file.display().to_string()).collect();
+                write!(f, "imports itself through {}", files.join(" -> "))
+            }
+            ImportErrKind::Conflict(other) => write!(f, "is also imported from {}", other.display()),
+            ImportErrKind::InvalidExtends(v) => write!(f, "extends {}, which isn't a path to a field", display_value(v)),
+            ImportErrKind::DanglingExtends(target) => write!(f, "extends {}, which isn't in the spec", join(target)),
+            ImportErrKind::NotAnObject(target) => write!(f, "extends {}, but only objects can be extended", join(target)),
+            ImportErrKind::ExtendsCycle(cycle) => {
+                let cycle: Vec<_> = cycle.iter().map(|path| join(path)).collect();
+                write!(f, "extends itself through {}", cycle.join(" -> "))
+            }
+        }
+    }
+}
+
+/// Which file each part of a composed spec was written in, by the path of the
+/// field it starts at. The longest matching path wins.
+type Origins = Vec<(Vec<Value>, PathBuf)>;
+
+fn origin<'o>(root: &'o Path, origins: &'o Origins, path: &[&Value]) -> &'o Path {
+    origins.iter()
+        .filter(|(prefix, _)| prefix.len() <= path.len() && prefix.iter().zip(path).all(|(a, b)| a == *b))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map_or(root, |(_, file)| file)
+}
+
+fn canonical(file: &Path) -> PathBuf {
+    fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf())
+}
+
+/// Copies where everything under `from` came from over to `to`
+fn inherit(origins: &mut Origins, source: (&Path, &Origins), from: &[Value], to: &[Value]) {
+    let from_refs: Vec<_> = from.iter().collect();
+    let mut inherited = vec![(to.to_vec(), origin(source.0, source.1, &from_refs).to_path_buf())];
+    for (prefix, file) in source.1 {
+        if prefix.len() > from.len() && prefix.starts_with(from) {
+            let mut path = to.to_vec();
+            path.extend_from_slice(&prefix[from.len()..]);
+            inherited.push((path, file.clone()));
+        }
+    }
+    origins.extend(inherited);
+}
+
+/// A spec with its imports merged in and every `extends` resolved, ready to be
+/// used like one that was written in a single file
+#[derive(Debug, Clone)]
+pub struct Composed {
+    pub spec: Mapping,
+    file: PathBuf,
+    origins: Origins,
+}
+
+impl Composed {
+    /// The file a field was defined in, for pointing errors at it. The path is
+    /// made of field names only.
+    pub fn file_of(&self, path: &[&Value]) -> &Path {
+        origin(&self.file, &self.origins, path)
+    }
+}
+
+/// Loads a spec, along with everything it imports. A top-level `imports` list
+/// names other spec files, by a path relative to the importing file or by a
+/// name in the registry. Plain entries merge the other file's fields in, and
+/// the spec's own definitions win over imported ones. `{ from: ..., as: alias }`
+/// entries only make the other file's definitions available to `extends`, as
+/// `[alias, field, ...]`. An object with `extends: [path, to, object]` starts
+/// from that object's definition: its own keys replace the inherited ones,
+/// except `fields`, which are merged field by field.
+pub fn load(file: &Path, registry: &Registry) -> Result<Composed, Vec<ImportErr>> {
+    let canonical = fs::canonicalize(file).map_err(|e| vec![ImportErr::new(file, &[], ImportErrKind::Io(e))])?;
+    load_file(file, canonical, registry, &mut vec![])
+}
+
+/// Each file is on the stack, as it's found and as it was named, while its
+/// imports are loaded
+fn load_file(file: &Path, canonical: PathBuf, registry: &Registry, stack: &mut Vec<(PathBuf, PathBuf)>) -> Result<Composed, Vec<ImportErr>> {
+    stack.push((canonical, file.to_path_buf()));
+    let composed = compose(file, registry, stack);
+    stack.pop();
+    composed
+}
+
+fn compose(file: &Path, registry: &Registry, stack: &mut Vec<(PathBuf, PathBuf)>) -> Result<Composed, Vec<ImportErr>> {
+    let raw = fs::read_to_string(file).map_err(|e| vec![ImportErr::new(file, &[], ImportErrKind::Io(e))])?;
+    let mut own: Mapping = serde_yaml::from_str(&raw).map_err(|e| vec![ImportErr::new(file, &[], ImportErrKind::Yaml(e))])?;
+    // a field definition is never a list, so a list is always the directive
+    let imports = match own.get(&IMPORTS) {
+        Some(Value::Sequence(_)) => own.remove(&IMPORTS),
+        _ => None,
+    };
+
+    let mut err = vec![];
+    let mut spec = Mapping::new();
+    let mut origins = vec![];
+    let mut aliases = HashMap::new();
+    for entry in imports.iter().flat_map(|imports| imports.as_sequence().into_iter().flatten()) {
+        let (source, alias) = match entry {
+            Value::String(source) => (source, None),
+            Value::Mapping(m) => match (m.get(&FROM), m.get(&AS)) {
+                (Some(Value::String(source)), Some(alias @ Value::String(_))) if m.len() == 2 => (source, Some(alias)),
+                _ => {
+                    err.push(ImportErr::new(file, slice::from_ref(&*IMPORTS), ImportErrKind::InvalidImports(entry.clone())));
+                    continue;
+                }
+            },
+            _ => {
+                err.push(ImportErr::new(file, slice::from_ref(&*IMPORTS), ImportErrKind::InvalidImports(entry.clone())));
+                continue;
+            }
+        };
+        let imported = match locate(source, file, registry) {
+            Ok(path) => import(&path, file, registry, stack),
+            Err(kind) => Err(vec![ImportErr::new(file, slice::from_ref(&*IMPORTS), kind)]),
+        };
+        let imported = match imported {
+            Ok(imported) => imported,
+            Err(e) => {
+                err.extend(e);
+                continue;
+            }
+        };
+        if let Some(alias) = alias {
+            aliases.insert(alias.clone(), imported);
+            continue;
+        }
+        // the importing spec's version is the one that counts
+        for (name, definition) in definitions(&imported.spec) {
+            if own.contains_key(name) {
+                continue;
+            }
+            if spec.contains_key(name) {
+                let first = origin(file, &origins, &[name]).to_path_buf();
+                let second = origin(&imported.file, &imported.origins, &[name]);
+                // two imports can share a file of their own, which is still one definition
+                if canonical(&first) != canonical(second) {
+                    err.push(ImportErr::new(second, slice::from_ref(name), ImportErrKind::Conflict(first)));
+                }
+                continue;
+            }
+            spec.insert(name.clone(), definition.clone());
+            inherit(&mut origins, (&imported.file, &imported.origins), slice::from_ref(name), slice::from_ref(name));
+        }
+    }
+    for (name, definition) in own {
+        spec.insert(name, definition);
+    }
+
+    let mut scope = Scope { file, spec: &spec, aliases: &aliases, origins, done: HashMap::new(), chain: vec![], err };
+    let composed = spec.iter()
+        .map(|(name, definition)| (name.clone(), scope.expand(slice::from_ref(name)).unwrap_or_else(|_| definition.clone())))
+        .collect();
+    match scope.err.is_empty() {
+        true => Ok(Composed { spec: composed, file: file.to_path_buf(), origins: scope.origins }),
+        false => Err(scope.err),
+    }
+}
+
+/// Finds the file an import refers to. Anything that looks like a path is
+/// one, and everything else has to be registered.
+fn locate(source: &str, importer: &Path, registry: &Registry) -> Result<PathBuf, ImportErrKind> {
+    if source.contains("://") {
+        return Err(ImportErrKind::Remote(String::from(source)));
+    }
+    if source.ends_with(".yamlfmt") || source.contains('/') || source.contains('\\') {
+        let dir = importer.parent().unwrap_or_else(|| Path::new(""));
+        return Ok(dir.join(source));
+    }
+    match registry.names.get(source) {
+        Some(path) if path.to_string_lossy().contains("://") => Err(ImportErrKind::Remote(path.display().to_string())),
+        Some(path) => Ok(path.clone()),
+        None => Err(ImportErrKind::UnknownName(String::from(source))),
+    }
+}
+
+fn import(path: &Path, importer: &Path, registry: &Registry, stack: &mut Vec<(PathBuf, PathBuf)>) -> Result<Composed, Vec<ImportErr>> {
+    let canonical = fs::canonicalize(path).map_err(|e| vec![ImportErr::new(importer, slice::from_ref(&*IMPORTS), ImportErrKind::Io(e))])?;
+    if let Some(start) = stack.iter().position(|(c, _)| *c == canonical) {
+        let mut cycle: Vec<_> = stack[start..].iter().map(|(_, shown)| shown.clone()).collect();
+        cycle.push(path.to_path_buf());
+        return Err(vec![ImportErr::new(importer, slice::from_ref(&*IMPORTS), ImportErrKind::ImportCycle(cycle))]);
+    }
+    load_file(path, canonical, registry, stack)
+}
+
+/// Why a definition couldn't be resolved
+enum Unresolved {
+    Missing,
+    /// it's part of a cycle, which has been reported
+    Cycle,
+}
+
+/// Resolves the `extends` in one file's definitions
+struct Scope<'s> {
+    file: &'s Path,
+    /// the file's definitions with plain imports merged in, as written
+    spec: &'s Mapping,
+    aliases: &'s HashMap<Value, Composed>,
+    origins: Origins,
+    /// definitions that have been resolved, by path
+    done: HashMap<Vec<Value>, Value>,
+    /// the definitions being resolved, outermost first
+    chain: Vec<Vec<Value>>,
+    err: Vec<ImportErr>,
+}
+
+impl Scope<'_> {
+    /// The definition at `path` as written in this file, if it is
+    fn written(&self, path: &[Value]) -> Option<&Value> {
+        let (first, rest) = path.split_first()?;
+        rest.iter().try_fold(self.spec.get(first)?, |definition, name| definition.get(&*FIELDS)?.get(name))
+    }
+
+    /// The definition at `path` with everything it extends merged in. Fields
+    /// that were inherited from somewhere else have been resolved already.
+    fn expand(&mut self, path: &[Value]) -> Result<Value, Unresolved> {
+        if let Some(done) = self.done.get(path) {
+            return Ok(done.clone());
+        }
+        if let Some(start) = self.chain.iter().position(|p| p == path) {
+            let cycle = self.chain[start..].to_vec();
+            // each cycle is reported once, from wherever it was closed first
+            let reported = self.err.iter().any(|e| matches!(&e.kind, ImportErrKind::ExtendsCycle(c) if c.len() == cycle.len() && c.iter().all(|p| cycle.contains(p))));
+            if !reported {
+                self.err.push(ImportErr::new(self.file, path, ImportErrKind::ExtendsCycle(cycle)));
+            }
+            return Err(Unresolved::Cycle);
+        }
+        let written = match self.written(path) {
+            Some(written) => written.clone(),
+            None => return self.inherited(path),
+        };
+
+        self.chain.push(path.to_vec());
+        let expanded = match written {
+            Value::Mapping(config) => Value::Mapping(self.expand_mapping(config, path)),
+            other => other,
+        };
+        self.chain.pop();
+        self.done.insert(path.to_vec(), expanded.clone());
+        Ok(expanded)
+    }
+
+    /// A field that isn't written in this file, but that its parent inherits
+    fn inherited(&mut self, path: &[Value]) -> Result<Value, Unresolved> {
+        match path.split_last() {
+            Some((name, parent)) if !parent.is_empty() => {
+                self.expand(parent)?.get(&*FIELDS).and_then(|fields| fields.get(name)).cloned().ok_or(Unresolved::Missing)
+            }
+            _ => Err(Unresolved::Missing),
+        }
+    }
+
+    fn expand_mapping(&mut self, mut config: Mapping, path: &[Value]) -> Mapping {
+        if let Some(extends) = config.remove(&EXTENDS) {
+            if let Some(base) = self.base(&extends, path) {
+                config = self.merge(base, config, &extends, path);
+            }
+        }
+        // fields written here can extend other definitions too
+        let names: Vec<_> = config.get(&FIELDS).and_then(Value::as_mapping).into_iter().flatten()
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in names {
+            let mut field_path = path.to_vec();
+            field_path.push(name.clone());
+            let field = match self.written(&field_path).cloned() {
+                // a field that's part of a cycle is left as it is
+                Some(written) => self.expand(&field_path).unwrap_or(written),
+                None => self.expand_inline(&config[&*FIELDS][&name], &field_path),
+            };
+            if let Some(Value::Mapping(fields)) = config.get_mut(&FIELDS) {
+                fields.insert(name, field);
+            }
+        }
+        if let Some(Value::Mapping(discriminator)) = config.get_mut(&DISCRIMINATOR) {
+            if let Some(Value::Mapping(variants)) = discriminator.get_mut(&VARIANTS) {
+                for (_, variant) in variants.iter_mut() {
+                    if let Value::Mapping(fields) = variant {
+                        *fields = fields.iter()
+                            .map(|(name, field)| {
+                                let mut field_path = path.to_vec();
+                                field_path.push(name.clone());
+                                (name.clone(), self.expand_inline(field, &field_path))
+                            })
+                            .collect();
+                    }
+                }
+            }
+        }
+        config
+    }
+
+    /// Fields under a variant, or under one of those, can't be extended since
+    /// `extends` can't point at them, but they can extend other definitions
+    fn expand_inline(&mut self, field: &Value, path: &[Value]) -> Value {
+        match field {
+            Value::Mapping(config) => Value::Mapping(self.expand_mapping(config.clone(), path)),
+            other => other.clone(),
+        }
+    }
+
+    /// The resolved definition `extends` points at, if it's an object
+    fn base(&mut self, extends: &Value, path: &[Value]) -> Option<Mapping> {
+        let target = match extends {
+            Value::Sequence(target) if !target.is_empty() && target.iter().all(Value::is_string) => target.clone(),
+            _ => {
+                self.err.push(ImportErr::new(self.file, path, ImportErrKind::InvalidExtends(extends.clone())));
+                return None;
+            }
+        };
+        let base = match self.aliases.get(&target[0]) {
+            Some(imported) => target[1..].split_first().and_then(|(first, rest)| {
+                rest.iter().try_fold(imported.spec.get(first)?, |definition, name| definition.get(&*FIELDS)?.get(name))
+            }).cloned().ok_or(Unresolved::Missing),
+            None => self.expand(&target),
+        };
+        match base {
+            Err(Unresolved::Missing) => {
+                self.err.push(ImportErr::new(self.file, path, ImportErrKind::DanglingExtends(target)));
+                None
+            }
+            Err(Unresolved::Cycle) => None,
+            Ok(Value::Mapping(base)) if base.get(&TYPE) == Some(&OBJECT) => Some(base),
+            Ok(base) if base == *OBJECT => Some(std::iter::once((TYPE.clone(), OBJECT.clone())).collect()),
+            Ok(_) => {
+                self.err.push(ImportErr::new(self.file, path, ImportErrKind::NotAnObject(target)));
+                None
+            }
+        }
+    }
+
+    /// The definition's own keys on top of its base's, with `fields` merged
+    fn merge(&mut self, base: Mapping, config: Mapping, extends: &Value, path: &[Value]) -> Mapping {
+        let target: Vec<_> = extends.as_sequence().cloned().unwrap_or_default();
+        if config.get(&TYPE).is_some_and(|t| *t != *OBJECT) {
+            self.err.push(ImportErr::new(self.file, path, ImportErrKind::NotAnObject(target)));
+            return config;
+        }
+        let inherited: Vec<_> = base.get(&FIELDS).and_then(Value::as_mapping).into_iter().flatten()
+            .map(|(name, _)| name.clone())
+            .filter(|name| config.get(&FIELDS).and_then(|fields| fields.get(name)).is_none())
+            .collect();
+        for name in inherited {
+            let (mut from, mut to) = (target.clone(), path.to_vec());
+            from.push(name.clone());
+            to.push(name);
+            match self.aliases.get(&target[0]) {
+                Some(imported) => inherit(&mut self.origins, (&imported.file, &imported.origins), &from[1..], &to),
+                None => {
+                    let local = self.origins.clone();
+                    inherit(&mut self.origins, (self.file, &local), &from, &to);
+                }
+            }
+        }
+
+        let mut merged = base;
+        for (key, value) in config {
+            match (merged.get_mut(&key), value) {
+                (Some(Value::Mapping(fields)), Value::Mapping(own)) if key == *FIELDS => {
+                    for (name, field) in own {
+                        fields.insert(name, field);
+                    }
+                }
+                (_, value) => {
+                    merged.insert(key, value);
+                }
+            }
+        }
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extending_an_ancestor_is_a_cycle() {
+        let file = Path::new("tests/res/import-errors/extends-ancestor.yamlfmt");
+        let err = load(file, &Registry::default()).unwrap_err();
+        assert_eq!(1, err.len());
+        let (a, b) = (valstr!("a"), valstr!("b"));
+        assert!(matches!(&err[0].kind, ImportErrKind::ExtendsCycle(cycle) if *cycle == vec![vec![a.clone()], vec![a, b]]));
+    }
+}
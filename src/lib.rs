@@ -1,7 +1,10 @@
 use constraint::Constraint;
-use parse::{ParseErr, YamlParseResult};
+use bubble::Bubble;
+use lazy_static::lazy_static;
+use parse::{PEType, ParseErr, YamlParseResult};
 use obj::{ObjConstr, ObjectConstraint};
-use rule::{Rule, RuleEvalErr, RuleEvalSuccess};
+use rule::{Rule, RuleErrType, RuleEvalErr, RuleEvalSuccess};
+use options::Severity;
 use serde_yaml::{Mapping, Value};
 use value_ref::ValueResolutionErr;
 
@@ -14,6 +17,24 @@ pub mod rule;
 pub mod value_ref;
 pub mod bubble;
 pub mod constraint;
+pub mod stream;
+pub mod options;
+pub mod when;
+pub mod expr;
+pub mod format;
+pub mod quantity;
+pub mod temporal;
+pub mod fix;
+pub mod any;
+pub mod meta;
+pub mod lint;
+pub mod imports;
+pub mod overlay;
+pub mod diff;
+pub mod docs;
+pub mod example;
+
+mod refs;
 
 #[derive(Debug)]
 pub enum Evaluation<'a> {
@@ -23,24 +44,62 @@ pub enum Evaluation<'a> {
     Completed { ok: Vec<RuleEvalSuccess<'a>>, err: Vec<RuleEvalErr<'a>>},
 }
 
-pub fn yamlfmt<'a>(spec: &'a Mapping, input: &'a Value, name: &'a Value) -> Evaluation<'a> {
-    // first, parse the spec
-    let spec: Vec<_> = spec.iter().map(Constraint::from_spec).collect();
-    let yaml_parse: YamlParseResult = spec.into();
+/// How many failures an evaluation had at each severity. Problems with the
+/// spec itself always count as errors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub passed: usize,
+    pub errors: usize,
+    pub warnings: usize,
+    pub infos: usize,
+}
 
-    // if there are errors, return them
-    let (constraints, err): (Vec<_>, _) = yaml_parse.into_iter().partition(Result::is_ok);
-    if !err.is_empty() {
-        return Evaluation::GrammarParseErr(err.into_iter().map(Result::unwrap_err).collect());
+impl Summary {
+    fn count(&mut self, severity: Severity) {
+        match severity {
+            Severity::Error => self.errors += 1,
+            Severity::Warning => self.warnings += 1,
+            Severity::Info => self.infos += 1,
+        }
     }
 
+    /// Whether the input should be accepted, i.e. nothing failed as an error
+    pub fn is_ok(&self) -> bool {
+        self.errors == 0
+    }
+}
+
+impl Evaluation<'_> {
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary::default();
+        match self {
+            Evaluation::GrammarParseErr(err) => summary.errors = err.len(),
+            Evaluation::ValueResolutionErr(err) => summary.errors = err.len(),
+            Evaluation::RuleEvalErr(err) => err.iter().for_each(|e| summary.count(e.severity)),
+            Evaluation::Completed { ok, err } => {
+                for s in ok {
+                    match s.result {
+                        true => summary.passed += 1,
+                        false => summary.count(s.severity),
+                    }
+                }
+                err.iter().for_each(|e| summary.count(e.severity));
+            }
+        }
+        summary
+    }
+}
+
+pub fn yamlfmt<'a>(spec: &'a Mapping, input: &'a Value, name: &'a Value) -> Evaluation<'a> {
+    // first, parse the spec, returning any errors
+    let constraint = match document_constraint(spec, name) {
+        Ok(constraint) => constraint,
+        Err(err) => return Evaluation::GrammarParseErr(err),
+    };
+
     // if there are no errors, try value resolution
-    let constraints: Vec<_> = constraints.into_iter().map(Result::unwrap).collect();
-    let map = constraints.into_iter().map(|c| (c.field_name(), c)).collect();
-    let objconstr = ObjectConstraint::new(name, ObjConstr::Fields(map), None);
-    let constraint = Constraint::Obj(objconstr);
     let context = constraint.clone();
-    let (rules, err): (Vec<_>, _) = Rule::new(constraint, &input, &context).get().into_iter()
+    let (rules, err): (Vec<_>, _) = Rule::new(constraint, input, &context).get().into_iter()
         .partition(Result::is_ok);
     if !err.is_empty() {
         return Evaluation::ValueResolutionErr(err.into_iter().map(Result::unwrap_err).collect());
@@ -49,11 +108,91 @@ pub fn yamlfmt<'a>(spec: &'a Mapping, input: &'a Value, name: &'a Value) -> Eval
     // if all the rules are valid, evaluate them
     let rules: Vec<_> = rules.into_iter().map(Result::unwrap).collect();
     let (ok, err): (Vec<_>, _) = rules.into_iter()
-        .map(|rule| rule.eval(input, &[]))
-        .flatten()
+        .flat_map(|rule| rule.eval(input, &[]))
         .partition(Result::is_ok);
     let ok = ok.into_iter().map(Result::unwrap).collect();
     let err = err.into_iter().map(Result::unwrap_err).collect();
     Evaluation::Completed { ok, err }
 
 }
+
+lazy_static! {
+    static ref VERSION: Value = valstr!("version");
+}
+
+/// The spec's optional `version:` header. It's told apart from a field called
+/// `version` by its value, a number or a dotted string like `"1.4.0"`, which
+/// no field definition can be.
+pub fn version(spec: &Mapping) -> Option<&Value> {
+    spec.get(&VERSION).filter(|v| is_version(v))
+}
+
+fn is_version(value: &Value) -> bool {
+    match value {
+        Value::Number(_) => true,
+        Value::String(s) => s.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit())),
+        _ => false,
+    }
+}
+
+/// A spec's field definitions, without its header
+pub(crate) fn definitions(spec: &Mapping) -> impl Iterator<Item = (&Value, &Value)> {
+    spec.iter().filter(|(k, v)| !(**k == *VERSION && is_version(v)))
+}
+
+/// The top-level fields of a spec, wrapped up in the object that the document
+/// as a whole has to be
+pub(crate) fn document_constraint<'a>(spec: &'a Mapping, name: &'a Value) -> Result<Constraint<'a>, Vec<ParseErr<'a>>> {
+    let spec: Vec<_> = definitions(spec).map(Constraint::from_spec).collect();
+    let yaml_parse: YamlParseResult = spec.into();
+    let (constraints, err): (Vec<_>, _) = yaml_parse.into_iter().partition(Result::is_ok);
+    if !err.is_empty() {
+        return Err(err.into_iter().map(Result::unwrap_err).collect());
+    }
+    let map = constraints.into_iter().map(Result::unwrap).map(|c| (c.field_name(), c)).collect();
+    let constraint = Constraint::Obj(ObjectConstraint::new(name, ObjConstr::Fields(map), None));
+
+    // references and defaults can point anywhere in the spec, so they're
+    // checked once everything's parsed
+    let mut err = refs::check(&constraint);
+    if err.is_empty() {
+        default_errors(&constraint, &[], &constraint, &mut err);
+    }
+    match err.is_empty() {
+        true => Ok(constraint),
+        false => Err(err),
+    }
+}
+
+/// Every default has to pass its own field's constraint, with references
+/// resolving to the defaults of the fields they point at
+fn default_errors<'a>(constraint: &Constraint<'a>, path: &[&'a Value], root: &Constraint<'a>, err: &mut Vec<ParseErr<'a>>) {
+    lazy_static! {
+        static ref EMPTY: Value = Value::Mapping(Mapping::new());
+    }
+    // clauses don't apply to a document with nothing in it, so the default
+    // only has to fit the unconditional constraint
+    let constraint = constraint.unconditional();
+    if let (Some((_, parent)), Some(default)) = (path.split_last(), constraint.default()) {
+        // a reference that can't be resolved is reported when the rules are
+        if let Bubble::Single(Ok(rule)) = Rule::new(constraint.clone(), &EMPTY, root) {
+            // assertions look at the whole document rather than the default
+            let failures: Vec<_> = rule.eval(default, parent).into_iter()
+                .filter(|res| match res {
+                    Ok(s) => !s.result,
+                    Err(e) => !matches!(e.err, RuleErrType::AssertionFailed(_) | RuleErrType::Assertion { .. }),
+                })
+                .collect();
+            if !failures.is_empty() {
+                err.push(ParseErr::new(path, PEType::InvalidDefault { default, failures }));
+            }
+        }
+    }
+    if let Constraint::Obj(o) = constraint {
+        for (key, field) in o.fields() {
+            let mut field_path = path.to_vec();
+            field_path.push(key);
+            default_errors(field, &field_path, root, err);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+
+use lazy_static::lazy_static;
+use serde_yaml::{Mapping, Value};
+
+use crate::{constraint::Constraint, definitions, document_constraint, options::display_value, parse::ParseErr, valstr};
+
+lazy_static! {
+    static ref ROOT: Value = valstr!(".");
+    static ref TYPE: Value = valstr!("type");
+    static ref FIELDS: Value = valstr!("fields");
+    static ref DISCRIMINATOR: Value = valstr!("discriminator");
+    static ref VARIANTS: Value = valstr!("variants");
+}
+
+/// The keys that pick a field's constraint, in the order the builders look for
+/// them. Keys in the same group are used together. A string's length bounds,
+/// affixes and format are checked on top of whichever of these is used, so
+/// they never conflict.
+const STRING_PRECEDENCE: &[&[&str]] = &[
+    &["regex"], &["allowed"], &["disallowed"], &["eq"], &["neq"],
+];
+const QUANTITY_PRECEDENCE: &[&[&str]] = &[
+    &["allowed"], &["disallowed"], &["min", "max"], &["eq"], &["neq"], &["gt"], &["gte"], &["lt"], &["lte"],
+];
+
+/// Keys whose value can be a path to a number. Specs can't describe numbers
+/// yet, so unlike other references these aren't an error when they don't
+/// point at a field in the spec.
+const REFERENCES: &[&str] = &["min_length", "max_length"];
+/// Keys that hold a list of values
+const LISTS: &[&str] = &["allowed", "disallowed"];
+
+/// Something in a spec that parses fine but probably isn't what its author meant
+#[derive(Debug, Clone, PartialEq)]
+pub enum LintKind<'a> {
+    /// a field only gets one constraint, so the keys after the first are dropped
+    Conflicting { used: &'a Value, ignored: Vec<&'a Value> },
+    DuplicateValue { key: &'a Value, value: &'a Value },
+    /// a reference to a number field that the spec doesn't have
+    DanglingRef { key: &'a Value, target: Vec<&'a Value> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint<'a> {
+    /// the field's path in the spec, made of field names only
+    pub path: Vec<&'a Value>,
+    pub kind: LintKind<'a>,
+}
+
+fn join(values: &[&Value], separator: &str) -> String {
+    values.iter().map(|v| display_value(v)).collect::<Vec<_>>().join(separator)
+}
+
+impl fmt::Display for Lint<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", join(&self.path, "."))?;
+        match &self.kind {
+            LintKind::Conflicting { used, ignored } => {
+                write!(f, "{} is ignored because {} takes precedence", join(ignored, ", "), display_value(used))
+            }
+            LintKind::DuplicateValue { key, value } => {
+                write!(f, "{} is listed more than once in {}", display_value(value), display_value(key))
+            }
+            LintKind::DanglingRef { key, target } => {
+                write!(f, "{} refers to {}, which isn't in the spec", display_value(key), join(target, "."))
+            }
+        }
+    }
+}
+
+/// Looks for mistakes in a spec that can't be seen from any one key: keys that
+/// are silently ignored, duplicated list entries and length bounds that refer
+/// to fields that don't exist. A spec has to parse before it can be linted.
+pub fn lint(spec: &Mapping) -> Result<Vec<Lint<'_>>, Vec<ParseErr<'_>>> {
+    let root = document_constraint(spec, &ROOT)?;
+    let mut lints = vec![];
+    for (name, definition) in definitions(spec) {
+        check_definition(name, definition, &[], &root, &mut lints);
+    }
+    Ok(lints)
+}
+
+fn key<'a>(config: &'a Mapping, name: &str) -> Option<&'a Value> {
+    config.iter().map(|(k, _)| k).find(|k| matches!(k, Value::String(s) if s == name))
+}
+
+fn check_definition<'a>(name: &'a Value, definition: &'a Value, path: &[&'a Value], root: &Constraint<'a>, lints: &mut Vec<Lint<'a>>) {
+    let config = match definition {
+        Value::Mapping(m) => m,
+        _ => return,
+    };
+    let mut field_path = path.to_vec();
+    field_path.push(name);
+    let precedence = match config.get(&TYPE) {
+        Some(Value::String(t)) if t == "string" => STRING_PRECEDENCE,
+        Some(Value::String(t)) if t == "duration" || t == "bytesize" => QUANTITY_PRECEDENCE,
+        _ => &[],
+    };
+    let groups: Vec<Vec<_>> = precedence.iter()
+        .map(|group| group.iter().filter_map(|k| key(config, k)).collect())
+        .filter(|present: &Vec<_>| !present.is_empty())
+        .collect();
+    if let Some((used, rest)) = groups.split_first() {
+        if !rest.is_empty() {
+            let ignored = rest.iter().flatten().copied().collect();
+            lints.push(Lint { path: field_path.clone(), kind: LintKind::Conflicting { used: used[0], ignored } });
+        }
+    }
+
+    for (k, v) in config {
+        let name = match k {
+            Value::String(s) => s.as_str(),
+            _ => continue,
+        };
+        match v {
+            Value::Sequence(items) if LISTS.contains(&name) => {
+                for (i, item) in items.iter().enumerate() {
+                    // each repeat is only reported the first time it comes up again
+                    if items[..i].iter().filter(|x| *x == item).count() == 1 {
+                        lints.push(Lint { path: field_path.clone(), kind: LintKind::DuplicateValue { key: k, value: item } });
+                    }
+                }
+            }
+            Value::Sequence(target) if REFERENCES.contains(&name) => check_reference(k, target, &field_path, root, lints),
+            _ => {}
+        }
+    }
+
+    if let Some(Value::Mapping(fields)) = config.get(&FIELDS) {
+        for (k, v) in fields {
+            check_definition(k, v, &field_path, root, lints);
+        }
+    }
+    if let Some(Value::Mapping(discriminator)) = config.get(&DISCRIMINATOR) {
+        if let Some(Value::Mapping(variants)) = discriminator.get(&VARIANTS) {
+            for fields in variants.iter().filter_map(|(_, v)| v.as_mapping()) {
+                for (k, v) in fields {
+                    check_definition(k, v, &field_path, root, lints);
+                }
+            }
+        }
+    }
+}
+
+fn check_reference<'a>(key: &'a Value, target: &'a [Value], path: &[&'a Value], root: &Constraint<'a>, lints: &mut Vec<Lint<'a>>) {
+    let target: Vec<_> = target.iter().collect();
+    if root.find(&target).is_none() {
+        lints.push(Lint { path: path.to_vec(), kind: LintKind::DanglingRef { key, target } });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conflicting_keys_in_precedence_order() {
+        let spec: Mapping = serde_yaml::from_str(concat!(
+            "name:\n",
+            "  type: string\n",
+            "  allowed: [a, b]\n",
+            "  regex: ^a$\n",
+            "  min_length: 1\n",
+            "  max_length: 2\n",
+        )).unwrap();
+        let (name, regex, allowed) = (valstr!("name"), valstr!("regex"), valstr!("allowed"));
+        let expected = Lint { path: vec![&name], kind: LintKind::Conflicting { used: &regex, ignored: vec![&allowed] } };
+        assert_eq!(vec![expected.clone()], lint(&spec).unwrap());
+        assert_eq!("name: allowed is ignored because regex takes precedence", expected.to_string());
+    }
+}
@@ -0,0 +1,223 @@
+use std::{env, fs, path::Path, process};
+
+use serde_yaml::Value;
+use yaml_grammar::{Evaluation, Summary, docs::{self, DocFormat}, example::{self, Fill}, fix::{self, FixErr}, imports::{self, Composed, Registry}, lint, meta, options::{display_path, Severity}, overlay::{self, Layer}, rule::{RuleEvalErr, RuleEvalSuccess}, yamlfmt};
+
+const USAGE: &str = "usage: yaml-grammar [--fix] [--docs md|html] [--example minimal|full] [--env <name>] [--register <name>=<spec.yamlfmt>]... <spec.yamlfmt> [<input.yaml>...]";
+
+struct Args {
+    fix: bool,
+    /// print the spec's field reference instead of checking anything
+    docs: Option<DocFormat>,
+    /// print an example document instead of checking anything
+    example: Option<Fill>,
+    /// the environment whose refinements of the spec apply
+    env: Option<String>,
+    /// specs that can be imported by name
+    registry: Registry,
+    spec: String,
+    /// layers that are merged in order, like `base.yaml` and `prod.yaml`.
+    /// Without any, only the spec itself is checked.
+    inputs: Vec<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut fix = false;
+    let mut docs = None;
+    let mut example = None;
+    let mut env = None;
+    let mut registry = Registry::default();
+    let mut paths = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fix" => fix = true,
+            "--docs" => docs = match args.next().as_deref() {
+                Some("md") => Some(DocFormat::Markdown),
+                Some("html") => Some(DocFormat::Html),
+                _ => return Err(format!("--docs takes md or html\n{}", USAGE)),
+            },
+            "--example" => example = match args.next().as_deref() {
+                Some("minimal") => Some(Fill::Minimal),
+                Some("full") => Some(Fill::Full),
+                _ => return Err(format!("--example takes minimal or full\n{}", USAGE)),
+            },
+            "--env" => env = Some(args.next().ok_or_else(|| format!("--env takes an environment name\n{}", USAGE))?),
+            "--register" => match args.next().as_deref().and_then(|entry| entry.split_once('=')) {
+                Some((name, path)) => registry.register(name, path),
+                None => return Err(format!("--register takes <name>=<spec.yamlfmt>\n{}", USAGE)),
+            },
+            "-h" | "--help" => return Err(String::from(USAGE)),
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}\n{}", flag, USAGE)),
+            _ => paths.push(arg),
+        }
+    }
+    match paths.as_slice() {
+        // fixes are written back to the input, so there has to be just the one
+        [_] | [_, _, _, ..] if fix => Err(format!("--fix takes a single input\n{}", USAGE)),
+        [_, _, ..] if docs.is_some() => Err(format!("--docs only takes a spec\n{}", USAGE)),
+        [_, _, ..] if example.is_some() => Err(format!("--example only takes a spec\n{}", USAGE)),
+        [spec, inputs @ ..] => Ok(Args { fix, docs, example, env, registry, spec: spec.clone(), inputs: inputs.to_vec() }),
+        _ => Err(String::from(USAGE)),
+    }
+}
+
+fn read(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Failures of the document as a whole have no path to show
+fn describe(severity: Severity, path: &[&Value], message: String) -> String {
+    match display_path(path).as_str() {
+        "" => format!("{}: {}", severity, message),
+        path => format!("{}: {}: {}", severity, path, message),
+    }
+}
+
+fn describe_failure(s: &RuleEvalSuccess) -> String {
+    let message = s.diagnostic.as_ref().and_then(|d| d.message.clone())
+        .or_else(|| s.reason.as_ref().map(ToString::to_string))
+        .unwrap_or_else(|| String::from("is not valid"));
+    describe(s.severity, &s.path, message)
+}
+
+fn describe_err(e: &RuleEvalErr) -> String {
+    let message = e.diagnostic.as_ref().and_then(|d| d.message.clone())
+        .unwrap_or_else(|| e.err.to_string());
+    describe(e.severity, &e.path, message)
+}
+
+/// Meta-grammar results are at paths through the spec's own keys, like
+/// `.server.fields.tls`, but only the field names say which file a definition
+/// came from
+fn field_path<'a>(path: &[&'a Value]) -> Vec<&'a Value> {
+    let mut fields: Vec<_> = path.get(1..2).unwrap_or(&[]).to_vec();
+    let mut rest = path.get(2..).unwrap_or(&[]);
+    loop {
+        match rest {
+            [key, name, tail @ ..] if key.as_str() == Some("fields") => {
+                fields.push(*name);
+                rest = tail;
+            }
+            [key, variants, _, name, tail @ ..] if key.as_str() == Some("discriminator") && variants.as_str() == Some("variants") => {
+                fields.push(*name);
+                rest = tail;
+            }
+            _ => return fields,
+        }
+    }
+}
+
+/// Prints every failure and the totals. Problems with the spec say which of
+/// its files they're in, and `origin` says where anything else came from.
+fn report(eval: &Evaluation, spec: &Composed, origin: impl Fn(&[&Value]) -> String) -> Summary {
+    match eval {
+        Evaluation::GrammarParseErr(err) => err.iter().for_each(|e| println!("error: {}: {}", spec.file_of(&e.path).display(), e)),
+        Evaluation::ValueResolutionErr(err) => err.iter().for_each(|e| println!("error: {}", e)),
+        Evaluation::RuleEvalErr(err) => err.iter().for_each(|e| println!("{}{}", origin(&e.path), describe_err(e))),
+        Evaluation::Completed { ok, err } => {
+            ok.iter().filter(|s| !s.result).for_each(|s| println!("{}{}", origin(&s.path), describe_failure(s)));
+            err.iter().for_each(|e| println!("{}{}", origin(&e.path), describe_err(e)));
+        }
+    }
+    let summary = eval.summary();
+    println!("{} passed, {} errors, {} warnings, {} infos", summary.passed, summary.errors, summary.warnings, summary.infos);
+    summary
+}
+
+fn run(args: Args) -> Result<bool, String> {
+    let composed = match imports::load(Path::new(&args.spec), &args.registry) {
+        Ok(composed) => composed,
+        Err(err) => {
+            err.iter().for_each(|e| println!("error: {}", e));
+            return Ok(false);
+        }
+    };
+    let spec = match overlay::environment(&composed.spec, args.env.as_deref()) {
+        Ok(spec) => spec,
+        Err(err) => {
+            let file = |path: &[Value]| composed.file_of(&path.iter().collect::<Vec<_>>()).display().to_string();
+            err.iter().for_each(|e| println!("error: {}: {}", file(&e.path), e));
+            return Ok(false);
+        }
+    };
+    let spec = &spec;
+    let name = Value::String(String::from("."));
+    let spec_file = |path: &[&Value]| format!("{}: ", composed.file_of(&field_path(path)).display());
+
+    // a broken spec is reported against the meta-grammar, which says more
+    // than the spec's own parse errors would
+    let checked = meta::check(spec, &name);
+    if !checked.summary().is_ok() {
+        return Ok(report(&checked, &composed, spec_file).is_ok());
+    }
+    if let Some(format) = args.docs {
+        return match docs::render(spec, format) {
+            Ok(reference) => {
+                print!("{}", reference);
+                Ok(true)
+            }
+            Err(err) => Ok(report(&Evaluation::GrammarParseErr(err), &composed, spec_file).is_ok()),
+        };
+    }
+    if let Some(fill) = args.example {
+        return match example::example(spec, fill) {
+            Ok(example) => {
+                print!("{}", example.text);
+                Ok(true)
+            }
+            Err(err) => Ok(report(&Evaluation::GrammarParseErr(err), &composed, spec_file).is_ok()),
+        };
+    }
+    // lints are only ever warnings, the spec is still used as written
+    if let Ok(lints) = lint::lint(spec) {
+        lints.iter().for_each(|l| println!("warning: {}: {}", composed.file_of(&l.path).display(), l));
+    }
+    if args.inputs.is_empty() {
+        return Ok(report(&checked, &composed, spec_file).is_ok());
+    }
+    if args.inputs.len() > 1 {
+        let mut layers = vec![];
+        for path in &args.inputs {
+            let value = serde_yaml::from_str(&read(path)?).map_err(|e| format!("{}: {}", path, e))?;
+            layers.push(Layer { name: path.clone(), value });
+        }
+        let layered = overlay::merge(&layers);
+        let layer = |path: &[&Value]| layered.layer_of(path).map_or_else(String::new, |layer| format!("{}: ", layer));
+        return Ok(report(&yamlfmt(spec, &layered.value, &name), &composed, layer).is_ok());
+    }
+    let input_path = &args.inputs[0];
+    let mut raw = read(input_path)?;
+
+    if args.fix {
+        let fixed = match fix::fix(spec, &raw, &name) {
+            Ok(fixed) => fixed,
+            Err(FixErr::GrammarParseErr(err)) => {
+                let errors: Vec<_> = err.iter().map(|e| format!("{}: {}", args.spec, e)).collect();
+                return Err(errors.join("\n"));
+            }
+            Err(FixErr::Input(e)) => return Err(format!("{}: {}", input_path, e)),
+        };
+        fixed.applied.iter().for_each(|f| println!("fixed {}", f));
+        fixed.skipped.iter().for_each(|f| println!("could not fix {}", f));
+        if !fixed.applied.is_empty() {
+            fs::write(input_path, &fixed.text).map_err(|e| format!("{}: {}", input_path, e))?;
+        }
+        raw = fixed.text;
+    }
+
+    let input: Value = serde_yaml::from_str(&raw).map_err(|e| format!("{}: {}", input_path, e))?;
+    Ok(report(&yamlfmt(spec, &input, &name), &composed, |_| String::new()).is_ok())
+}
+
+fn main() {
+    let result = parse_args().and_then(run);
+    match result {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use lazy_static::lazy_static;
+use serde_yaml::{Mapping, Value};
+
+use crate::{Evaluation, bubble::Bubble, definitions, constraint::Constraint, parse::ParseErr, rule::{Rule, RuleEvalErr, RuleEvalSuccess}, valstr};
+
+lazy_static! {
+    /// The grammar of spec files, see `meta.yamlfmt`
+    pub static ref GRAMMAR: Mapping = serde_yaml::from_str(include_str!("meta.yamlfmt"))
+        .expect("the meta-grammar is valid YAML");
+    static ref TYPE_NAME: Value = valstr!("type_name");
+    static ref FIELD: Value = valstr!("field");
+    static ref FIELDS: Value = valstr!("fields");
+    static ref DISCRIMINATOR: Value = valstr!("discriminator");
+    static ref VARIANTS: Value = valstr!("variants");
+    static ref TYPE: Value = valstr!("type");
+    static ref WHEN: Value = valstr!("when");
+    static ref THEN: Value = valstr!("then");
+    static ref ELSE: Value = valstr!("else");
+    /// The grammar of a `when` branch for each type. A branch takes the type of
+    /// its field, so it's a closed object with the settings every type accepts
+    /// and those of that one type.
+    static ref BRANCHES: Mapping = {
+        let field = &GRAMMAR[&*FIELD];
+        let common = field[&*FIELDS].as_mapping().expect("the meta-grammar lists the common settings");
+        let variants = field[&*DISCRIMINATOR][&*VARIANTS].as_mapping().expect("the meta-grammar lists the types");
+        variants.iter()
+            .map(|(tag, settings)| {
+                let mut fields = common.clone();
+                fields.extend(settings.as_mapping().into_iter().flatten().map(|(k, v)| (k.clone(), v.clone())));
+                let mut branch = Mapping::new();
+                branch.insert(TYPE.clone(), valstr!("object"));
+                branch.insert(valstr!("additional_keys"), Value::Bool(false));
+                branch.insert(FIELDS.clone(), Value::Mapping(fields));
+                (tag.clone(), Value::Mapping(branch))
+            })
+            .collect()
+    };
+}
+
+/// Checks a spec against the grammar of the spec language itself, so that
+/// misspelled keys, unknown types and wrongly typed settings are reported the
+/// same way as problems in a document. Paths run through the spec, including
+/// keys like `fields` and `variants`.
+pub fn check<'a>(spec: &'a Mapping, name: &'a Value) -> Evaluation<'a> {
+    let mut results = vec![];
+    for (key, definition) in definitions(spec) {
+        if let Err(err) = check_field(key, definition, &[name], &mut results) {
+            return Evaluation::GrammarParseErr(vec![err]);
+        }
+    }
+    let (ok, err): (Vec<_>, _) = results.into_iter().partition(Result::is_ok);
+    let ok = ok.into_iter().map(Result::unwrap).collect();
+    let err = err.into_iter().map(Result::unwrap_err).collect();
+    Evaluation::Completed { ok, err }
+}
+
+type MetaResults<'a> = Vec<Result<RuleEvalSuccess<'a>, RuleEvalErr<'a>>>;
+
+/// The grammar is checked one field definition at a time, since a field's
+/// definition can nest more of them under any key
+fn check_field<'a>(key: &'a Value, definition: &'a Value, path: &[&'a Value], results: &mut MetaResults<'a>) -> Result<(), ParseErr<'a>> {
+    let grammar = match definition {
+        Value::String(_) => &GRAMMAR[&*TYPE_NAME],
+        _ => &GRAMMAR[&*FIELD],
+    };
+    check_definition(grammar, key, definition, None, path, results)
+}
+
+/// Checks one definition against `grammar`, then the definitions nested in it.
+/// `when` branches don't name a type, so they're given the one of their field.
+fn check_definition<'a>(grammar: &'static Value, key: &'a Value, definition: &'a Value, field_type: Option<&'a str>, path: &[&'a Value], results: &mut MetaResults<'a>) -> Result<(), ParseErr<'a>> {
+    // the grammar's rule takes on the name of the field it's checking
+    let constraint = match Constraint::parse(key, grammar, path) {
+        Bubble::Single(Ok(constraint)) => constraint,
+        errors => return Err(errors.into_iter().find_map(Result::err).expect("a failed parse has errors")),
+    };
+    let context = constraint.clone();
+    match Rule::new(constraint, definition, &context) {
+        Bubble::Single(Ok(rule)) => results.extend(rule.eval(definition, path)),
+        _ => unreachable!("the meta-grammar doesn't refer to other fields"),
+    }
+
+    let definition = match definition {
+        Value::Mapping(m) => m,
+        _ => return Ok(()),
+    };
+    let mut field_path = path.to_vec();
+    field_path.push(key);
+    if let Some(Value::Mapping(fields)) = definition.get(&FIELDS) {
+        let mut fields_path = field_path.clone();
+        fields_path.push(&*FIELDS);
+        for (k, v) in fields {
+            check_field(k, v, &fields_path, results)?;
+        }
+    }
+    let variants = match definition.get(&DISCRIMINATOR) {
+        Some(Value::Mapping(d)) => d.get(&VARIANTS),
+        _ => None,
+    };
+    if let Some(Value::Mapping(variants)) = variants {
+        for (tag, variant) in variants {
+            if let Value::Mapping(fields) = variant {
+                let mut variant_path = field_path.clone();
+                variant_path.extend([&*DISCRIMINATOR, &*VARIANTS, tag]);
+                for (k, v) in fields {
+                    check_field(k, v, &variant_path, results)?;
+                }
+            }
+        }
+    }
+    let field_type = match definition.get(&TYPE) {
+        Some(Value::String(t)) => Some(t.as_str()),
+        _ => field_type,
+    };
+    let clauses = match definition.get(&WHEN) {
+        Some(Value::Sequence(clauses)) => clauses.iter().collect(),
+        Some(clause) => vec![clause],
+        None => vec![],
+    };
+    if let Some(grammar) = field_type.and_then(|t| BRANCHES.get(&valstr!(t))) {
+        let mut when_path = field_path.clone();
+        when_path.push(&*WHEN);
+        for clause in clauses {
+            for branch in [&*THEN, &*ELSE] {
+                if let Some(b) = clause.get(branch) {
+                    check_definition(grammar, branch, b, field_type, &when_path, results)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grammar_parses() {
+        let (name, input) = (valstr!("."), Value::Mapping(Mapping::new()));
+        let eval = crate::yamlfmt(&GRAMMAR, &input, &name);
+        assert!(!matches!(eval, Evaluation::GrammarParseErr(_)), "{:?}", eval);
+    }
+
+    #[test]
+    fn grammar_checks_itself() {
+        let name = valstr!(".");
+        let summary = check(&GRAMMAR, &name).summary();
+        assert_eq!(0, summary.errors);
+        assert!(summary.passed > 0);
+    }
+}
@@ -1,6 +1,16 @@
-use serde_yaml::{Mapping, Number, Value};
+use serde_yaml::{Number, Value};
 
-use crate::value_ref::ValueRef;
+use crate::{parse::PEType, value_ref::ValueRef};
+
+impl<'a> ValueRef<'a, Number> {
+    pub fn new(value: &'a Value) -> Result<ValueRef<'a, Number>, PEType<'a>> {
+        match value {
+            Value::Number(literal) => Ok(ValueRef::Literal(literal)),
+            Value::Sequence(path) => ValueRef::abs_path(path),
+            _ => Err(PEType::IncorrectType(value))
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum NumConstr<'a> {
@@ -24,7 +34,7 @@ pub struct NumberConstraint<'a> {
 }
 
 impl<'a> NumberConstraint<'a> {
-    pub fn default(field_name: &Value) -> NumberConstraint {
+    pub fn default(field_name: &Value) -> NumberConstraint<'_> {
         NumberConstraint { field_name, constr: NumConstr::Any, default: None }
     }
 
@@ -32,11 +42,3 @@ impl<'a> NumberConstraint<'a> {
         NumberConstraint { field_name, constr, default }
     }
 }
-
-#[derive(Debug)]
-struct NumConstrBuilder<'a, 'b> {
-    field_name: &'a Value,
-    map: &'a Mapping,
-    path: &'b [&'a Value],
-    default: Option<&'a Number>
-}
\ No newline at end of file
@@ -4,62 +4,154 @@ use parse::YamlParseResult;
 use lazy_static::lazy_static;
 use serde_yaml::{Mapping, Value};
 
-use crate::{bubble::Bubble, constraint::Constraint, parse::{self, PEType, ParseErr}, rule::{Rule, RuleErrType, RuleEvalErr, RuleEvalResult, RuleEvalSuccess, ValueResolutionResult}, value_ref::DefaultFetchErr};
+use crate::{bubble::Bubble, constraint::Constraint, expr::{Assertion, EvalErr}, options::{display_value, FieldOptions, Severity}, parse::{self, PEType, ParseErr}, rule::{Reason, Rule, RuleErrType, RuleEvalErr, RuleEvalResult, RuleEvalSuccess, ValueResolutionResult}, value_ref::DefaultFetchErr};
 use crate::valstr;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ObjConstr<'a> {
     Fields(HashMap<&'a Value, Constraint<'a>>),
+    /// The value of `key` picks which set of variant fields applies, on top of
+    /// the fields that every variant shares. Variants stay in spec order so
+    /// that errors can list them the way the author wrote them.
+    Discriminated {
+        key: &'a Value,
+        fields: HashMap<&'a Value, Constraint<'a>>,
+        variants: Vec<(&'a Value, HashMap<&'a Value, Constraint<'a>>)>,
+    },
     Any,
 }
 
+/// Rules about which keys of an object may (or must) appear together. These
+/// only look at the keys in the input, not at defaults.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyRelation<'a> {
+    /// at most one of the keys may be present
+    Exclusive(Vec<&'a Value>),
+    /// at least one of the keys must be present
+    OneRequired(Vec<&'a Value>),
+    /// if `key` is present, all of the keys it requires have to be as well
+    DependsOn {
+        key: &'a Value,
+        requires: Vec<&'a Value>,
+    },
+}
+
+impl<'a> KeyRelation<'a> {
+    /// Every key the relation names
+    pub fn keys(&self) -> Vec<&'a Value> {
+        match self {
+            KeyRelation::Exclusive(keys) | KeyRelation::OneRequired(keys) => keys.clone(),
+            KeyRelation::DependsOn { key, requires } => std::iter::once(*key).chain(requires.iter().copied()).collect(),
+        }
+    }
+
+    pub fn eval(&self, input: &'a Mapping, path: &[&'a Value]) -> RuleEvalResult<'a> {
+        let present = |keys: &[&'a Value]| keys.iter()
+            .filter(|k| input.contains_key(k))
+            .copied()
+            .collect::<Vec<_>>();
+        match self {
+            KeyRelation::Exclusive(keys) => {
+                let found = present(keys);
+                if found.len() > 1 {
+                    let err = RuleErrType::ExclusiveKeys { keys: keys.clone(), present: found };
+                    return RuleEvalErr::new(path, err).into();
+                }
+            }
+            KeyRelation::OneRequired(keys) => {
+                if present(keys).is_empty() {
+                    return RuleEvalErr::new(path, RuleErrType::OneRequired(keys.clone())).into();
+                }
+            }
+            KeyRelation::DependsOn { key, requires } => {
+                if input.contains_key(key) {
+                    let found = present(requires);
+                    if found.len() < requires.len() {
+                        let missing = requires.iter().filter(|k| !found.contains(k)).copied().collect();
+                        let err = RuleErrType::MissingDependency { key, requires: requires.clone(), missing };
+                        return RuleEvalErr::new(path, err).into();
+                    }
+                }
+            }
+        }
+        RuleEvalSuccess::new(true, path).into()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ObjectConstraint<'a> {
     pub field_name: &'a Value,
     pub constr: ObjConstr<'a>,
     pub default: Option<&'a Value>,
+    pub options: FieldOptions,
+    pub relations: Vec<KeyRelation<'a>>,
+    pub assertions: Vec<Assertion<'a>>,
+    /// whether keys that none of the fields account for are let through
+    pub additional_keys: bool,
 }
 
 impl<'a> ObjectConstraint<'a> {
-    pub fn default(field_name: &Value) -> ObjectConstraint {
-        ObjectConstraint { field_name, constr: ObjConstr::Any, default: None }
+    pub fn default(field_name: &Value) -> ObjectConstraint<'_> {
+        ObjectConstraint { field_name, constr: ObjConstr::Any, default: None, options: FieldOptions::default(), relations: vec![], assertions: vec![], additional_keys: true }
     }
 
     pub fn new(field_name: &'a Value, constr: ObjConstr<'a>, default: Option<&'a Value>) -> ObjectConstraint<'a> {
-        ObjectConstraint { field_name, constr, default }
+        ObjectConstraint { field_name, constr, default, options: FieldOptions::default(), relations: vec![], assertions: vec![], additional_keys: true }
     }
 
     pub fn add(&mut self, field_name: &'a Value, constraint: Constraint<'a>) {
         if let ObjConstr::Fields(map) = &mut self.constr {
-            map.insert(&field_name, constraint);
+            map.insert(field_name, constraint);
         }
     }
 
-    pub fn constraint(&self, path: &[&'a Value]) -> Result<&Constraint<'a>, DefaultFetchErr<'a>> {
+    /// Finds the constraint for one of this object's fields. A field that only
+    /// exists in some variants is looked up in the first variant that has it.
+    pub fn field(&self, key: &Value) -> Option<&Constraint<'a>> {
         match &self.constr {
-            ObjConstr::Fields(f) => {
-                let key = path.iter().next().ok_or_else(|| DefaultFetchErr::PathIsTooShort(path.to_vec()))?;
-                if let Some(constr) = f.get(key) {
-                    // if we're at the end of the line, return
-                    if path.len() == 1 {
-                        return Ok(constr);
-                    }
-                    // if we need to traverse further, see if that's possible
-                    match constr {
-                        Constraint::Obj(obj_constr) => {
-                            // we know the length is at least 1 (from above)
-                            // so there's no risk of panicking
-                            obj_constr.constraint(&path[1..])
-                        }
-                        _ => Err(DefaultFetchErr::IncorrectType{ residual_path: path.to_vec(), constr: constr.clone() }),
-                    }
-                } else {
-                    return Err(DefaultFetchErr::KeyNotFound(path.to_vec()));
-                }
+            ObjConstr::Fields(f) => f.get(key),
+            ObjConstr::Discriminated { fields, variants, .. } => fields.get(key)
+                .or_else(|| variants.iter().find_map(|(_, v)| v.get(key))),
+            ObjConstr::Any => None,
+        }
+    }
+
+    /// Every field this object can have, including those of every variant, in
+    /// key order
+    pub fn fields(&self) -> Vec<(&'a Value, &Constraint<'a>)> {
+        let mut fields: Vec<_> = match &self.constr {
+            ObjConstr::Fields(fields) => fields.iter().map(|(k, c)| (*k, c)).collect(),
+            ObjConstr::Discriminated { fields, variants, .. } => fields.iter()
+                .chain(variants.iter().flat_map(|(_, v)| v.iter()))
+                .map(|(k, c)| (*k, c))
+                .collect(),
+            ObjConstr::Any => vec![],
+        };
+        fields.sort_by_key(|(k, _)| display_value(k));
+        fields
+    }
+
+    pub fn constraint(&self, path: &[&'a Value]) -> Result<&Constraint<'a>, DefaultFetchErr<'a>> {
+        if let ObjConstr::Any = self.constr {
+            return Err(DefaultFetchErr::ConstraintIsAny(path.to_vec()));
+        }
+        let key = path.iter().next().ok_or_else(|| DefaultFetchErr::PathIsTooShort(path.to_vec()))?;
+        if let Some(constr) = self.field(key) {
+            // if we're at the end of the line, return
+            if path.len() == 1 {
+                return Ok(constr);
             }
-            ObjConstr::Any => {
-                Err(DefaultFetchErr::ConstraintIsAny(path.to_vec()))
+            // if we need to traverse further, see if that's possible
+            match constr.unconditional() {
+                Constraint::Obj(obj_constr) => {
+                    // we know the length is at least 1 (from above)
+                    // so there's no risk of panicking
+                    obj_constr.constraint(&path[1..])
+                }
+                _ => Err(DefaultFetchErr::IncorrectType{ residual_path: path.to_vec(), constr: Box::new(constr.clone()) }),
             }
+        } else {
+            Err(DefaultFetchErr::KeyNotFound(path.to_vec()))
         }
     }
 }
@@ -76,12 +168,138 @@ struct ObjectConstraintBuilder<'a, 'b> {
     config: &'a Mapping,
     path: &'b [&'a Value],
     default: Option<&'a Value>,
+    options: FieldOptions,
+    relations: Vec<KeyRelation<'a>>,
+    assertions: Vec<Assertion<'a>>,
+    additional_keys: bool,
 }
 
 impl<'a, 'b> ObjectConstraintBuilder<'a, 'b> {
     fn new(field_name: &'a Value, config: &'a Mapping, path: &'b [&'a Value]) -> Result<Self, ParseErr<'a>> {
+        lazy_static! {
+            static ref ADDITIONAL_KEYS: Value = valstr!("additional_keys");
+        }
         let default = Self::field_default(config, path)?;
-        Ok(Self { field_name, config, path, default })
+        let options = FieldOptions::parse(config, path)?;
+        let mut full_path = path.to_vec();
+        full_path.push(field_name);
+        let relations = Self::relations(config, &full_path)?;
+        let assertions = Self::assertions(config, &full_path)?;
+        let additional_keys = match config.get(&ADDITIONAL_KEYS) {
+            Some(Value::Bool(b)) => *b,
+            Some(val) => return Err(ParseErr::new(&full_path, PEType::IncorrectType(val))),
+            None => true,
+        };
+        Ok(Self { field_name, config, path, default, options, relations, assertions, additional_keys })
+    }
+
+    fn constraint(&self, constr: ObjConstr<'a>) -> YamlParseResult<'a> {
+        let constraint = ObjectConstraint {
+            field_name: self.field_name,
+            constr,
+            default: self.default,
+            options: self.options.clone(),
+            relations: self.relations.clone(),
+            assertions: self.assertions.clone(),
+            additional_keys: self.additional_keys,
+        };
+        if self.additional_keys {
+            return constraint.into();
+        }
+        // a closed object can't have any other keys, so a relation about one
+        // could never hold or fail
+        let mut declared: Vec<&Value> = constraint.fields().into_iter()
+            .flat_map(|(key, field)| std::iter::once(key).chain(field.options().renamed_from.iter()))
+            .collect();
+        if let ObjConstr::Discriminated { key, .. } = &constraint.constr {
+            declared.push(key);
+        }
+        let path = self.path();
+        let undeclared: Vec<_> = self.relations.iter()
+            .flat_map(KeyRelation::keys)
+            .filter(|key| !declared.contains(key))
+            .map(|key| ParseErr::new(&path, PEType::UndeclaredKey(key)))
+            .collect();
+        match undeclared.is_empty() {
+            true => constraint.into(),
+            false => undeclared.into(),
+        }
+    }
+
+    /// Assertions are parsed up front, so syntax errors are reported along
+    /// with every other problem in the spec
+    fn assertions(config: &'a Mapping, path: &[&'a Value]) -> Result<Vec<Assertion<'a>>, ParseErr<'a>> {
+        lazy_static! {
+            static ref ASSERT: Value = valstr!("assert");
+        }
+        let sources = match config.get(&ASSERT) {
+            Some(Value::Sequence(seq)) => seq.iter().collect(),
+            Some(val) => vec![val],
+            None => vec![],
+        };
+        sources.into_iter()
+            .map(|source| match source {
+                Value::String(s) => Assertion::new(s, path)
+                    .map_err(|err| ParseErr::new(path, PEType::Expression { source: s, err })),
+                _ => Err(ParseErr::new(path, PEType::IncorrectType(source))),
+            })
+            .collect()
+    }
+
+    fn relations(config: &'a Mapping, path: &[&'a Value]) -> Result<Vec<KeyRelation<'a>>, ParseErr<'a>> {
+        lazy_static! {
+            static ref EXCLUSIVE: Value = valstr!("exclusive");
+            static ref ONE_REQUIRED: Value = valstr!("one_required");
+            static ref DEPENDS_ON: Value = valstr!("depends_on");
+        }
+        let mut relations = vec![];
+        if let Some(val) = config.get(&EXCLUSIVE) {
+            relations.extend(Self::key_groups(val, path)?.into_iter().map(KeyRelation::Exclusive));
+        }
+        if let Some(val) = config.get(&ONE_REQUIRED) {
+            relations.extend(Self::key_groups(val, path)?.into_iter().map(KeyRelation::OneRequired));
+        }
+        if let Some(val) = config.get(&DEPENDS_ON) {
+            if let Value::Mapping(m) = val {
+                for (key, requires) in m {
+                    let requires = match requires {
+                        Value::Sequence(_) => Self::keys(requires, path)?,
+                        _ => vec![Self::key(requires, path)?],
+                    };
+                    let key = Self::key(key, path)?;
+                    relations.push(KeyRelation::DependsOn { key, requires });
+                }
+            } else {
+                return Err(ParseErr::new(path, PEType::IncorrectType(val)));
+            }
+        }
+        Ok(relations)
+    }
+
+    /// Either a single list of keys, or a list of lists for several groups
+    fn key_groups(groups: &'a Value, path: &[&'a Value]) -> Result<Vec<Vec<&'a Value>>, ParseErr<'a>> {
+        match groups {
+            Value::Sequence(seq) if seq.iter().all(|g| matches!(g, Value::Sequence(_))) => {
+                seq.iter().map(|group| Self::keys(group, path)).collect()
+            }
+            _ => Ok(vec![Self::keys(groups, path)?]),
+        }
+    }
+
+    fn keys(keys: &'a Value, path: &[&'a Value]) -> Result<Vec<&'a Value>, ParseErr<'a>> {
+        if let Value::Sequence(seq) = keys {
+            seq.iter().map(|key| Self::key(key, path)).collect()
+        } else {
+            Err(ParseErr::new(path, PEType::IncorrectType(keys)))
+        }
+    }
+
+    /// Keys are scalars, since that's all a relation can be written about
+    fn key(key: &'a Value, path: &[&'a Value]) -> Result<&'a Value, ParseErr<'a>> {
+        match key {
+            Value::String(_) | Value::Number(_) | Value::Bool(_) => Ok(key),
+            _ => Err(ParseErr::new(path, PEType::IncorrectType(key))),
+        }
     }
 
     fn field_default(config: &'a Mapping, path: &'b [&'a Value]) -> Result<Option<&'a Value>, ParseErr<'a>> {
@@ -91,7 +309,7 @@ impl<'a, 'b> ObjectConstraintBuilder<'a, 'b> {
         if let Some(val) = config.get(&DEFAULT) {
             match val {
                 Value::Mapping(_) => Ok(Some(val)),
-                _ => Err(ParseErr::new(path, PEType::InvalidDefault(val)))
+                _ => Err(ParseErr::new(path, PEType::InvalidDefault { default: val, failures: vec![] }))
             }
         } else {
             Ok(None)
@@ -104,38 +322,91 @@ impl<'a, 'b> ObjectConstraintBuilder<'a, 'b> {
         path
     }
 
+    #[allow(clippy::wrong_self_convention)]
     fn from_mapping(&self) -> YamlParseResult<'a> {
         lazy_static! {
             static ref FIELDS: Value = valstr!("fields");
+            static ref DISCRIMINATOR: Value = valstr!("discriminator");
+        }
+        if let Some(val) = self.config.get(&DISCRIMINATOR) {
+            return self.discriminator(val, self.config.get(&FIELDS));
         }
         if let Some(val) = self.config.get(&FIELDS) {
             return self.fields(val);
         }
-        let default = ObjectConstraint::default(self.field_name);
-        Constraint::Obj(default).into()
+        self.constraint(ObjConstr::Any)
     }
 
     fn fields(&self, fields: &'a Value) -> YamlParseResult<'a> {
+        match self.field_map(fields) {
+            Ok(map) => {
+                self.constraint(ObjConstr::Fields(map))
+            }
+            Err(e) => e.into(),
+        }
+    }
+
+    fn field_map(&self, fields: &'a Value) -> Result<HashMap<&'a Value, Constraint<'a>>, Vec<ParseErr<'a>>> {
         if let Value::Mapping(f) = fields {
-            let mut path = self.path.to_vec();
-            path.push(self.field_name);
+            let path = self.path();
             let (ok, err): (Vec<_>, Vec<_>) = f.iter()
                 .map(|(k, v)| Constraint::parse(k, v, &path))
                 .partition(|b| b.all(Result::is_ok));
             // see if it was parsed without errors
             if err.is_empty() {
-                let map = ok.into_iter()
+                Ok(ok.into_iter()
                     .flatten()
                     .map(Result::unwrap)
                     .map(|c| (c.field_name(), c))
-                    .collect();
-                let constr = ObjConstr::Fields(map);
-                ObjectConstraint::new(self.field_name, constr, self.default).into()
+                    .collect())
             } else {
-                YamlParseResult::Multi(err.into_iter().flatten().collect())
+                Err(err.into_iter().flatten().filter_map(Result::err).collect())
             }
         } else {
-            ParseErr::new(&self.path(), PEType::IncorrectType(fields)).into()
+            Err(vec![ParseErr::new(&self.path(), PEType::IncorrectType(fields))])
+        }
+    }
+
+    fn discriminator(&self, discriminator: &'a Value, common: Option<&'a Value>) -> YamlParseResult<'a> {
+        lazy_static! {
+            static ref KEY: Value = valstr!("key");
+            static ref VARIANTS: Value = valstr!("variants");
+        }
+        let config = match discriminator {
+            Value::Mapping(m) => m,
+            _ => return ParseErr::new(&self.path(), PEType::IncorrectType(discriminator)).into(),
+        };
+        let key = match config.get(&KEY) {
+            Some(key @ Value::String(_)) => key,
+            Some(key) => return ParseErr::new(&self.path(), PEType::IncorrectType(key)).into(),
+            None => return ParseErr::new(&self.path(), PEType::MissingKey("discriminator.key")).into(),
+        };
+        let variants = match config.get(&VARIANTS) {
+            Some(Value::Mapping(v)) => v,
+            Some(v) => return ParseErr::new(&self.path(), PEType::IncorrectType(v)).into(),
+            None => return ParseErr::new(&self.path(), PEType::MissingKey("discriminator.variants")).into(),
+        };
+        // fields shared by every variant are optional
+        let mut errors = vec![];
+        let fields = match common.map(|c| self.field_map(c)) {
+            Some(Ok(map)) => map,
+            Some(Err(e)) => {
+                errors.extend(e);
+                HashMap::new()
+            }
+            None => HashMap::new(),
+        };
+        let mut parsed = vec![];
+        for (tag, variant) in variants {
+            match self.field_map(variant) {
+                Ok(map) => parsed.push((tag, map)),
+                Err(e) => errors.extend(e),
+            }
+        }
+        if errors.is_empty() {
+            self.constraint(ObjConstr::Discriminated { key, fields, variants: parsed })
+        } else {
+            errors.into()
         }
     }
 }
@@ -147,9 +418,18 @@ pub fn build<'a>(field_name: &'a Value, config: &'a Mapping, path: &[&'a Value])
     }
 }
 
+/// The rules for a variant are resolved up front like any other rule, but a
+/// resolution failure is only reported if the input actually selects it
+pub type VariantRules<'a> = Result<HashMap<&'a Value, Rule<'a>>, Box<ValueResolutionResult<'a>>>;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ObjRule<'a> {
     Fields(HashMap<&'a Value, Rule<'a>>),
+    Discriminated {
+        key: &'a Value,
+        fields: HashMap<&'a Value, Rule<'a>>,
+        variants: Vec<(&'a Value, VariantRules<'a>)>,
+    },
     Any,
 }
 
@@ -158,32 +438,61 @@ pub struct ObjectRule<'a> {
     pub field_name: &'a Value,
     rule: ObjRule<'a>,
     pub default: Option<&'a Value>,
+    pub options: FieldOptions,
+    relations: Vec<KeyRelation<'a>>,
+    /// assertions are checked against the whole document while rules are being
+    /// resolved, so all that's left is the outcome
+    assertions: Vec<(&'a str, Result<bool, EvalErr>)>,
+    additional_keys: bool,
 }
 
 impl<'a> ObjectRule<'a> {
     pub fn resolve(constraint: ObjectConstraint<'a>, root: &'a Value, context: &Constraint<'a>) -> ValueResolutionResult<'a> {
-        match constraint.constr {
+        let rule = match constraint.constr {
             ObjConstr::Fields(constraints) => {
-                let (ok, err): (Vec<_>, Vec<_>) = constraints.into_iter()
-                    .map(|(_, c)| Rule::new(c, root, context))
-                    .partition(|b| b.all(Result::is_ok));
-                if err.is_empty() {
-                    let map = ok.into_iter()
-                        .flatten()
-                        .map(Result::unwrap)
-                        .map(|r| (r.field_name(), r))
-                        .collect();
-                    let rule = ObjRule::Fields(map);
-                    let object_rule = ObjectRule { field_name: constraint.field_name, rule, default: constraint.default };
-                    Bubble::Single(Ok(Rule::Obj(object_rule)))
-                } else {
-                    Bubble::Multi(err.into_iter().flatten().collect())
+                match ObjectRule::resolve_fields(constraints, root, context) {
+                    Ok(map) => ObjRule::Fields(map),
+                    Err(e) => return *e,
                 }
             }
-            ObjConstr::Any => {
-                let object_rule = ObjectRule { field_name: constraint.field_name, rule: ObjRule::Any, default: constraint.default };
-                Bubble::Single(Ok(Rule::Obj(object_rule)))
-            },
+            ObjConstr::Discriminated { key, fields, variants } => {
+                let fields = match ObjectRule::resolve_fields(fields, root, context) {
+                    Ok(map) => map,
+                    Err(e) => return *e,
+                };
+                let variants = variants.into_iter()
+                    .map(|(tag, constraints)| (tag, ObjectRule::resolve_fields(constraints, root, context)))
+                    .collect();
+                ObjRule::Discriminated { key, fields, variants }
+            }
+            ObjConstr::Any => ObjRule::Any,
+        };
+        let object_rule = ObjectRule {
+            field_name: constraint.field_name,
+            rule,
+            default: constraint.default,
+            options: constraint.options,
+            relations: constraint.relations,
+            assertions: constraint.assertions.iter()
+                .map(|a| (a.source, a.check(root, context)))
+                .collect(),
+            additional_keys: constraint.additional_keys,
+        };
+        Bubble::Single(Ok(Rule::Obj(object_rule)))
+    }
+
+    fn resolve_fields(constraints: HashMap<&'a Value, Constraint<'a>>, root: &'a Value, context: &Constraint<'a>) -> VariantRules<'a> {
+        let (ok, err): (Vec<_>, Vec<_>) = constraints.into_values()
+            .map(|c| Rule::new(c, root, context))
+            .partition(|b| b.all(Result::is_ok));
+        if err.is_empty() {
+            Ok(ok.into_iter()
+                .flatten()
+                .map(Result::unwrap)
+                .map(|r| (r.field_name(), r))
+                .collect())
+        } else {
+            Err(Box::new(Bubble::Multi(err.into_iter().flatten().collect())))
         }
     }
 
@@ -191,30 +500,139 @@ impl<'a> ObjectRule<'a> {
         // this doesn't work for the very top level of rules
         // that evaluation is treated as a special case and done in a separate loop
         if let Value::Mapping(mapping) = value {
-            match self.rule {
+            let unknown = match self.additional_keys {
+                true => vec![],
+                false => self.unknown_keys(mapping, path),
+            };
+            let results = match self.rule {
                 ObjRule::Fields(rules) => {
                     let results: Vec<_> = rules.into_iter()
                         .map(|(key, rule)| ObjectRule::subrule(key, rule, mapping, path))
                         .collect();
                     results.into()
                 }
+                ObjRule::Discriminated { key, fields, variants } => {
+                    ObjectRule::eval_variant(key, fields, variants, mapping, path)
+                }
                 ObjRule::Any => {
                     RuleEvalSuccess::new(true, path).into()
                 }
+            };
+            if self.relations.is_empty() && self.assertions.is_empty() && unknown.is_empty() {
+                return results;
             }
+            let relations = self.relations.iter().map(|r| r.eval(mapping, path));
+            let assertions = self.assertions.into_iter().map(|(source, outcome)| match outcome {
+                Ok(true) => RuleEvalSuccess::new(true, path).into(),
+                Ok(false) => RuleEvalErr::new(path, RuleErrType::AssertionFailed(source)).into(),
+                Err(err) => RuleEvalErr::new(path, RuleErrType::Assertion { source, err }).into(),
+            });
+            std::iter::once(results).chain(relations).chain(assertions).chain(unknown).collect::<Vec<_>>().into()
         } else {
             RuleEvalErr::new(path, RuleErrType::IncorrectType(value)).into()
         }
     }
 
+    /// Keys that no field accounts for, under its current name or an old one.
+    /// If the input doesn't select a variant, that's the only error reported.
+    fn unknown_keys(&self, input: &'a Mapping, path: &[&'a Value]) -> Vec<RuleEvalResult<'a>> {
+        let (mut valid, rules): (Vec<&'a Value>, Vec<&Rule<'a>>) = match &self.rule {
+            ObjRule::Fields(rules) => (rules.keys().copied().collect(), rules.values().collect()),
+            ObjRule::Discriminated { key, fields, variants } => {
+                let selected = input.get(key).and_then(|tag| variants.iter().find(|(t, _)| *t == tag));
+                let variant = match selected {
+                    Some((_, Ok(rules))) => rules,
+                    _ => return vec![],
+                };
+                let all = || fields.iter().chain(variant.iter());
+                (std::iter::once(*key).chain(all().map(|(k, _)| *k)).collect(), all().map(|(_, r)| r).collect())
+            }
+            ObjRule::Any => (vec![], vec![]),
+        };
+        valid.sort_by_key(|k| display_value(k));
+        input.iter().map(|(k, _)| k)
+            .filter(|k| !valid.contains(k) && !rules.iter().any(|r| r.options().renamed_from.contains(k)))
+            .map(|key| RuleEvalErr::new(path, RuleErrType::UnknownKey { key, valid: valid.clone() }).into())
+            .collect()
+    }
+
+    // every rule fails with a `RuleEvalErr`, which is a little over the size
+    // clippy likes to see in a `Result`
+    #[allow(clippy::result_large_err)]
+    fn eval_variant(
+        key: &'a Value,
+        fields: HashMap<&'a Value, Rule<'a>>,
+        variants: Vec<(&'a Value, VariantRules<'a>)>,
+        input: &'a Mapping,
+        path: &[&'a Value],
+    ) -> RuleEvalResult<'a> {
+        let valid: Vec<_> = variants.iter().map(|(tag, _)| *tag).collect();
+        let found = match input.get(key) {
+            Some(found) => found,
+            None => return RuleEvalErr::new(path, RuleErrType::MissingVariant { key, valid }).into(),
+        };
+        let (variant, rules) = match variants.into_iter().find(|(tag, _)| *tag == found) {
+            Some(selected) => selected,
+            None => return RuleEvalErr::new(path, RuleErrType::UnknownVariant { key, found, valid }).into(),
+        };
+        match rules {
+            Ok(rules) => {
+                let results: Vec<_> = fields.into_iter()
+                    .chain(rules)
+                    .map(|(key, rule)| ObjectRule::subrule(key, rule, input, path))
+                    .collect();
+                let results: Vec<_> = RuleEvalResult::from(results).into_iter()
+                    .map(|res| res.map_err(|e| e.in_variant(variant)))
+                    .collect();
+                results.into()
+            }
+            Err(unresolved) => {
+                RuleEvalErr::new(path, RuleErrType::Resolution(unresolved)).in_variant(variant).into()
+            }
+        }
+    }
+
     pub fn subrule(key: &'a Value, rule: Rule<'a>, input: &'a Mapping, path: &[&'a Value]) -> RuleEvalResult<'a> {
-        if let Some(value) = input.get(key) {
-            return rule.eval(value, path);
+        let mut field_path = path.to_vec();
+        field_path.push(key);
+        // migration warnings are reported next to whatever the value itself gets
+        let mut warnings = vec![];
+        let renamed = input.iter().find(|(k, _)| rule.options().renamed_from.contains(k));
+        if let Some((old, _)) = renamed {
+            let mut old_path = path.to_vec();
+            old_path.push(old);
+            let warning = RuleEvalSuccess::failed(&old_path, Reason::Renamed { from: old, to: key });
+            warnings.push(Ok(warning.with_severity(Severity::Warning)));
         }
-        if let Some(value) = rule.default() {
-            return rule.eval(&value, path);
+        // the new key wins if somebody has both
+        let value = input.get(key).or_else(|| renamed.map(|(_, v)| v));
+        if let (Some(hint), Some(_)) = (&rule.options().deprecated, value) {
+            let warning = RuleEvalSuccess::failed(&field_path, Reason::Deprecated(hint.clone()));
+            warnings.push(Ok(warning.with_severity(Severity::Warning)));
+        }
+        if let Some((found, replacement)) = value.and_then(|v| rule.options().replacement(v).map(|r| (v, r))) {
+            let reason = Reason::DeprecatedValue { found, replacement: replacement.clone() };
+            let warning = RuleEvalSuccess::failed(&field_path, reason);
+            warnings.push(Ok(warning.with_severity(Severity::Warning)));
+        }
+        let results = if let Some(value) = value {
+            rule.eval(value, path)
+        } else if let Some(value) = rule.default() {
+            rule.eval(value, path)
+        } else if !rule.options().required {
+            // there's nothing to check if an optional field is left out
+            Bubble::Multi(vec![])
+        } else {
+            let diagnostic = rule.options().diagnostic(None, &field_path);
+            RuleEvalErr::new(path, RuleErrType::KeyNotFound(key))
+                .with_diagnostic(diagnostic)
+                .with_severity(rule.options().severity)
+                .into()
+        };
+        match warnings.is_empty() {
+            true => results,
+            false => Bubble::Multi(results.into_iter().chain(warnings).collect()),
         }
-        RuleEvalErr::new(path, RuleErrType::KeyNotFound(key)).into()
     }
 }
 
@@ -264,7 +682,7 @@ mod tests {
         // parse yaml and validate
         let config: Mapping = serde_yaml::from_str(raw).unwrap();
         let name = valstr!("f");
-        let res = build(&name, &config, &vec![]);
+        let res = build(&name, &config, &[]);
         if let YamlParseResult::Single(Ok(Constraint::Obj(obj))) = res {
             assert_eq!(&String::from("f"), obj.field_name);
             assert_eq!(expected, obj);
@@ -285,18 +703,77 @@ mod tests {
 
         let config: Mapping = serde_yaml::from_str(raw).unwrap();
         let name = valstr!("parent");
-        let results = build(&name, &config, &vec![]).get();
+        let results = build(&name, &config, &[]).get();
         assert_eq!(results.len(), 1);
 
         let pe = results.into_iter().next()
             .expect("expected one error")
             .expect_err("First entry should be an error");
         let (p1, p2) = (valstr!("parent"), valstr!("hello"));
-        let expected = ParseErr::new(&vec![&p1, &p2], PEType::UnknownType("stringerino"));
+        let expected = ParseErr::new(&[&p1, &p2], PEType::UnknownType("stringerino"));
         assert_eq!(expected, pe);
     }
 
     #[test]
+    fn discriminator_without_variants() {
+        let raw = concat!(
+            "type: object\n",
+            "discriminator:\n",
+            "  key: kind\n",
+        );
+
+        let config: Mapping = serde_yaml::from_str(raw).unwrap();
+        let name = valstr!("storage");
+        let pe = build(&name, &config, &[]).get().into_iter().next()
+            .expect("expected one error")
+            .expect_err("First entry should be an error");
+        assert_eq!(ParseErr::new(&[&name], PEType::MissingKey("discriminator.variants")), pe);
+    }
+
+    #[test]
+    fn relation_keys_are_scalars() {
+        let raw = concat!(
+            "type: object\n",
+            "depends_on:\n",
+            "  cert: {path: string}\n",
+        );
+
+        let config: Mapping = serde_yaml::from_str(raw).unwrap();
+        let name = valstr!("tls");
+        let pe = build(&name, &config, &[]).get().into_iter().next()
+            .expect("expected one error")
+            .expect_err("First entry should be an error");
+        let required: Value = serde_yaml::from_str("path: string").unwrap();
+        assert_eq!(ParseErr::new(&[&name], PEType::IncorrectType(&required)), pe);
+    }
+
+    #[test]
+    fn closed_object_relations_name_declared_keys() {
+        let raw = concat!(
+            "type: object\n",
+            "additional_keys: false\n",
+            "exclusive: [pasword, token]\n",
+            "fields:\n",
+            "  password: string\n",
+            "  token: string\n",
+        );
+
+        let mut config: Mapping = serde_yaml::from_str(raw).unwrap();
+        let name = valstr!("auth");
+        let typo = valstr!("pasword");
+        let pe = build(&name, &config, &[]).get().into_iter().next()
+            .expect("expected one error")
+            .expect_err("First entry should be an error");
+        assert_eq!(ParseErr::new(&[&name], PEType::UndeclaredKey(&typo)), pe);
+        assert_eq!("pasword isn't one of the fields, and no other keys are allowed", pe.err.to_string());
+
+        // an open object may still have the key
+        config.insert(valstr!("additional_keys"), Value::Bool(true));
+        assert!(build(&name, &config, &[]).all(Result::is_ok));
+    }
+
+    #[test]
+    #[allow(clippy::invalid_regex)]
     fn obj_constr_multiple_invalid() {
         let raw = concat!(
             "type: object\n",
@@ -309,18 +786,37 @@ mod tests {
 
         let config: Mapping = serde_yaml::from_str(raw).unwrap();
         let name = valstr!("parent");
-        let results = build(&name, &config, &vec![]).get();
+        let results = build(&name, &config, &[]).get();
         assert_eq!(results.len(), 2);
 
         let (p1, p2) = (valstr!("parent"), valstr!("hello"));
-        let hello_type_error = ParseErr::new(&vec![&p1, &p2], PEType::UnknownType("stringerino"));
+        let hello_type_error = ParseErr::new(&[&p1, &p2], PEType::UnknownType("stringerino"));
         assert!(results.contains(&Err(hello_type_error)));
 
         let (p1, p2) = (valstr!("parent"), valstr!("world"));
-        let world_regex_error = ParseErr::new(&vec![&p1, &p2], Regex::new("^\\d{{{{$").unwrap_err().into());
+        let world_regex_error = ParseErr::new(&[&p1, &p2], Regex::new("^\\d{{{{$").unwrap_err().into());
         assert!(results.contains(&Err(world_regex_error)));
     }
 
+    #[test]
+    fn nested_invalid_path() {
+        let raw = concat!(
+            "type: object\n",
+            "fields:\n",
+            "  nested:\n",
+            "    type: object\n",
+            "    fields:\n",
+            "      hello: stringerino\n",
+        );
+
+        let config: Value = serde_yaml::from_str(raw).unwrap();
+        let name = valstr!("parent");
+        let results = Constraint::parse(&name, &config, &[]).get();
+        let (p1, p2, p3) = (valstr!("parent"), valstr!("nested"), valstr!("hello"));
+        let expected = ParseErr::new(&[&p1, &p2, &p3], PEType::UnknownType("stringerino"));
+        assert_eq!(results, vec![Err(expected)]);
+    }
+
     #[test]
     fn obj_empty_fields() {
         let raw = concat!(
@@ -330,13 +826,13 @@ mod tests {
         
         let config: Mapping = serde_yaml::from_str(raw).unwrap();
         let name = valstr!("parent");
-        let results = build(&name, &config, &vec![]).get();
+        let results = build(&name, &config, &[]).get();
         assert_eq!(results.len(), 1);
 
         let pe = results.into_iter().next()
             .expect("expected one error")
             .expect_err("First entry should be an error");
-        let expected = ParseErr::new(&vec![&name], PEType::IncorrectType(&Value::Null));
+        let expected = ParseErr::new(&[&name], PEType::IncorrectType(&Value::Null));
         assert_eq!(expected, pe);
     }
 
@@ -362,6 +858,10 @@ mod tests {
             field_name: &obj_name,
             constr: ObjConstr::Fields(map),
             default: None,
+            options: FieldOptions::default(),
+            relations: vec![],
+            assertions: vec![],
+            additional_keys: true,
         };
         // fetch a value that exists
         let vals = [valstr!("foo")];
@@ -382,7 +882,7 @@ mod tests {
         let res = parent.constraint(&path);
         assert_eq!(res, Err(DefaultFetchErr::IncorrectType {
             residual_path: valpath!["foo", "bar"], 
-            constr: Constraint::Str(StringConstraint::default(&valstr!("foo")))
+            constr: Box::new(Constraint::Str(StringConstraint::default(&valstr!("foo"))))
         }));
     }
 
@@ -399,6 +899,10 @@ mod tests {
             field_name: &inner_name,
             constr: ObjConstr::Fields(map),
             default: None,
+            options: FieldOptions::default(),
+            relations: vec![],
+            assertions: vec![],
+            additional_keys: true,
         };
         let inner_constr = Constraint::Obj(inner);
         // save this for later
@@ -411,6 +915,10 @@ mod tests {
             field_name: &outer_name,
             constr: ObjConstr::Fields(map),
             default: None,
+            options: FieldOptions::default(),
+            relations: vec![],
+            assertions: vec![],
+            additional_keys: true,
         };
         // fetch foo from the nested structure
         let vals = [valstr!("inner"), valstr!("foo")];
@@ -436,7 +944,7 @@ mod tests {
         // we only get the residual path here too
         assert_eq!(res, Err(DefaultFetchErr::IncorrectType {
             residual_path: valpath!["foo", "bar"], 
-            constr: Constraint::Str(StringConstraint::default(&valstr!("foo")))
+            constr: Box::new(Constraint::Str(StringConstraint::default(&valstr!("foo"))))
         }));
     }
 
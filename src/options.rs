@@ -0,0 +1,171 @@
+use std::fmt;
+
+use lazy_static::lazy_static;
+use serde_yaml::{Mapping, Value};
+
+use crate::{parse::{PEType, ParseErr}, valstr};
+
+/// Settings that every field accepts, no matter what its type is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldOptions {
+    pub required: bool,
+    /// shown instead of the built-in failure, with `{value}` and `{path}` filled in
+    pub message: Option<String>,
+    pub code: Option<String>,
+    pub severity: Severity,
+    /// a migration hint, e.g. "use `replicas` instead"
+    pub deprecated: Option<String>,
+    /// keys the field used to go by, which are still accepted
+    pub renamed_from: Vec<Value>,
+    /// values that are still accepted but have a replacement, old to new
+    pub deprecated_values: Vec<(Value, Value)>,
+    /// what the field is for, which only ends up in generated docs
+    pub description: Option<String>,
+}
+
+impl Default for FieldOptions {
+    fn default() -> Self {
+        FieldOptions {
+            required: true,
+            message: None,
+            code: None,
+            severity: Severity::Error,
+            deprecated: None,
+            renamed_from: vec![],
+            deprecated_values: vec![],
+            description: None,
+        }
+    }
+}
+
+/// How much a failure matters. Only errors should make a caller reject the
+/// input, which lets new rules start out as warnings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Info,
+    Warning,
+    #[default]
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// The spec author's own description of a failure
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub code: Option<String>,
+    pub message: Option<String>,
+}
+
+impl FieldOptions {
+    pub fn parse<'a>(config: &'a Mapping, path: &[&'a Value]) -> Result<FieldOptions, ParseErr<'a>> {
+        lazy_static! {
+            static ref REQUIRED: Value = valstr!("required");
+            static ref MESSAGE: Value = valstr!("message");
+            static ref CODE: Value = valstr!("code");
+            static ref SEVERITY: Value = valstr!("severity");
+            static ref DEPRECATED: Value = valstr!("deprecated");
+            static ref RENAMED_FROM: Value = valstr!("renamed_from");
+            static ref DEPRECATED_VALUES: Value = valstr!("deprecated_values");
+            static ref DESCRIPTION: Value = valstr!("description");
+        }
+        let required = match config.get(&REQUIRED) {
+            Some(Value::Bool(b)) => *b,
+            Some(val) => return Err(ParseErr::new(path, PEType::IncorrectType(val))),
+            None => true,
+        };
+        let text = |key: &Value| match config.get(key) {
+            Some(Value::String(s)) => Ok(Some(s.clone())),
+            Some(val) => Err(ParseErr::new(path, PEType::IncorrectType(val))),
+            None => Ok(None),
+        };
+        let severity = match config.get(&SEVERITY) {
+            None => Severity::Error,
+            Some(Value::String(s)) if s == "error" => Severity::Error,
+            Some(Value::String(s)) if s == "warning" => Severity::Warning,
+            Some(Value::String(s)) if s == "info" => Severity::Info,
+            Some(val) => return Err(ParseErr::new(path, PEType::IncorrectType(val))),
+        };
+        let renamed_from = match config.get(&RENAMED_FROM) {
+            None => vec![],
+            Some(key @ Value::String(_)) => vec![key.clone()],
+            Some(Value::Sequence(keys)) => keys.clone(),
+            Some(val) => return Err(ParseErr::new(path, PEType::IncorrectType(val))),
+        };
+        let deprecated_values = match config.get(&DEPRECATED_VALUES) {
+            None => vec![],
+            Some(Value::Mapping(m)) => m.iter().map(|(old, new)| (old.clone(), new.clone())).collect(),
+            Some(val) => return Err(ParseErr::new(path, PEType::IncorrectType(val))),
+        };
+        Ok(FieldOptions {
+            required,
+            message: text(&MESSAGE)?,
+            code: text(&CODE)?,
+            severity,
+            deprecated: text(&DEPRECATED)?,
+            renamed_from,
+            deprecated_values,
+            description: text(&DESCRIPTION)?,
+        })
+    }
+
+    /// What a deprecated value should be replaced with
+    pub fn replacement(&self, value: &Value) -> Option<&Value> {
+        self.deprecated_values.iter().find(|(old, _)| old == value).map(|(_, new)| new)
+    }
+
+    /// `value` is `None` when the field is missing altogether
+    pub fn diagnostic(&self, value: Option<&Value>, path: &[&Value]) -> Option<Diagnostic> {
+        if self.message.is_none() && self.code.is_none() {
+            return None;
+        }
+        let message = self.message.as_ref().map(|m| {
+            m.replace("{value}", &value.map(display_value).unwrap_or_default())
+                .replace("{path}", &display_path(path))
+        });
+        Some(Diagnostic { code: self.code.clone(), message })
+    }
+}
+
+/// Paths are shown relative to the document, so the root's own name is left out
+pub fn display_path(path: &[&Value]) -> String {
+    let segments: Vec<_> = path.iter().skip(1).map(|v| display_value(v)).collect();
+    segments.join(".")
+}
+
+/// What kind of YAML value something is, for saying it's the wrong kind
+pub fn value_kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Sequence(_) => "sequence",
+        Value::Mapping(_) => "mapping",
+    }
+}
+
+/// Field names or listed values, e.g. `a.b.c` or `tcp, udp`
+pub fn join(values: &[&Value], separator: &str) -> String {
+    values.iter().map(|v| display_value(v)).collect::<Vec<_>>().join(separator)
+}
+
+pub fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Null => String::from("null"),
+        other => serde_yaml::to_string(other)
+            .map(|s| s.trim_start_matches("---").trim().to_string())
+            .unwrap_or_default(),
+    }
+}
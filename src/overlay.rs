@@ -0,0 +1,238 @@
+use std::{fmt, slice};
+
+use lazy_static::lazy_static;
+use serde_yaml::{Mapping, Value};
+
+use crate::{options::display_value, valstr};
+
+lazy_static! {
+    static ref ENVIRONMENTS: Value = valstr!("environments");
+    static ref ALLOWED: Value = valstr!("allowed");
+    static ref FIELDS: Value = valstr!("fields");
+    static ref DISCRIMINATOR: Value = valstr!("discriminator");
+    static ref VARIANTS: Value = valstr!("variants");
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnvErrKind {
+    /// `environments` has to map environment names to the keys they change
+    InvalidEnvironments(Value),
+    /// a refinement can only narrow `allowed`, these values aren't in the base list
+    Widened(Vec<Value>),
+    UnknownEnvironment { name: String, known: Vec<String> },
+}
+
+/// A problem with a spec's environments. The path is made of field names,
+/// like a `ParseErr`'s, and is empty for an environment no field mentions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvErr {
+    pub path: Vec<Value>,
+    pub kind: EnvErrKind,
+}
+
+impl fmt::Display for EnvErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.path.is_empty() {
+            let path: Vec<_> = self.path.iter().map(display_value).collect();
+            write!(f, "{}: ", path.join("."))?;
+        }
+        match &self.kind {
+            EnvErrKind::InvalidEnvironments(v) => write!(f, "{} doesn't map environment names to refinements", display_value(v)),
+            EnvErrKind::Widened(values) => {
+                let values: Vec<_> = values.iter().map(display_value).collect();
+                write!(f, "{} can't be allowed, since the base grammar doesn't allow it", values.join(", "))
+            }
+            EnvErrKind::UnknownEnvironment { name, known } if known.is_empty() => write!(f, "the spec has no environments, so it has no {}", name),
+            EnvErrKind::UnknownEnvironment { name, known } => write!(f, "{} isn't one of the spec's environments: {}", name, known.join(", ")),
+        }
+    }
+}
+
+/// The spec as it applies in one environment. Any field can refine itself for
+/// an environment with `environments: { prod: { allowed: [...], required: true } }`,
+/// whose keys replace the field's own. Without an environment, that's the
+/// base grammar, and every refinement is dropped.
+pub fn environment(spec: &Mapping, env: Option<&str>) -> Result<Mapping, Vec<EnvErr>> {
+    let mut refinements = Refinements { env, known: vec![], err: vec![] };
+    let selected = spec.iter()
+        .map(|(name, definition)| (name.clone(), refinements.refine(definition, slice::from_ref(name))))
+        .collect();
+    if let Some(name) = env {
+        if !refinements.known.iter().any(|known| known == name) {
+            let kind = EnvErrKind::UnknownEnvironment { name: String::from(name), known: refinements.known.clone() };
+            refinements.err.push(EnvErr { path: vec![], kind });
+        }
+    }
+    match refinements.err.is_empty() {
+        true => Ok(selected),
+        false => Err(refinements.err),
+    }
+}
+
+struct Refinements<'e> {
+    env: Option<&'e str>,
+    /// every environment the spec mentions, in the order they come up
+    known: Vec<String>,
+    err: Vec<EnvErr>,
+}
+
+impl Refinements<'_> {
+    fn refine(&mut self, definition: &Value, path: &[Value]) -> Value {
+        let mut config = match definition {
+            Value::Mapping(m) => m.clone(),
+            other => return other.clone(),
+        };
+        if let Some(environments) = config.remove(&ENVIRONMENTS) {
+            match self.refinement(&environments) {
+                Some(Some(refinement)) => self.apply(&mut config, refinement, path),
+                Some(None) => {}
+                None => self.err.push(EnvErr { path: path.to_vec(), kind: EnvErrKind::InvalidEnvironments(environments.clone()) }),
+            }
+        }
+
+        // a refinement can bring in fields of its own, so these come after
+        if let Some(Value::Mapping(fields)) = config.get_mut(&FIELDS) {
+            self.refine_fields(fields, path);
+        }
+        if let Some(Value::Mapping(discriminator)) = config.get_mut(&DISCRIMINATOR) {
+            if let Some(Value::Mapping(variants)) = discriminator.get_mut(&VARIANTS) {
+                for (_, variant) in variants.iter_mut() {
+                    if let Value::Mapping(fields) = variant {
+                        self.refine_fields(fields, path);
+                    }
+                }
+            }
+        }
+        Value::Mapping(config)
+    }
+
+    fn refine_fields(&mut self, fields: &mut Mapping, path: &[Value]) {
+        *fields = fields.iter()
+            .map(|(name, field)| {
+                let mut field_path = path.to_vec();
+                field_path.push(name.clone());
+                (name.clone(), self.refine(field, &field_path))
+            })
+            .collect();
+    }
+
+    /// The selected environment's refinement, or `None` if `environments`
+    /// isn't a mapping of names to refinements
+    fn refinement<'v>(&mut self, environments: &'v Value) -> Option<Option<&'v Mapping>> {
+        let environments = environments.as_mapping()?;
+        let mut selected = None;
+        for (name, refinement) in environments {
+            let (name, refinement) = match (name, refinement) {
+                (Value::String(name), Value::Mapping(refinement)) => (name, refinement),
+                _ => return None,
+            };
+            if !self.known.contains(name) {
+                self.known.push(name.clone());
+            }
+            if self.env == Some(name.as_str()) {
+                selected = Some(refinement);
+            }
+        }
+        Some(selected)
+    }
+
+    fn apply(&mut self, config: &mut Mapping, refinement: &Mapping, path: &[Value]) {
+        for (key, value) in refinement {
+            if let (true, Some(Value::Sequence(base)), Value::Sequence(narrowed)) = (*key == *ALLOWED, config.get(key), value) {
+                let widened: Vec<_> = narrowed.iter().filter(|v| !base.contains(v)).cloned().collect();
+                if !widened.is_empty() {
+                    self.err.push(EnvErr { path: path.to_vec(), kind: EnvErrKind::Widened(widened) });
+                }
+            }
+            config.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+/// One of the documents an input is put together from, like `base.yaml` or
+/// `prod.yaml`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub value: Value,
+}
+
+/// The input the layers make up, and which layer each of its values came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layered {
+    pub value: Value,
+    names: Vec<String>,
+    /// the layer that last set each value, by its path in the document
+    origins: Vec<(Vec<Value>, usize)>,
+}
+
+impl Layered {
+    /// The layer that contributed the value at an evaluation's path, which
+    /// starts with the name the document was evaluated under. A missing field
+    /// is traced to the layer its parent came from.
+    pub fn layer_of(&self, path: &[&Value]) -> Option<&str> {
+        let path = path.get(1..).unwrap_or(&[]);
+        self.origins.iter()
+            .filter(|(prefix, _)| prefix.len() <= path.len() && prefix.iter().zip(path).all(|(a, b)| a == *b))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, layer)| self.names[*layer].as_str())
+    }
+}
+
+/// Merges layers the way most config loaders do: later layers win, mappings
+/// are merged key by key, and anything else, lists included, is replaced
+/// whole.
+pub fn merge(layers: &[Layer]) -> Layered {
+    let mut layered = Layered { value: Value::Null, names: vec![], origins: vec![] };
+    for (index, layer) in layers.iter().enumerate() {
+        layered.names.push(layer.name.clone());
+        overlay(&mut layered.value, &layer.value, &mut vec![], index, &mut layered.origins);
+    }
+    layered
+}
+
+fn overlay(into: &mut Value, from: &Value, path: &mut Vec<Value>, layer: usize, origins: &mut Vec<(Vec<Value>, usize)>) {
+    match (into, from) {
+        (Value::Mapping(into), Value::Mapping(from)) => {
+            for (key, value) in from {
+                path.push(key.clone());
+                match into.get_mut(key) {
+                    Some(existing) => overlay(existing, value, path, layer, origins),
+                    None => {
+                        into.insert(key.clone(), value.clone());
+                        set(origins, path, layer);
+                    }
+                }
+                path.pop();
+            }
+        }
+        (into, from) => {
+            *into = from.clone();
+            set(origins, path, layer);
+        }
+    }
+}
+
+/// Whatever was under the path before came from a layer that's been overridden
+fn set(origins: &mut Vec<(Vec<Value>, usize)>, path: &[Value], layer: usize) {
+    origins.retain(|(prefix, _)| !prefix.starts_with(path));
+    origins.push((path.to_vec(), layer));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_layers_replace_lists() {
+        let layer = |name: &str, raw: &str| Layer { name: String::from(name), value: serde_yaml::from_str(raw).unwrap() };
+        let layered = merge(&[
+            layer("base.yaml", "hosts: [a, b]\nport: 80\n"),
+            layer("prod.yaml", "hosts: [c]\n"),
+        ]);
+        assert_eq!(serde_yaml::from_str::<Value>("hosts: [c]\nport: 80\n").unwrap(), layered.value);
+        let (root, hosts, port) = (valstr!("."), valstr!("hosts"), valstr!("port"));
+        assert_eq!(Some("prod.yaml"), layered.layer_of(&[&root, &hosts, &Value::from(0)]));
+        assert_eq!(Some("base.yaml"), layered.layer_of(&[&root, &port]));
+    }
+}
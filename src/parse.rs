@@ -1,5 +1,7 @@
+use std::fmt;
+
 use serde_yaml::Value;
-use crate::{bubble::Bubble, constraint::Constraint};
+use crate::{bubble::Bubble, constraint::Constraint, expr::ExprErr, options::{display_value, join, value_kind}, quantity::QuantityErr, rule::{RuleEvalErr, RuleEvalSuccess}, temporal::TemporalErr};
 
 #[macro_export]
 macro_rules! valstr {
@@ -12,12 +14,53 @@ macro_rules! valstr {
 #[derive(Debug, PartialEq)]
 pub enum PEType<'a> {
     Unsupported,
+    /// a setting the definition can't do without, named by where it goes,
+    /// e.g. `discriminator.key`
+    MissingKey(&'static str),
+    /// an `else` on a clause that other clauses come after, which would keep
+    /// them from ever being tried
+    MisplacedElse,
     UnknownType(&'a str),
+    UnknownFormat(&'a str),
     InvalidTypeInfo(&'a Value),
     IncorrectType(&'a Value),
     Regex(regex::Error),
-    InvalidDefault(&'a Value),
+    RegexSizeLimit {
+        source: &'a str,
+        limit: usize,
+    },
+    /// A default that doesn't satisfy its own field. There are no failures if
+    /// it isn't even the right kind of value for the field.
+    InvalidDefault {
+        default: &'a Value,
+        failures: Vec<Result<RuleEvalSuccess<'a>, RuleEvalErr<'a>>>,
+    },
     InvalidAbsolutePath(&'a Value),
+    /// a reference to a field that isn't in the spec
+    DanglingRef(Vec<&'a Value>),
+    /// a key that `exclusive`, `one_required` or `depends_on` name, but that
+    /// an object without `additional_keys` doesn't declare
+    UndeclaredKey(&'a Value),
+    RefTypeMismatch {
+        target: Vec<&'a Value>,
+        expected: String,
+        found: &'static str,
+    },
+    /// fields that end up referring back to themselves, in the order they refer
+    /// to each other
+    RefCycle(Vec<Vec<&'a Value>>),
+    Expression {
+        source: &'a str,
+        err: ExprErr,
+    },
+    Quantity {
+        source: &'a str,
+        err: QuantityErr,
+    },
+    Temporal {
+        source: &'a str,
+        err: TemporalErr,
+    },
 }
 
 #[derive(Debug, PartialEq)]
@@ -32,6 +75,73 @@ impl<'a> ParseErr<'a> {
     }
 }
 
+impl<'a> From<Vec<ParseErr<'a>>> for YamlParseResult<'a> {
+    fn from(errors: Vec<ParseErr<'a>>) -> Self {
+        YamlParseResult::Multi(errors.into_iter().map(Err).collect())
+    }
+}
+
+/// Scalars are shown as they are, anything bigger just by its kind
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Sequence(_) | Value::Mapping(_) => format!("a {}", value_kind(value)),
+        _ => display_value(value),
+    }
+}
+
+impl fmt::Display for PEType<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PEType::Unsupported => write!(f, "a field has to be a type name or a mapping"),
+            PEType::MissingKey(key) => write!(f, "{} is missing", key),
+            PEType::MisplacedElse => write!(f, "only the last `when` clause can have an `else`"),
+            PEType::UnknownType(t) => write!(f, "unknown type {}", t),
+            PEType::UnknownFormat(format) => write!(f, "unknown format {}", format),
+            PEType::InvalidTypeInfo(_) => write!(f, "`type` is missing or isn't a string"),
+            PEType::IncorrectType(value) => write!(f, "{} isn't valid here", describe(value)),
+            PEType::Regex(err) => write!(f, "{}", err),
+            PEType::RegexSizeLimit { source, limit } => write!(f, "{:?} compiles to more than {} bytes", source, limit),
+            PEType::InvalidDefault { default, failures } => {
+                write!(f, "the default {} isn't valid", describe(default))?;
+                let reasons: Vec<_> = failures.iter()
+                    .filter_map(|failure| match failure {
+                        Ok(s) => s.reason.as_ref().map(ToString::to_string),
+                        Err(e) => Some(e.err.to_string()),
+                    })
+                    .collect();
+                if !reasons.is_empty() {
+                    write!(f, ": {}", reasons.join("; "))?;
+                }
+                Ok(())
+            }
+            PEType::InvalidAbsolutePath(segment) => write!(f, "{} can't be part of a path", describe(segment)),
+            PEType::DanglingRef(target) => write!(f, "refers to {}, which isn't in the spec", join(target, ".")),
+            PEType::UndeclaredKey(key) => write!(f, "{} isn't one of the fields, and no other keys are allowed", describe(key)),
+            PEType::RefTypeMismatch { target, expected, found } => {
+                write!(f, "refers to {}, which is a {} field rather than a {}", join(target, "."), found, expected)
+            }
+            PEType::RefCycle(cycle) => {
+                let mut fields: Vec<_> = cycle.iter().map(|path| join(path, ".")).collect();
+                fields.extend(fields.first().cloned());
+                write!(f, "refers back to itself through {}", fields.join(" -> "))
+            }
+            PEType::Expression { source, err } => write!(f, "{:?}: {}", source, err),
+            PEType::Quantity { source, err } => write!(f, "{:?}: {}", source, err),
+            PEType::Temporal { source, err } => write!(f, "{:?}: {}", source, err),
+        }
+    }
+}
+
+/// Paths are field names, so an error about the spec as a whole has none
+impl fmt::Display for ParseErr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "{}: ", join(&self.path, "."))?;
+        }
+        write!(f, "{}", self.err)
+    }
+}
+
 impl From<regex::Error> for PEType<'_> {
     fn from(re_err: regex::Error) -> Self {
         PEType::Regex(re_err)
@@ -0,0 +1,414 @@
+use lazy_static::lazy_static;
+use serde_yaml::{Mapping, Value};
+use std::fmt;
+
+use crate::{constraint::Constraint, options::FieldOptions, parse::{PEType, ParseErr}, rule::{Reason, Rule, RuleErrType, RuleEvalErr, RuleEvalResult, RuleEvalSuccess}, valstr, value_ref::{ValueRef, ValueResolutionErr}};
+
+/// Strings with human units that stand for a number. Durations are measured in
+/// seconds and byte sizes in bytes; a plain YAML number is taken to already be
+/// in those units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Duration,
+    ByteSize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuantityErr {
+    Empty,
+    InvalidNumber(String),
+    UnknownUnit(String),
+    NotAQuantity,
+}
+
+impl fmt::Display for QuantityErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuantityErr::Empty => write!(f, "value is empty"),
+            QuantityErr::InvalidNumber(n) => write!(f, "{:?} is not a non-negative number", n),
+            QuantityErr::UnknownUnit(u) => write!(f, "{:?} is not a known unit", u),
+            QuantityErr::NotAQuantity => write!(f, "expected a string or a number"),
+        }
+    }
+}
+
+impl Unit {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Unit::Duration => "duration",
+            Unit::ByteSize => "bytesize",
+        }
+    }
+
+    /// The canonical amount a value stands for
+    pub fn amount(&self, value: &Value) -> Result<f64, QuantityErr> {
+        match value {
+            Value::String(s) => self.parse(s),
+            Value::Number(n) => match n.as_f64() {
+                Some(n) if n.is_finite() && n >= 0.0 => Ok(n),
+                _ => Err(QuantityErr::InvalidNumber(n.to_string())),
+            },
+            _ => Err(QuantityErr::NotAQuantity),
+        }
+    }
+
+    pub fn parse(&self, s: &str) -> Result<f64, QuantityErr> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(QuantityErr::Empty);
+        }
+        match self {
+            Unit::Duration => duration(s),
+            Unit::ByteSize => bytesize(s),
+        }
+    }
+}
+
+/// Splits off the leading number, e.g. `1.5` from `1.5h30m`. Only digits and
+/// dots count, so `inf` or `1e3` aren't numbers here.
+fn leading_number(s: &str) -> Result<(f64, &str), QuantityErr> {
+    let end = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, rest) = s.split_at(end);
+    match number.parse::<f64>() {
+        Ok(n) if n.is_finite() => Ok((n, rest)),
+        _ => Err(QuantityErr::InvalidNumber(number.to_string())),
+    }
+}
+
+/// Durations are one or more `<number><unit>` segments, e.g. `1h30m` or `250ms`
+fn duration(s: &str) -> Result<f64, QuantityErr> {
+    if let (seconds, "") = leading_number(s)? {
+        return Ok(seconds);
+    }
+    let mut rest = s;
+    let mut total = 0.0;
+    while !rest.is_empty() {
+        let (n, after) = leading_number(rest)?;
+        let end = after.find(|c: char| c.is_ascii_digit() || c == '.').unwrap_or(after.len());
+        let (unit, after) = after.split_at(end);
+        let scale = match unit {
+            "ns" => 1e-9,
+            "us" | "µs" => 1e-6,
+            "ms" => 1e-3,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "d" => 86400.0,
+            "w" => 604800.0,
+            _ => return Err(QuantityErr::UnknownUnit(unit.to_string())),
+        };
+        total += n * scale;
+        rest = after;
+    }
+    Ok(total)
+}
+
+/// Byte sizes are a single `<number><unit>`, where `k`/`kB` are powers of 1000
+/// and `Ki`/`KiB` are powers of 1024
+fn bytesize(s: &str) -> Result<f64, QuantityErr> {
+    let (n, unit) = leading_number(s)?;
+    let unit = unit.trim_start();
+    let scale = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1e3,
+        "ki" | "kib" => 1024.0,
+        "m" | "mb" => 1e6,
+        "mi" | "mib" => 1024.0 * 1024.0,
+        "g" | "gb" => 1e9,
+        "gi" | "gib" => 1024.0 * 1024.0 * 1024.0,
+        "t" | "tb" => 1e12,
+        "ti" | "tib" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        "p" | "pb" => 1e15,
+        "pi" | "pib" => 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(QuantityErr::UnknownUnit(unit.to_string())),
+    };
+    Ok(n * scale)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QtyConstr<'a> {
+    Allowed(Vec<ValueRef<'a, Value>>),
+    Disallowed(Vec<ValueRef<'a, Value>>),
+    Range { min: Option<ValueRef<'a, Value>>, max: Option<ValueRef<'a, Value>> },
+    Equals(ValueRef<'a, Value>),
+    NotEquals(ValueRef<'a, Value>),
+    GreaterThan(ValueRef<'a, Value>),
+    GreaterThanEq(ValueRef<'a, Value>),
+    LessThan(ValueRef<'a, Value>),
+    LessThanEq(ValueRef<'a, Value>),
+    Any,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuantityConstraint<'a> {
+    pub field_name: &'a Value,
+    pub unit: Unit,
+    pub constr: QtyConstr<'a>,
+    pub default: Option<&'a Value>,
+    pub options: FieldOptions,
+}
+
+impl<'a> QuantityConstraint<'a> {
+    pub fn default(field_name: &Value, unit: Unit) -> QuantityConstraint<'_> {
+        QuantityConstraint {
+            field_name,
+            unit,
+            constr: QtyConstr::Any,
+            default: None,
+            options: FieldOptions::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct QuantityConstraintBuilder<'a, 'b> {
+    field_name: &'a Value,
+    unit: Unit,
+    config: &'a Mapping,
+    path: &'b [&'a Value],
+    default: Option<&'a Value>,
+    options: FieldOptions,
+}
+
+impl<'a, 'b> QuantityConstraintBuilder<'a, 'b> {
+    fn new(field_name: &'a Value, unit: Unit, config: &'a Mapping, path: &'b [&'a Value]) -> Result<Self, ParseErr<'a>> {
+        lazy_static! {
+            static ref DEFAULT: Value = valstr!("default");
+        }
+        let default = match config.get(&DEFAULT) {
+            Some(val) if unit.amount(val).is_ok() => Some(val),
+            Some(val) => return Err(ParseErr::new(path, PEType::InvalidDefault { default: val, failures: vec![] })),
+            None => None,
+        };
+        let options = FieldOptions::parse(config, path)?;
+        Ok(Self { field_name, unit, config, path, default, options })
+    }
+
+    fn constraint(&self, constr: QtyConstr<'a>) -> QuantityConstraint<'a> {
+        QuantityConstraint {
+            field_name: self.field_name,
+            unit: self.unit,
+            constr,
+            default: self.default,
+            options: self.options.clone(),
+        }
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    fn from_mapping(&self) -> Result<QuantityConstraint<'a>, ParseErr<'a>> {
+        lazy_static! {
+            static ref ALLOWED: Value = valstr!("allowed");
+            static ref DISALLOWED: Value = valstr!("disallowed");
+            static ref MIN: Value = valstr!("min");
+            static ref MAX: Value = valstr!("max");
+            static ref EQ: Value = valstr!("eq");
+            static ref NEQ: Value = valstr!("neq");
+            static ref GT: Value = valstr!("gt");
+            static ref GTE: Value = valstr!("gte");
+            static ref LT: Value = valstr!("lt");
+            static ref LTE: Value = valstr!("lte");
+        }
+        if let Some(val) = self.config.get(&ALLOWED) {
+            return Ok(self.constraint(QtyConstr::Allowed(self.operands(val)?)));
+        }
+        if let Some(val) = self.config.get(&DISALLOWED) {
+            return Ok(self.constraint(QtyConstr::Disallowed(self.operands(val)?)));
+        }
+        let (min, max) = (self.config.get(&MIN), self.config.get(&MAX));
+        if min.is_some() || max.is_some() {
+            let min = min.map(|val| self.operand(val)).transpose()?;
+            let max = max.map(|val| self.operand(val)).transpose()?;
+            return Ok(self.constraint(QtyConstr::Range { min, max }));
+        }
+        if let Some(val) = self.config.get(&EQ) {
+            return Ok(self.constraint(QtyConstr::Equals(self.operand(val)?)));
+        }
+        if let Some(val) = self.config.get(&NEQ) {
+            return Ok(self.constraint(QtyConstr::NotEquals(self.operand(val)?)));
+        }
+        if let Some(val) = self.config.get(&GT) {
+            return Ok(self.constraint(QtyConstr::GreaterThan(self.operand(val)?)));
+        }
+        if let Some(val) = self.config.get(&GTE) {
+            return Ok(self.constraint(QtyConstr::GreaterThanEq(self.operand(val)?)));
+        }
+        if let Some(val) = self.config.get(&LT) {
+            return Ok(self.constraint(QtyConstr::LessThan(self.operand(val)?)));
+        }
+        if let Some(val) = self.config.get(&LTE) {
+            return Ok(self.constraint(QtyConstr::LessThanEq(self.operand(val)?)));
+        }
+        Ok(self.constraint(QtyConstr::Any))
+    }
+
+    /// Either a path to another field, or a literal that has to parse right away
+    fn operand(&self, val: &'a Value) -> Result<ValueRef<'a, Value>, ParseErr<'a>> {
+        match val {
+            Value::Sequence(path) => ValueRef::abs_path(path).map_err(|e| ParseErr::new(self.path, e)),
+            Value::String(s) => match self.unit.parse(s) {
+                Ok(_) => Ok(ValueRef::Literal(val)),
+                Err(err) => Err(ParseErr::new(self.path, PEType::Quantity { source: s, err })),
+            },
+            Value::Number(_) => match self.unit.amount(val) {
+                Ok(_) => Ok(ValueRef::Literal(val)),
+                Err(_) => Err(ParseErr::new(self.path, PEType::IncorrectType(val))),
+            },
+            _ => Err(ParseErr::new(self.path, PEType::IncorrectType(val))),
+        }
+    }
+
+    fn operands(&self, val: &'a Value) -> Result<Vec<ValueRef<'a, Value>>, ParseErr<'a>> {
+        match val {
+            Value::Sequence(seq) => seq.iter().map(|v| self.operand(v)).collect(),
+            _ => Err(ParseErr::new(self.path, PEType::IncorrectType(val))),
+        }
+    }
+}
+
+pub fn build<'a>(field_name: &'a Value, unit: Unit, map: &'a Mapping, path: &[&'a Value]) -> Result<QuantityConstraint<'a>, ParseErr<'a>> {
+    QuantityConstraintBuilder::new(field_name, unit, map, path)?.from_mapping()
+}
+
+/// A resolved operand, which keeps what it was written as for error messages
+#[derive(Debug, Clone, PartialEq)]
+pub struct Amount<'a> {
+    pub value: &'a Value,
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QtyRule<'a> {
+    Allowed(Vec<Amount<'a>>),
+    Disallowed(Vec<Amount<'a>>),
+    Range { min: Option<Amount<'a>>, max: Option<Amount<'a>> },
+    Equals(Amount<'a>),
+    NotEquals(Amount<'a>),
+    GreaterThan(Amount<'a>),
+    GreaterThanEq(Amount<'a>),
+    LessThan(Amount<'a>),
+    LessThanEq(Amount<'a>),
+    Any,
+}
+
+impl<'a> QtyRule<'a> {
+    pub fn new(constr: QtyConstr<'a>, unit: Unit, root: &'a Value, context: &Constraint<'a>) -> Result<QtyRule<'a>, ValueResolutionErr<'a>> {
+        let resolve = |vr: ValueRef<'a, Value>| {
+            let value = vr.resolve(root, context)?;
+            match unit.amount(value) {
+                Ok(amount) => Ok(Amount { value, amount }),
+                Err(_) => Err(ValueResolutionErr::IncorrectType(value)),
+            }
+        };
+        let all = |vrs: Vec<ValueRef<'a, Value>>| vrs.into_iter().map(resolve).collect::<Result<Vec<_>, _>>();
+        Ok(match constr {
+            QtyConstr::Allowed(v) => QtyRule::Allowed(all(v)?),
+            QtyConstr::Disallowed(v) => QtyRule::Disallowed(all(v)?),
+            QtyConstr::Range { min, max } => QtyRule::Range {
+                min: min.map(resolve).transpose()?,
+                max: max.map(resolve).transpose()?,
+            },
+            QtyConstr::Equals(vr) => QtyRule::Equals(resolve(vr)?),
+            QtyConstr::NotEquals(vr) => QtyRule::NotEquals(resolve(vr)?),
+            QtyConstr::GreaterThan(vr) => QtyRule::GreaterThan(resolve(vr)?),
+            QtyConstr::GreaterThanEq(vr) => QtyRule::GreaterThanEq(resolve(vr)?),
+            QtyConstr::LessThan(vr) => QtyRule::LessThan(resolve(vr)?),
+            QtyConstr::LessThanEq(vr) => QtyRule::LessThanEq(resolve(vr)?),
+            QtyConstr::Any => QtyRule::Any,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantityRule<'a> {
+    pub field_name: &'a Value,
+    unit: Unit,
+    rule: QtyRule<'a>,
+    pub default: Option<&'a Value>,
+    pub options: FieldOptions,
+}
+
+impl<'a> From<QuantityRule<'a>> for Rule<'a> {
+    fn from(qr: QuantityRule<'a>) -> Self {
+        Rule::Qty(qr)
+    }
+}
+
+impl<'a> QuantityRule<'a> {
+    pub fn new(constraint: QuantityConstraint<'a>, root: &'a Value, context: &Constraint<'a>) -> Result<QuantityRule<'a>, ValueResolutionErr<'a>> {
+        let rule = QtyRule::new(constraint.constr, constraint.unit, root, context)?;
+        Ok(QuantityRule {
+            field_name: constraint.field_name,
+            unit: constraint.unit,
+            rule,
+            default: constraint.default,
+            options: constraint.options,
+        })
+    }
+
+    pub fn eval(&self, value: &'a Value, path: &[&'a Value]) -> RuleEvalResult<'a> {
+        let x = match value {
+            Value::String(_) | Value::Number(_) => match self.unit.amount(value) {
+                Ok(x) => x,
+                Err(err) => {
+                    let reason = Reason::InvalidQuantity { unit: self.unit.name(), err };
+                    return RuleEvalSuccess::failed(path, reason).into();
+                }
+            },
+            _ => return RuleEvalErr::new(path, RuleErrType::IncorrectType(value)).into(),
+        };
+        let compare = |op: &'static str, holds: fn(f64, f64) -> bool, bound: &Amount<'a>| {
+            match holds(x, bound.amount) {
+                true => RuleEvalSuccess::new(true, path),
+                false => RuleEvalSuccess::failed(path, Reason::Comparison { op, bound: bound.value }),
+            }
+        };
+        let result = match &self.rule {
+            QtyRule::Allowed(list) => RuleEvalSuccess::new(list.iter().any(|a| a.amount == x), path),
+            QtyRule::Disallowed(list) => RuleEvalSuccess::new(!list.iter().any(|a| a.amount == x), path),
+            QtyRule::Range { min, max } => match (min, max) {
+                (Some(min), _) if x < min.amount => compare(">=", |x, b| x >= b, min),
+                (_, Some(max)) if x > max.amount => compare("<=", |x, b| x <= b, max),
+                _ => RuleEvalSuccess::new(true, path),
+            },
+            QtyRule::Equals(b) => compare("==", |x, b| x == b, b),
+            QtyRule::NotEquals(b) => compare("!=", |x, b| x != b, b),
+            QtyRule::GreaterThan(b) => compare(">", |x, b| x > b, b),
+            QtyRule::GreaterThanEq(b) => compare(">=", |x, b| x >= b, b),
+            QtyRule::LessThan(b) => compare("<", |x, b| x < b, b),
+            QtyRule::LessThanEq(b) => compare("<=", |x, b| x <= b, b),
+            QtyRule::Any => RuleEvalSuccess::new(true, path),
+        };
+        result.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(Unit::Duration.parse("30s"), Ok(30.0));
+        assert_eq!(Unit::Duration.parse("1h30m"), Ok(5400.0));
+        assert_eq!(Unit::Duration.parse("250ms"), Ok(0.25));
+        assert_eq!(Unit::Duration.parse("90"), Ok(90.0));
+        assert_eq!(Unit::Duration.parse("5 minutes"), Err(QuantityErr::UnknownUnit(String::from(" minutes"))));
+        assert_eq!(Unit::Duration.parse("s"), Err(QuantityErr::InvalidNumber(String::new())));
+        assert_eq!(Unit::Duration.parse("inf"), Err(QuantityErr::InvalidNumber(String::new())));
+        assert_eq!(Unit::Duration.parse("1e3"), Err(QuantityErr::UnknownUnit(String::from("e"))));
+        let infinite: Value = serde_yaml::from_str(".inf").unwrap();
+        assert_eq!(Unit::Duration.amount(&infinite), Err(QuantityErr::InvalidNumber(String::from(".inf"))));
+    }
+
+    #[test]
+    fn byte_sizes() {
+        assert_eq!(Unit::ByteSize.parse("10MiB"), Ok(10.0 * 1024.0 * 1024.0));
+        assert_eq!(Unit::ByteSize.parse("512Mi"), Ok(512.0 * 1024.0 * 1024.0));
+        assert_eq!(Unit::ByteSize.parse("1 GB"), Ok(1e9));
+        assert_eq!(Unit::ByteSize.parse("100"), Ok(100.0));
+        assert_eq!(Unit::ByteSize.parse("10XB"), Err(QuantityErr::UnknownUnit(String::from("XB"))));
+        assert_eq!(Unit::ByteSize.amount(&Value::from(-1)), Err(QuantityErr::InvalidNumber(String::from("-1"))));
+        assert_eq!(Unit::ByteSize.parse("infinity"), Err(QuantityErr::InvalidNumber(String::new())));
+        assert_eq!(Unit::ByteSize.amount(&Value::from(f64::NAN)), Err(QuantityErr::InvalidNumber(String::from(".nan"))));
+    }
+}
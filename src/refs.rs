@@ -0,0 +1,201 @@
+use serde_yaml::Value;
+
+use crate::{constraint::Constraint, parse::{PEType, ParseErr}, quantity::{QtyConstr, Unit}, str::StrConstr, value_ref::ValueRef};
+
+/// What kind of field a reference can point at
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Expected<'a> {
+    Str,
+    /// a string field whose regex has this named group
+    CaptureGroup(&'a str),
+    Quantity(Unit),
+    Temporal,
+}
+
+impl Expected<'_> {
+    fn describe(&self) -> String {
+        match self {
+            Expected::Str => String::from("string"),
+            Expected::CaptureGroup(group) => format!("string with a regex capture group named {:?}", group),
+            Expected::Quantity(unit) => unit.name().to_string(),
+            Expected::Temporal => String::from("datetime, date or time"),
+        }
+    }
+
+    /// Strings and `any` fields can hold anything that's written as a string
+    fn accepts(&self, target: &Constraint) -> bool {
+        match (self, target) {
+            (Expected::CaptureGroup(group), Constraint::Str(s)) => match &s.constr {
+                StrConstr::Regex(re) => re.capture_names().any(|name| name == Some(*group)),
+                _ => false,
+            },
+            (Expected::CaptureGroup(_), _) => false,
+            (_, Constraint::Str(_)) | (_, Constraint::Any(_)) => true,
+            (Expected::Quantity(unit), Constraint::Qty(q)) => q.unit == *unit,
+            (Expected::Temporal, Constraint::Time(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The fields a `when` tests. A condition reads whatever value the document
+/// has, so these only have to exist, and they aren't followed when looking
+/// for cycles.
+fn conditions<'a>(constraint: &Constraint<'a>) -> Vec<Vec<&'a Value>> {
+    match constraint {
+        Constraint::When(c) => c.clauses.iter()
+            .filter_map(|clause| match &clause.condition.field {
+                ValueRef::AbsolutePath(path) => Some(path.clone()),
+                ValueRef::Literal(_) => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// A field's outgoing references, with what each has to point at
+fn references<'a>(constraint: &Constraint<'a>) -> Vec<(Vec<&'a Value>, Expected<'a>)> {
+    let mut refs = vec![];
+    match constraint {
+        Constraint::Str(s) => {
+            let operands: Vec<_> = s.constrs().flat_map(|constr| match constr {
+                StrConstr::Allowed(list) | StrConstr::Disallowed(list) => list.iter().collect(),
+                StrConstr::Equals(vr) | StrConstr::NotEquals(vr) | StrConstr::StartsWith(vr)
+                | StrConstr::EndsWith(vr) | StrConstr::Contains(vr) => vec![vr],
+                // length bounds point at numbers, which specs can't describe yet
+                _ => vec![],
+            }).collect();
+            for operand in operands {
+                if let ValueRef::AbsolutePath(path) = operand {
+                    refs.push(match path.split_last() {
+                        Some((Value::String(last), field)) if last.starts_with('#') => (field.to_vec(), Expected::CaptureGroup(&last[1..])),
+                        _ => (path.clone(), Expected::Str),
+                    });
+                }
+            }
+        }
+        Constraint::Qty(q) => {
+            let operands: Vec<_> = match &q.constr {
+                QtyConstr::Allowed(list) | QtyConstr::Disallowed(list) => list.iter().collect(),
+                QtyConstr::Range { min, max } => min.iter().chain(max.iter()).collect(),
+                QtyConstr::Equals(vr) | QtyConstr::NotEquals(vr) | QtyConstr::GreaterThan(vr)
+                | QtyConstr::GreaterThanEq(vr) | QtyConstr::LessThan(vr) | QtyConstr::LessThanEq(vr) => vec![vr],
+                QtyConstr::Any => vec![],
+            };
+            for operand in operands {
+                if let ValueRef::AbsolutePath(path) = operand {
+                    refs.push((path.clone(), Expected::Quantity(q.unit)));
+                }
+            }
+        }
+        Constraint::Time(t) => {
+            for operand in t.after.iter().chain(t.before.iter()) {
+                if let ValueRef::AbsolutePath(path) = operand {
+                    refs.push((path.clone(), Expected::Temporal));
+                }
+            }
+        }
+        Constraint::When(c) => {
+            refs.extend(references(&c.base));
+            for clause in &c.clauses {
+                refs.extend(references(&clause.then));
+                refs.extend(clause.otherwise.iter().flat_map(references));
+            }
+        }
+        Constraint::Obj(_) | Constraint::Any(_) => {}
+    }
+    refs
+}
+
+/// Every field in the spec that references another, with the fields it points at
+type Graph<'a> = Vec<(Vec<&'a Value>, Vec<Vec<&'a Value>>)>;
+
+fn collect<'a>(constraint: &Constraint<'a>, path: &[&'a Value], root: &Constraint<'a>, graph: &mut Graph<'a>, err: &mut Vec<ParseErr<'a>>) {
+    let mut targets = vec![];
+    for (target, expected) in references(constraint) {
+        match root.find(&target).map(Constraint::unconditional) {
+            None => err.push(ParseErr::new(path, PEType::DanglingRef(target))),
+            Some(found) if !expected.accepts(found) => {
+                let err_type = PEType::RefTypeMismatch { target: target.clone(), expected: expected.describe(), found: found.type_name() };
+                err.push(ParseErr::new(path, err_type));
+            }
+            Some(_) => targets.push(target),
+        }
+    }
+    if !targets.is_empty() {
+        graph.push((path.to_vec(), targets));
+    }
+    for target in conditions(constraint) {
+        if root.find(&target).is_none() {
+            err.push(ParseErr::new(path, PEType::DanglingRef(target)));
+        }
+    }
+    if let Constraint::Obj(o) = constraint.unconditional() {
+        for (key, field) in o.fields() {
+            let mut field_path = path.to_vec();
+            field_path.push(key);
+            collect(field, &field_path, root, graph, err);
+        }
+    }
+}
+
+/// Walks the references depth first, recording each cycle the first time
+/// it's closed
+fn find_cycles<'a>(node: &[&'a Value], graph: &Graph<'a>, stack: &mut Vec<Vec<&'a Value>>, done: &mut Vec<Vec<&'a Value>>, cycles: &mut Vec<Vec<Vec<&'a Value>>>) {
+    if let Some(start) = stack.iter().position(|n| n == node) {
+        cycles.push(stack[start..].to_vec());
+        return;
+    }
+    if done.iter().any(|n| n == node) {
+        return;
+    }
+    stack.push(node.to_vec());
+    let targets = graph.iter().find(|(from, _)| from == node).map(|(_, to)| &to[..]).unwrap_or(&[]);
+    for target in targets {
+        find_cycles(target, graph, stack, done, cycles);
+    }
+    done.push(stack.pop().unwrap());
+}
+
+/// Checks every reference in the spec: that the field it points at exists,
+/// that it's the right type, and that no field ends up depending on itself.
+/// Fields tested by a `when` have to exist too. Paths in `assert` expressions
+/// aren't checked, since they can name keys an object only allows through
+/// `additional_keys`.
+pub(crate) fn check<'a>(root: &Constraint<'a>) -> Vec<ParseErr<'a>> {
+    let (mut graph, mut err) = (vec![], vec![]);
+    collect(root, &[], root, &mut graph, &mut err);
+    let (mut done, mut cycles) = (vec![], vec![]);
+    for (node, _) in &graph {
+        find_cycles(node, &graph, &mut vec![], &mut done, &mut cycles);
+    }
+    for cycle in cycles {
+        err.push(ParseErr::new(&cycle[0], PEType::RefCycle(cycle.clone())));
+    }
+    err
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{document_constraint, valstr};
+
+    #[test]
+    fn self_reference_is_a_cycle() {
+        let spec = serde_yaml::from_str(concat!(
+            "a:\n",
+            "  type: string\n",
+            "  eq: [a]\n",
+        )).unwrap();
+        let (name, a) = (valstr!("."), valstr!("a"));
+        let expected = ParseErr::new(&[&a], PEType::RefCycle(vec![vec![&a]]));
+        assert_eq!(Err(vec![expected]), document_constraint(&spec, &name));
+    }
+
+    #[test]
+    fn cycles_are_shown_as_fields() {
+        let (a, b) = (valstr!("a"), valstr!("b"));
+        let err = ParseErr::new(&[&a], PEType::RefCycle(vec![vec![&a], vec![&b]]));
+        assert_eq!("a: refers back to itself through a -> b -> a", err.to_string());
+    }
+}
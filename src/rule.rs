@@ -1,6 +1,8 @@
-use serde_yaml::Value;
+use std::fmt;
 
-use crate::{bubble::Bubble, constraint::Constraint, obj::ObjectRule, str::StringRule, value_ref::ValueResolutionErr};
+use serde_yaml::{Number, Value};
+
+use crate::{any::AnyRule, bubble::Bubble, constraint::Constraint, expr::EvalErr, format::FormatErr, quantity::{QuantityErr, QuantityRule}, temporal::{TemporalErr, TemporalRule}, obj::ObjectRule, options::{display_value, join, value_kind, Diagnostic, FieldOptions, Severity}, str::{RegexOptions, StringRule}, value_ref::ValueResolutionErr};
 
 pub type RuleEvalResult<'a> = Bubble<Result<RuleEvalSuccess<'a>, RuleEvalErr<'a>>>;
 
@@ -9,24 +11,106 @@ pub enum RuleErrType<'a> {
     MissingRequired,
     KeyNotFound(&'a Value),
     IncorrectType(&'a Value),
-    Resolution(ValueResolutionResult<'a>),
+    Resolution(Box<ValueResolutionResult<'a>>),
+    MissingVariant {
+        key: &'a Value,
+        valid: Vec<&'a Value>,
+    },
+    UnknownVariant {
+        key: &'a Value,
+        found: &'a Value,
+        valid: Vec<&'a Value>,
+    },
+    ExclusiveKeys {
+        keys: Vec<&'a Value>,
+        present: Vec<&'a Value>,
+    },
+    OneRequired(Vec<&'a Value>),
+    /// a key in an object that doesn't allow `additional_keys`
+    UnknownKey {
+        key: &'a Value,
+        valid: Vec<&'a Value>,
+    },
+    MissingDependency {
+        key: &'a Value,
+        requires: Vec<&'a Value>,
+        missing: Vec<&'a Value>,
+    },
+    AssertionFailed(&'a str),
+    /// the assertion couldn't be evaluated, usually because of a type mismatch
+    Assertion {
+        source: &'a str,
+        err: EvalErr,
+    },
+    /// An error in one of the fields of the variant selected by a discriminator
+    InVariant {
+        variant: &'a Value,
+        err: Box<RuleErrType<'a>>,
+    },
 }
 
 impl<'a> From<ValueResolutionResult<'a>> for RuleErrType<'a> {
     fn from(vrr: ValueResolutionResult<'a>) -> Self {
-        RuleErrType::Resolution(vrr)
+        RuleErrType::Resolution(Box::new(vrr))
+    }
+}
+
+impl fmt::Display for RuleErrType<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleErrType::MissingRequired => write!(f, "is required"),
+            RuleErrType::KeyNotFound(key) => write!(f, "{} is required", display_value(key)),
+            RuleErrType::IncorrectType(value) => write!(f, "is a {}, which is the wrong type", value_kind(value)),
+            RuleErrType::Resolution(results) => {
+                let errors: Vec<_> = results.iter().filter_map(|r| r.as_ref().err()).map(ToString::to_string).collect();
+                write!(f, "can't resolve a reference: {}", errors.join("; "))
+            }
+            RuleErrType::MissingVariant { key, valid } => {
+                write!(f, "{} is required to pick one of {}", display_value(key), join(valid, ", "))
+            }
+            RuleErrType::UnknownVariant { key, found, valid } => {
+                write!(f, "{} is {}, which isn't one of {}", display_value(key), display_value(found), join(valid, ", "))
+            }
+            RuleErrType::ExclusiveKeys { keys, present } => {
+                write!(f, "only one of {} can be set, found {}", join(keys, ", "), join(present, ", "))
+            }
+            RuleErrType::OneRequired(keys) => write!(f, "one of {} is required", join(keys, ", ")),
+            RuleErrType::UnknownKey { key, valid } if valid.is_empty() => write!(f, "{} isn't allowed here", display_value(key)),
+            RuleErrType::UnknownKey { key, valid } => write!(f, "{} isn't one of {}", display_value(key), join(valid, ", ")),
+            RuleErrType::MissingDependency { key, missing, .. } => {
+                write!(f, "{} also needs {}", display_value(key), join(missing, ", "))
+            }
+            RuleErrType::AssertionFailed(source) => write!(f, "assertion failed: {}", source),
+            RuleErrType::Assertion { source, err } => write!(f, "can't evaluate {}: {}", source, err),
+            RuleErrType::InVariant { variant, err } => write!(f, "in variant {}: {}", display_value(variant), err),
+        }
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct RuleEvalErr<'a> {
-    path: Vec<&'a Value>,
-    err: RuleErrType<'a>,
+    pub path: Vec<&'a Value>,
+    pub err: RuleErrType<'a>,
+    pub diagnostic: Option<Diagnostic>,
+    pub severity: Severity,
 }
 
 impl<'a> RuleEvalErr<'a> {
     pub fn new(path: &[&'a Value], err: RuleErrType<'a>) -> RuleEvalErr<'a> {
-        RuleEvalErr { path: path.to_vec(), err }
+        RuleEvalErr { path: path.to_vec(), err, diagnostic: None, severity: Severity::Error }
+    }
+
+    pub fn in_variant(self, variant: &'a Value) -> RuleEvalErr<'a> {
+        let err = RuleErrType::InVariant { variant, err: Box::new(self.err) };
+        RuleEvalErr { err, ..self }
+    }
+
+    pub fn with_diagnostic(self, diagnostic: Option<Diagnostic>) -> RuleEvalErr<'a> {
+        RuleEvalErr { diagnostic, ..self }
+    }
+
+    pub fn with_severity(self, severity: Severity) -> RuleEvalErr<'a> {
+        RuleEvalErr { severity, ..self }
     }
 }
 
@@ -37,15 +121,75 @@ impl<'a> From<RuleEvalErr<'a>> for RuleEvalResult<'a> {
 }
 
 
+/// Why a value didn't satisfy its rule, for the rules that can say more than
+/// just `false`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reason<'a> {
+    TooShort { length: usize, min: &'a Number },
+    TooLong { length: usize, max: &'a Number },
+    MissingPrefix(&'a str),
+    MissingSuffix(&'a str),
+    MissingSubstring(&'a str),
+    Format { format: &'static str, err: FormatErr },
+    Comparison { op: &'static str, bound: &'a Value },
+    InvalidQuantity { unit: &'static str, err: QuantityErr },
+    InvalidTemporal { kind: &'static str, err: TemporalErr },
+    NoMatch { pattern: String, options: RegexOptions },
+    Deprecated(String),
+    Renamed { from: &'a Value, to: &'a Value },
+    DeprecatedValue { found: &'a Value, replacement: Value },
+}
+
+impl fmt::Display for Reason<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::TooShort { length, min } => write!(f, "length {} is less than {}", length, min),
+            Reason::TooLong { length, max } => write!(f, "length {} exceeds {}", length, max),
+            Reason::MissingPrefix(prefix) => write!(f, "does not start with {:?}", prefix),
+            Reason::MissingSuffix(suffix) => write!(f, "does not end with {:?}", suffix),
+            Reason::MissingSubstring(part) => write!(f, "does not contain {:?}", part),
+            Reason::Format { format, err } => write!(f, "not a valid {}: {}", format, err),
+            Reason::Comparison { op, bound: Value::String(s) } => write!(f, "is not {} {}", op, s),
+            Reason::Comparison { op, bound: Value::Number(n) } => write!(f, "is not {} {}", op, n),
+            Reason::Comparison { op, bound } => write!(f, "is not {} {:?}", op, bound),
+            Reason::InvalidQuantity { unit, err } => write!(f, "not a valid {}: {}", unit, err),
+            Reason::InvalidTemporal { kind, err } => write!(f, "not a valid {}: {}", kind, err),
+            Reason::NoMatch { pattern, options } => write!(f, "does not match {:?} ({})", pattern, options),
+            Reason::Deprecated(hint) => write!(f, "is deprecated: {}", hint),
+            Reason::Renamed { to: Value::String(to), .. } => write!(f, "has been renamed to {:?}", to),
+            Reason::Renamed { to, .. } => write!(f, "has been renamed to {:?}", to),
+            Reason::DeprecatedValue { found, replacement } => {
+                write!(f, "{} is deprecated, use {} instead", display_value(found), display_value(replacement))
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct RuleEvalSuccess<'a> {
-    result: bool,
-    path: Vec<&'a Value>,
+    pub result: bool,
+    pub path: Vec<&'a Value>,
+    pub reason: Option<Reason<'a>>,
+    pub diagnostic: Option<Diagnostic>,
+    /// only meaningful when `result` is false
+    pub severity: Severity,
 }
 
 impl<'a> RuleEvalSuccess<'a> {
     pub fn new(result: bool, path: &[&'a Value]) -> RuleEvalSuccess<'a> {
-        RuleEvalSuccess { result, path: path.to_vec(), }
+        RuleEvalSuccess { result, path: path.to_vec(), reason: None, diagnostic: None, severity: Severity::Error }
+    }
+
+    pub fn failed(path: &[&'a Value], reason: Reason<'a>) -> RuleEvalSuccess<'a> {
+        RuleEvalSuccess { result: false, path: path.to_vec(), reason: Some(reason), diagnostic: None, severity: Severity::Error }
+    }
+
+    pub fn with_diagnostic(self, diagnostic: Option<Diagnostic>) -> RuleEvalSuccess<'a> {
+        RuleEvalSuccess { diagnostic, ..self }
+    }
+
+    pub fn with_severity(self, severity: Severity) -> RuleEvalSuccess<'a> {
+        RuleEvalSuccess { severity, ..self }
     }
 }
 
@@ -59,6 +203,9 @@ impl<'a> From<RuleEvalSuccess<'a>> for RuleEvalResult<'a> {
 pub enum Rule<'a> {
     Str(StringRule<'a>),
     Obj(ObjectRule<'a>),
+    Qty(QuantityRule<'a>),
+    Time(TemporalRule<'a>),
+    Any(AnyRule<'a>),
 }
 
 pub type ValueResolutionResult<'a> = Bubble<Result<Rule<'a>, ValueResolutionErr<'a>>>;
@@ -73,6 +220,16 @@ impl<'a> Rule<'a> {
                 }
             }
             Constraint::Obj(oc) => ObjectRule::resolve(oc, root, context),
+            Constraint::Qty(qc) => match QuantityRule::new(qc, root, context) {
+                Ok(qr) => Bubble::Single(Ok(qr.into())),
+                Err(e) => Bubble::Single(Err(e)),
+            },
+            Constraint::Time(tc) => match TemporalRule::new(tc, root, context) {
+                Ok(tr) => Bubble::Single(Ok(tr.into())),
+                Err(e) => Bubble::Single(Err(e)),
+            },
+            Constraint::Any(ac) => Bubble::Single(Ok(ac.into())),
+            Constraint::When(cc) => Rule::new(cc.select(root, context), root, context),
         }
     }
 
@@ -80,15 +237,27 @@ impl<'a> Rule<'a> {
         match self {
             Rule::Str(sr) => sr.field_name,
             Rule::Obj(or) => or.field_name,
+            Rule::Qty(qr) => qr.field_name,
+            Rule::Time(tr) => tr.field_name,
+            Rule::Any(ar) => ar.field_name,
         }
     }
 
     pub fn eval(self, value: &'a Value, parent_path: &[&'a Value]) -> RuleEvalResult<'a> {
         let mut path = parent_path.to_vec();
         path.push(self.field_name());
-        match self {
+        let diagnostic = self.options().diagnostic(Some(value), &path);
+        let severity = self.options().severity;
+        let results = match self {
             Rule::Str(sr) => sr.eval(value, &path),
             Rule::Obj(or) => or.eval(value, &path),
+            Rule::Qty(qr) => qr.eval(value, &path),
+            Rule::Time(tr) => tr.eval(value, &path),
+            Rule::Any(ar) => ar.eval(value, &path),
+        };
+        match (diagnostic, severity) {
+            (None, Severity::Error) => results,
+            (diagnostic, severity) => annotate(results, &path, diagnostic, severity),
         }
     }
 
@@ -96,6 +265,39 @@ impl<'a> Rule<'a> {
         match self {
             Rule::Str(s) => s.default,
             Rule::Obj(o) => o.default,
+            Rule::Qty(q) => q.default,
+            Rule::Time(t) => t.default,
+            Rule::Any(a) => a.default,
+        }
+    }
+
+    pub fn options(&self) -> &FieldOptions {
+        match self {
+            Rule::Str(s) => &s.options,
+            Rule::Obj(o) => &o.options,
+            Rule::Qty(q) => &q.options,
+            Rule::Time(t) => &t.options,
+            Rule::Any(a) => &a.options,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Attaches the spec's own message and severity to the failures of the field
+/// they were declared on. Failures further down keep whatever their own fields
+/// declared.
+// see `ObjectRule::eval_variant` for why the lint is allowed
+#[allow(clippy::result_large_err)]
+pub fn annotate<'a>(results: RuleEvalResult<'a>, path: &[&'a Value], diagnostic: Option<Diagnostic>, severity: Severity) -> RuleEvalResult<'a> {
+    let results = results.into_iter().map(|res| match res {
+        Ok(s) if !s.result && s.path == path => {
+            let diagnostic = s.diagnostic.clone().or_else(|| diagnostic.clone());
+            Ok(s.with_diagnostic(diagnostic).with_severity(severity))
+        }
+        Err(e) if e.path == path => {
+            let diagnostic = e.diagnostic.clone().or_else(|| diagnostic.clone());
+            Err(e.with_diagnostic(diagnostic).with_severity(severity))
+        }
+        other => other,
+    });
+    Bubble::Multi(results.collect())
+}
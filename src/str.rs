@@ -1,33 +1,120 @@
 use lazy_static::lazy_static;
-use regex::Regex;
-use serde_yaml::{Mapping, Value};
-use std::ops::Deref;
+use regex::{Regex, RegexBuilder};
+use serde_yaml::{Mapping, Number, Value};
+use std::{borrow::Cow, fmt, ops::Deref};
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
 
-use crate::{constraint::Constraint, parse::{PEType, ParseErr}, rule::{Rule, RuleErrType, RuleEvalErr, RuleEvalResult, RuleEvalSuccess}, value_ref::{ValueRef, ValueResolutionErr}};
+use crate::{constraint::Constraint, format::Format, options::FieldOptions, parse::{PEType, ParseErr}, rule::{Reason, Rule, RuleErrType, RuleEvalErr, RuleEvalResult, RuleEvalSuccess}, value_ref::{ValueRef, ValueResolutionErr}};
 use crate::valstr;
 
 // A wrapper type because Regex doesn't implement Eq or PartialEq. In fairness,
 // most of the time you would never want to use a regex as a key -- but really we
 // just want this for test coverage
 #[derive(Debug, Clone)]
-pub struct WrappedRegex(Regex);
+pub struct WrappedRegex {
+    regex: Regex,
+    /// the pattern as it was written, before any anchoring
+    pub pattern: String,
+    pub options: RegexOptions,
+}
 
 impl Deref for WrappedRegex {
     type Target = Regex;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.regex
     }
 }
 
 impl PartialEq for WrappedRegex {
     fn eq(&self, other: &Self) -> bool {
-        self.0.as_str() == other.as_str()
+        self.regex.as_str() == other.as_str() && self.options == other.options
     }
 }
 
 impl Eq for WrappedRegex {}
 
+/// How a regex is applied. Matching is partial unless `match: full` is given,
+/// so `\d+` on its own accepts `abc1`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RegexOptions {
+    pub full: bool,
+    pub multiline: bool,
+    pub dot_all: bool,
+    pub case_insensitive: bool,
+    pub size_limit: Option<usize>,
+}
+
+impl fmt::Display for RegexOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags: Vec<_> = [
+            (self.multiline, "multiline"),
+            (self.dot_all, "dot_all"),
+            (self.case_insensitive, "case_insensitive"),
+        ].iter().filter(|(on, _)| *on).map(|(_, name)| *name).collect();
+        write!(f, "{} match", if self.full { "full" } else { "partial" })?;
+        if !flags.is_empty() {
+            write!(f, " with {}", flags.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// What the length of a string is measured in. Grapheme clusters are closer to
+/// what a person would call a character (`"e\u{301}"` is one grapheme, but two
+/// scalar values).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthUnit {
+    Chars,
+    Graphemes,
+}
+
+impl LengthUnit {
+    pub fn measure(&self, s: &str) -> usize {
+        match self {
+            LengthUnit::Chars => s.chars().count(),
+            LengthUnit::Graphemes => s.graphemes(true).count(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalForm {
+    Nfc,
+    Nfkc,
+}
+
+/// How strings are cleaned up before they're compared. The same treatment is
+/// given to the input, to literals from the spec, and to referenced values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Normalize {
+    pub case_insensitive: bool,
+    pub trim: bool,
+    pub form: Option<NormalForm>,
+}
+
+impl Normalize {
+    pub fn apply<'s>(&self, s: &'s str) -> Cow<'s, str> {
+        let mut s = Cow::Borrowed(if self.trim { s.trim() } else { s });
+        match self.form {
+            Some(NormalForm::Nfc) => s = Cow::Owned(s.nfc().collect()),
+            Some(NormalForm::Nfkc) => s = Cow::Owned(s.nfkc().collect()),
+            None => {}
+        }
+        if self.case_insensitive {
+            s = Cow::Owned(s.to_lowercase());
+        }
+        s
+    }
+
+    /// Regexes are compiled with the `i` flag instead of lowercasing the
+    /// input, so that patterns like `[A-Z]` still behave
+    fn apply_for_regex<'s>(&self, s: &'s str) -> Cow<'s, str> {
+        Normalize { case_insensitive: false, ..*self }.apply(s)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum StrConstr<'a> {
     Allowed(Vec<ValueRef<'a, String>>),
@@ -35,6 +122,15 @@ pub enum StrConstr<'a> {
     Regex(Box<WrappedRegex>),
     Equals(ValueRef<'a, String>),
     NotEquals(ValueRef<'a, String>),
+    Length {
+        min: Option<ValueRef<'a, Number>>,
+        max: Option<ValueRef<'a, Number>>,
+        unit: LengthUnit,
+    },
+    StartsWith(ValueRef<'a, String>),
+    EndsWith(ValueRef<'a, String>),
+    Contains(ValueRef<'a, String>),
+    Format(Format<'a>),
     Any,
 }
 
@@ -42,16 +138,29 @@ pub enum StrConstr<'a> {
 pub struct StringConstraint<'a> {
     pub field_name: &'a Value,
     pub constr: StrConstr<'a>,
+    /// length bounds, affixes and formats that come on top of `constr`, in
+    /// the order they're checked
+    pub checks: Vec<StrConstr<'a>>,
     pub default: Option<&'a Value>,
+    pub options: FieldOptions,
+    pub normalize: Normalize,
 }
 
 impl<'a> StringConstraint<'a> {
-    pub fn default(field_name: &Value) -> StringConstraint {
-        StringConstraint { field_name, constr: StrConstr::Any, default: None }
+    pub fn default(field_name: &Value) -> StringConstraint<'_> {
+        StringConstraint {
+            field_name,
+            constr: StrConstr::Any,
+            checks: vec![],
+            default: None,
+            options: FieldOptions::default(),
+            normalize: Normalize::default(),
+        }
     }
 
-    fn new(field_name: &'a Value, constr: StrConstr<'a>, default: Option<&'a Value>) -> StringConstraint<'a> {
-        StringConstraint { field_name, constr, default }
+    /// `constr` followed by the checks that come on top of it
+    pub fn constrs(&self) -> impl Iterator<Item = &StrConstr<'a>> {
+        std::iter::once(&self.constr).chain(&self.checks)
     }
 }
 
@@ -60,13 +169,37 @@ struct StringConstraintBuilder<'a, 'b> {
     field_name: &'a Value,
     config: &'a Mapping,
     path: &'b [&'a Value],
-    default: Option<&'a Value>
+    default: Option<&'a Value>,
+    options: FieldOptions,
+    normalize: Normalize,
 }
 
 impl<'a, 'b> StringConstraintBuilder<'a, 'b> {
     fn new(field_name: &'a Value, config: &'a Mapping, path: &'b [&'a Value]) -> Result<Self, ParseErr<'a>> {
         let default = Self::field_default(config, path)?;
-        Ok(Self { field_name, config, path, default })
+        let options = FieldOptions::parse(config, path)?;
+        let normalize = Self::normalize(config, path)?;
+        Ok(Self { field_name, config, path, default, options, normalize })
+    }
+
+    fn normalize(config: &'a Mapping, path: &'b [&'a Value]) -> Result<Normalize, ParseErr<'a>> {
+        lazy_static! {
+            static ref CASE_INSENSITIVE: Value = valstr!("case_insensitive");
+            static ref TRIM: Value = valstr!("trim");
+            static ref NORMALIZE: Value = valstr!("normalize");
+        }
+        let flag = |key: &Value| match config.get(key) {
+            Some(Value::Bool(b)) => Ok(*b),
+            Some(other) => Err(ParseErr::new(path, PEType::IncorrectType(other))),
+            None => Ok(false),
+        };
+        let form = match config.get(&NORMALIZE) {
+            None => None,
+            Some(Value::String(f)) if f == "nfc" => Some(NormalForm::Nfc),
+            Some(Value::String(f)) if f == "nfkc" => Some(NormalForm::Nfkc),
+            Some(other) => return Err(ParseErr::new(path, PEType::IncorrectType(other))),
+        };
+        Ok(Normalize { case_insensitive: flag(&CASE_INSENSITIVE)?, trim: flag(&TRIM)?, form })
     }
 
     fn field_default(map: &'a Mapping, path: &'b [&'a Value]) -> Result<Option<&'a Value>, ParseErr<'a>> {
@@ -76,13 +209,18 @@ impl<'a, 'b> StringConstraintBuilder<'a, 'b> {
         if let Some(val) = map.get(&DEFAULT) {
             match val {
                 Value::String(_) => Ok(Some(val)),
-                _ => Err(ParseErr::new(path, PEType::InvalidDefault(val)))
+                _ => Err(ParseErr::new(path, PEType::InvalidDefault { default: val, failures: vec![] }))
             }
         } else {
             Ok(None)
         }
     }
 
+    /// `regex`, `allowed`, `disallowed`, `eq` and `neq` each say what the
+    /// string is, so only the first of them counts. Length bounds, affixes
+    /// and `format` are checked on top of it, or of each other if there's
+    /// none.
+    #[allow(clippy::wrong_self_convention)]
     fn from_mapping(&self) -> Result<StringConstraint<'a>, ParseErr<'a>> {
         lazy_static! {
             static ref ALLOWED: Value = valstr!("allowed");
@@ -90,37 +228,133 @@ impl<'a, 'b> StringConstraintBuilder<'a, 'b> {
             static ref REGEX: Value = valstr!("regex");
             static ref EQ: Value = valstr!("eq");
             static ref NEQ: Value = valstr!("neq");
-            static ref DEFAULT: Value = valstr!("default");
+            static ref MIN_LENGTH: Value = valstr!("min_length");
+            static ref MAX_LENGTH: Value = valstr!("max_length");
+            static ref STARTS_WITH: Value = valstr!("starts_with");
+            static ref ENDS_WITH: Value = valstr!("ends_with");
+            static ref CONTAINS: Value = valstr!("contains");
+            static ref FORMAT: Value = valstr!("format");
         }
-        if let Some(val) = self.config.get(&REGEX) {
-            return self.regex(val);
+        let primary = if let Some(val) = self.config.get(&REGEX) {
+            Some(self.regex(val)?)
+        } else if let Some(val) = self.config.get(&ALLOWED) {
+            Some(self.allowed(val)?)
+        } else if let Some(val) = self.config.get(&DISALLOWED) {
+            Some(self.disallowed(val)?)
+        } else if let Some(val) = self.config.get(&EQ) {
+            Some(self.eq(val)?)
+        } else if let Some(val) = self.config.get(&NEQ) {
+            Some(self.neq(val)?)
+        } else {
+            None
+        };
+
+        let mut checks = vec![];
+        // the bounds are the one pair of keys that go together
+        let (min, max) = (self.config.get(&MIN_LENGTH), self.config.get(&MAX_LENGTH));
+        if min.is_some() || max.is_some() {
+            checks.push(self.length(min, max)?);
         }
-        if let Some(val) = self.config.get(&ALLOWED) {
-            return self.allowed(val);
+        if let Some(val) = self.config.get(&STARTS_WITH) {
+            checks.push(self.affix(val, StrConstr::StartsWith)?);
         }
-        if let Some(val) = self.config.get(&DISALLOWED) {
-            return self.disallowed(val);
+        if let Some(val) = self.config.get(&ENDS_WITH) {
+            checks.push(self.affix(val, StrConstr::EndsWith)?);
         }
-        if let Some(val) = self.config.get(&EQ) {
-            return self.eq(val);
+        if let Some(val) = self.config.get(&CONTAINS) {
+            checks.push(self.affix(val, StrConstr::Contains)?);
         }
-        if let Some(val) = self.config.get(&NEQ) {
-            return self.neq(val);
+        if let Some(val) = self.config.get(&FORMAT) {
+            checks.push(self.format(val)?);
         }
-        if self.default.is_some() {
-            return Ok(StringConstraint::new(self.field_name, StrConstr::Any, self.default));
+
+        let constr = match primary {
+            Some(constr) => constr,
+            None if checks.is_empty() => StrConstr::Any,
+            None => checks.remove(0),
+        };
+        Ok(StringConstraint {
+            field_name: self.field_name,
+            constr,
+            checks,
+            default: self.default,
+            options: self.options.clone(),
+            normalize: self.normalize,
+        })
+    }
+
+    fn length(&self, min: Option<&'a Value>, max: Option<&'a Value>) -> Result<StrConstr<'a>, ParseErr<'a>> {
+        lazy_static! {
+            static ref LENGTH_UNIT: Value = valstr!("length_unit");
         }
-        Ok(StringConstraint::default(self.field_name))
+        let bound = |val: Option<&'a Value>| match val {
+            Some(val) => ValueRef::<Number>::new(val)
+                .map(Some)
+                .map_err(|err| ParseErr::new(self.path, err)),
+            None => Ok(None),
+        };
+        let unit = match self.config.get(&LENGTH_UNIT) {
+            None => LengthUnit::Chars,
+            Some(Value::String(u)) if u == "chars" => LengthUnit::Chars,
+            Some(Value::String(u)) if u == "graphemes" => LengthUnit::Graphemes,
+            Some(other) => return Err(ParseErr::new(self.path, PEType::IncorrectType(other))),
+        };
+        Ok(StrConstr::Length { min: bound(min)?, max: bound(max)?, unit })
     }
 
-    fn regex(&self, re: &'a Value) -> Result<StringConstraint<'a>, ParseErr<'a>> {
+    fn affix(&self, val: &'a Value, constr: fn(ValueRef<'a, String>) -> StrConstr<'a>) -> Result<StrConstr<'a>, ParseErr<'a>> {
+        match ValueRef::<String>::new(val) {
+            Ok(vr) => Ok(constr(vr)),
+            Err(err) => Err(ParseErr::new(self.path, err)),
+        }
+    }
+
+    fn format(&self, format: &'a Value) -> Result<StrConstr<'a>, ParseErr<'a>> {
+        lazy_static! {
+            static ref SCHEMES: Value = valstr!("schemes");
+        }
+        let schemes = match self.config.get(&SCHEMES) {
+            Some(Value::Sequence(seq)) => Some(seq.iter()
+                .map(|s| match s {
+                    Value::String(s) => Ok(s),
+                    other => Err(ParseErr::new(self.path, PEType::IncorrectType(other))),
+                })
+                .collect::<Result<_, _>>()?),
+            Some(other) => return Err(ParseErr::new(self.path, PEType::IncorrectType(other))),
+            None => None,
+        };
+        match format {
+            Value::String(name) => match Format::from_name(name, schemes) {
+                Some(format) => Ok(StrConstr::Format(format)),
+                None => Err(ParseErr::new(self.path, PEType::UnknownFormat(name))),
+            },
+            _ => Err(ParseErr::new(self.path, PEType::IncorrectType(format))),
+        }
+    }
+
+    fn regex(&self, re: &'a Value) -> Result<StrConstr<'a>, ParseErr<'a>> {
         if let Value::String(re) = re {
-            match Regex::new(re) {
-                Ok(regex) => Ok(StringConstraint { 
-                    field_name: self.field_name,
-                    constr: StrConstr::Regex(Box::new(WrappedRegex(regex))),
-                    default: self.default,
-                }),
+            let options = self.regex_options()?;
+            // `\A` and `\z` rather than `^` and `$`, which move with `multiline`
+            let anchored = match options.full {
+                true => Cow::Owned(format!("\\A(?:{})\\z", re)),
+                false => Cow::Borrowed(re.as_str()),
+            };
+            let mut builder = RegexBuilder::new(&anchored);
+            builder.case_insensitive(options.case_insensitive)
+                .multi_line(options.multiline)
+                .dot_matches_new_line(options.dot_all);
+            if let Some(limit) = options.size_limit {
+                builder.size_limit(limit);
+            }
+            match builder.build() {
+                Ok(regex) => {
+                    let wrapped = WrappedRegex { regex, pattern: re.clone(), options };
+                    Ok(StrConstr::Regex(Box::new(wrapped)))
+                }
+                Err(regex::Error::CompiledTooBig(limit)) => {
+                    Err(ParseErr::new(self.path, PEType::RegexSizeLimit { source: re, limit }))
+                }
                 Err(e) => Err(ParseErr::new(self.path, PEType::Regex(e)))
             }
         } else {
@@ -128,11 +362,45 @@ impl<'a, 'b> StringConstraintBuilder<'a, 'b> {
         }
     }
 
-    fn allowed(&self, allowed: &'a Value) -> Result<StringConstraint<'a>, ParseErr<'a>> {
+    fn regex_options(&self) -> Result<RegexOptions, ParseErr<'a>> {
+        lazy_static! {
+            static ref MATCH: Value = valstr!("match");
+            static ref FLAGS: Value = valstr!("flags");
+            static ref SIZE_LIMIT: Value = valstr!("size_limit");
+        }
+        let mut options = RegexOptions { case_insensitive: self.normalize.case_insensitive, ..Default::default() };
+        match self.config.get(&MATCH) {
+            None => {}
+            Some(Value::String(m)) if m == "full" => options.full = true,
+            Some(Value::String(m)) if m == "partial" => options.full = false,
+            Some(other) => return Err(ParseErr::new(self.path, PEType::IncorrectType(other))),
+        }
+        let flags = match self.config.get(&FLAGS) {
+            None => &[][..],
+            Some(Value::Sequence(seq)) => seq.as_slice(),
+            Some(other) => return Err(ParseErr::new(self.path, PEType::IncorrectType(other))),
+        };
+        for flag in flags {
+            match flag {
+                Value::String(f) if f == "multiline" => options.multiline = true,
+                Value::String(f) if f == "dot_all" => options.dot_all = true,
+                Value::String(f) if f == "case_insensitive" => options.case_insensitive = true,
+                other => return Err(ParseErr::new(self.path, PEType::IncorrectType(other))),
+            }
+        }
+        options.size_limit = match self.config.get(&SIZE_LIMIT) {
+            None => None,
+            Some(Value::Number(n)) if n.as_u64().is_some() => n.as_u64().map(|n| n as usize),
+            Some(other) => return Err(ParseErr::new(self.path, PEType::IncorrectType(other))),
+        };
+        Ok(options)
+    }
+
+    fn allowed(&self, allowed: &'a Value) -> Result<StrConstr<'a>, ParseErr<'a>> {
         if let Value::Sequence(seq) = allowed {
-            let res = seq.iter().map(ValueRef::new).collect();
+            let res = seq.iter().map(ValueRef::<String>::new).collect();
             match res {
-                Ok(vals) => Ok(StringConstraint::new(self.field_name, StrConstr::Allowed(vals), self.default)),
+                Ok(vals) => Ok(StrConstr::Allowed(vals)),
                 Err(err) => Err(ParseErr::new(self.path, err)),
             }
         } else {
@@ -140,11 +408,11 @@ impl<'a, 'b> StringConstraintBuilder<'a, 'b> {
         }
     }
 
-    fn disallowed(&self, disallowed: &'a Value) -> Result<StringConstraint<'a>, ParseErr<'a>> {
+    fn disallowed(&self, disallowed: &'a Value) -> Result<StrConstr<'a>, ParseErr<'a>> {
         if let Value::Sequence(seq) = disallowed {
-            let res = seq.iter().map(ValueRef::new).collect();
+            let res = seq.iter().map(ValueRef::<String>::new).collect();
             match res {
-                Ok(vals) => Ok(StringConstraint::new(self.field_name, StrConstr::Disallowed(vals), self.default)),
+                Ok(vals) => Ok(StrConstr::Disallowed(vals)),
                 Err(err) => Err(ParseErr::new(self.path, err)),
             }
         } else {
@@ -152,16 +420,16 @@ impl<'a, 'b> StringConstraintBuilder<'a, 'b> {
         }
     }
 
-    fn eq(&self, to: &'a Value) -> Result<StringConstraint<'a>, ParseErr<'a>> {
-        match ValueRef::new(to) {
-            Ok(vr) => Ok(StringConstraint::new(self.field_name, StrConstr::Equals(vr), self.default)),
+    fn eq(&self, to: &'a Value) -> Result<StrConstr<'a>, ParseErr<'a>> {
+        match ValueRef::<String>::new(to) {
+            Ok(vr) => Ok(StrConstr::Equals(vr)),
             Err(err) => Err(ParseErr::new(self.path, err)),
         }
     }
 
-    fn neq(&self, to: &'a Value) -> Result<StringConstraint<'a>, ParseErr<'a>> {
-        match ValueRef::new(to) {
-            Ok(vr) => Ok(StringConstraint::new(self.field_name, StrConstr::NotEquals(vr), self.default)),
+    fn neq(&self, to: &'a Value) -> Result<StrConstr<'a>, ParseErr<'a>> {
+        match ValueRef::<String>::new(to) {
+            Ok(vr) => Ok(StrConstr::NotEquals(vr)),
             Err(err) => Err(ParseErr::new(self.path, err)),
         }
     }
@@ -183,11 +451,20 @@ pub fn build<'a>(field_name: &'a Value, map: &'a Mapping, path: &[&'a Value]) ->
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum StrRule<'a> {
-    Allowed(Vec<&'a String>),
-    Disallowed(Vec<&'a String>),
+    Allowed(Vec<&'a str>),
+    Disallowed(Vec<&'a str>),
     Regex(Box<WrappedRegex>),
-    Equals(&'a String),
-    NotEquals(&'a String),
+    Equals(&'a str),
+    NotEquals(&'a str),
+    Length {
+        min: Option<&'a Number>,
+        max: Option<&'a Number>,
+        unit: LengthUnit,
+    },
+    StartsWith(&'a str),
+    EndsWith(&'a str),
+    Contains(&'a str),
+    Format(Format<'a>),
     Any,
 }
 
@@ -195,24 +472,33 @@ impl<'a> StrRule<'a> {
     pub fn new(constr: StrConstr<'a>, root: &'a Value, context: &Constraint<'a>) -> Result<StrRule<'a>, ValueResolutionErr<'a>> {
         match constr {
             StrConstr::Allowed(v) => {
-                let resolved: Result<_,_> = v.iter().map(|val| val.resolve(root, context)).collect();
+                let resolved: Result<_,_> = v.iter().map(|val| val.resolve_str(root, context)).collect();
                 Ok(StrRule::Allowed(resolved?))
             }
             StrConstr::Disallowed(v) => {
-                let resolved: Result<_,_> = v.iter().map(|val| val.resolve(root, context)).collect();
+                let resolved: Result<_,_> = v.iter().map(|val| val.resolve_str(root, context)).collect();
                 Ok(StrRule::Disallowed(resolved?))
             }
             StrConstr::Regex(re) => {
                 Ok(StrRule::Regex(Box::new(*re)))
             }
             StrConstr::Equals(vr) => {
-                let resolved = vr.resolve(root, context)?;
+                let resolved = vr.resolve_str(root, context)?;
                 Ok(StrRule::Equals(resolved))
             }
             StrConstr::NotEquals(vr) => {
-                let resolved = vr.resolve(root, context)?;
+                let resolved = vr.resolve_str(root, context)?;
                 Ok(StrRule::NotEquals(resolved))
             }
+            StrConstr::Length { min, max, unit } => {
+                let min = min.map(|vr| vr.resolve(root, context)).transpose()?;
+                let max = max.map(|vr| vr.resolve(root, context)).transpose()?;
+                Ok(StrRule::Length { min, max, unit })
+            }
+            StrConstr::StartsWith(vr) => Ok(StrRule::StartsWith(vr.resolve_str(root, context)?)),
+            StrConstr::EndsWith(vr) => Ok(StrRule::EndsWith(vr.resolve_str(root, context)?)),
+            StrConstr::Contains(vr) => Ok(StrRule::Contains(vr.resolve_str(root, context)?)),
+            StrConstr::Format(format) => Ok(StrRule::Format(format)),
             StrConstr::Any => {
                 Ok(StrRule::Any)
             }
@@ -224,7 +510,10 @@ impl<'a> StrRule<'a> {
 pub struct StringRule<'a> {
     pub field_name: &'a Value,
     rule: StrRule<'a>,
+    checks: Vec<StrRule<'a>>,
     pub default: Option<&'a Value>,
+    pub options: FieldOptions,
+    normalize: Normalize,
 }
 
 impl<'a> From<StringRule<'a>> for Rule<'a> {
@@ -235,48 +524,105 @@ impl<'a> From<StringRule<'a>> for Rule<'a> {
 
 impl<'a> StringRule<'a> {
     pub fn new(constraint: StringConstraint<'a>, root: &'a Value, context: &Constraint<'a>) -> Result<StringRule<'a>, ValueResolutionErr<'a>> {
-        match StrRule::new(constraint.constr, root, context) {
-            Ok(rule) => Ok(StringRule {
-                field_name: constraint.field_name,
-                rule,
-                default: constraint.default,
-            }),
-            Err(v) => Err(v)
-        }
+        let rule = StrRule::new(constraint.constr, root, context)?;
+        let checks = constraint.checks.into_iter()
+            .map(|check| StrRule::new(check, root, context))
+            .collect::<Result<_, _>>()?;
+        Ok(StringRule {
+            field_name: constraint.field_name,
+            rule,
+            checks,
+            default: constraint.default,
+            options: constraint.options,
+            normalize: constraint.normalize,
+        })
     }
 
+    /// The first rule the value fails is the one that's reported
     pub fn eval(&self, value: &'a Value, path: &[&'a Value]) -> RuleEvalResult<'a> {
-        if let Value::String(x) = value {
-            match &self.rule {
-                StrRule::Allowed(list) => {
-                    RuleEvalSuccess::new(list.contains(&x), path).into()
+        if let Value::String(raw) = value {
+            std::iter::once(&self.rule).chain(&self.checks)
+                .map(|rule| self.check(rule, raw, path))
+                .find(|result| !result.result)
+                .unwrap_or_else(|| RuleEvalSuccess::new(true, path))
+                .into()
+        } else {
+            RuleEvalErr::new(path, RuleErrType::IncorrectType(value)).into()
+        }
+    }
+
+    fn check(&self, rule: &StrRule<'a>, raw: &'a str, path: &[&'a Value]) -> RuleEvalSuccess<'a> {
+        let norm = |s: &'a str| self.normalize.apply(s);
+        let x = &norm(raw);
+        match rule {
+            StrRule::Allowed(list) => {
+                RuleEvalSuccess::new(list.iter().any(|s| &norm(s) == x), path)
+            }
+            StrRule::Disallowed(list) => {
+                RuleEvalSuccess::new(!list.iter().any(|s| &norm(s) == x), path)
+            }
+            StrRule::Regex(re) => {
+                match re.is_match(&self.normalize.apply_for_regex(raw)) {
+                    true => RuleEvalSuccess::new(true, path),
+                    false => {
+                        let reason = Reason::NoMatch { pattern: re.pattern.clone(), options: re.options };
+                        RuleEvalSuccess::failed(path, reason)
+                    }
                 }
-                StrRule::Disallowed(list) => {
-                    RuleEvalSuccess::new(!list.contains(&x), path).into()
+            }
+            StrRule::Equals(other) => {
+                RuleEvalSuccess::new(x == &norm(other), path)
+            }
+            StrRule::NotEquals(other) => {
+                RuleEvalSuccess::new(x != &norm(other), path)
+            }
+            StrRule::Length { min, max, unit } => {
+                let length = unit.measure(x);
+                // bounds are compared as floats, so fractional bounds still work
+                let as_f64 = |n: &Number| n.as_f64().unwrap_or(f64::NAN);
+                match (min, max) {
+                    (Some(min), _) if (length as f64) < as_f64(min) => {
+                        RuleEvalSuccess::failed(path, Reason::TooShort { length, min })
+                    }
+                    (_, Some(max)) if (length as f64) > as_f64(max) => {
+                        RuleEvalSuccess::failed(path, Reason::TooLong { length, max })
+                    }
+                    _ => RuleEvalSuccess::new(true, path),
                 }
-                StrRule::Regex(re) => {
-                    RuleEvalSuccess::new(re.is_match(x), path).into()
+            }
+            StrRule::StartsWith(prefix) => {
+                match x.starts_with(&*norm(prefix)) {
+                    true => RuleEvalSuccess::new(true, path),
+                    false => RuleEvalSuccess::failed(path, Reason::MissingPrefix(prefix)),
                 }
-                StrRule::Equals(other) => {
-                    RuleEvalSuccess::new(x == *other, path).into()
+            }
+            StrRule::EndsWith(suffix) => {
+                match x.ends_with(&*norm(suffix)) {
+                    true => RuleEvalSuccess::new(true, path),
+                    false => RuleEvalSuccess::failed(path, Reason::MissingSuffix(suffix)),
                 }
-                StrRule::NotEquals(other) => {
-                    RuleEvalSuccess::new(x != *other, path).into()
+            }
+            StrRule::Contains(part) => {
+                match x.contains(&*norm(part)) {
+                    true => RuleEvalSuccess::new(true, path),
+                    false => RuleEvalSuccess::failed(path, Reason::MissingSubstring(part)),
                 }
-                StrRule::Any => {
-                    RuleEvalSuccess::new(true, path).into()
+            }
+            StrRule::Format(format) => {
+                match format.check(&self.normalize.apply_for_regex(raw)) {
+                    Ok(()) => RuleEvalSuccess::new(true, path),
+                    Err(err) => RuleEvalSuccess::failed(path, Reason::Format { format: format.name(), err }),
                 }
             }
-        } else {
-            RuleEvalErr::new(path, RuleErrType::IncorrectType(value)).into()
+            StrRule::Any => {
+                RuleEvalSuccess::new(true, path)
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use serde_yaml::Number;
-
     use super::*;
     use crate::lit;
     use crate::valstr;
@@ -289,7 +635,7 @@ mod tests {
         );
         let map: Mapping = serde_yaml::from_str(raw).unwrap();
         let name = valstr!("f");
-        let acutal = build(&name, &map, &vec![]);
+        let acutal = build(&name, &map, &[]);
         if let Ok(string_constraint) = acutal {
             assert_eq!(string_constraint.field_name, &valstr!("f"));
             assert_eq!(string_constraint.constr, StrConstr::Equals(lit!("hello")));
@@ -307,7 +653,7 @@ mod tests {
         );
         let map: Mapping = serde_yaml::from_str(raw).unwrap();
         let name = valstr!("f");
-        let acutal = build(&name, &map, &vec![]);
+        let acutal = build(&name, &map, &[]);
         if let Err(pe) = acutal {
             assert_eq!(pe, ParseErr {
                 err: PEType::IncorrectType(&Value::Number(Number::from(7))),
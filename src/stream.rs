@@ -0,0 +1,170 @@
+use serde_yaml::{Mapping, Value};
+
+use crate::{Evaluation, yamlfmt};
+
+/// A single document pulled out of a `---`-separated YAML stream. The index
+/// only counts documents with content, and the line is 1-based and points at
+/// the document's `---` marker (or its first line, if the marker was omitted).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub index: usize,
+    pub line: usize,
+    pub value: Value,
+}
+
+#[derive(Debug)]
+pub struct StreamErr {
+    pub index: usize,
+    pub line: usize,
+    pub err: serde_yaml::Error,
+}
+
+/// Splits a raw YAML stream on its document markers and deserializes each
+/// document separately. Documents that contain nothing but comments or blank
+/// lines (like the preamble in front of the first `---`) are skipped.
+pub fn documents(raw: &str) -> Result<Vec<Document>, StreamErr> {
+    let mut chunks: Vec<(usize, Vec<&str>)> = vec![(1, vec![])];
+    for (n, line) in raw.lines().enumerate() {
+        if is_marker(line) {
+            chunks.push((n + 1, vec![]));
+        }
+        if let Some((_, lines)) = chunks.last_mut() {
+            lines.push(line);
+        }
+    }
+    let mut docs = vec![];
+    for (line, lines) in chunks.into_iter().filter(|(_, lines)| has_content(lines)) {
+        let index = docs.len();
+        match serde_yaml::from_str(&lines.join("\n")) {
+            Ok(value) => docs.push(Document { index, line, value }),
+            Err(err) => return Err(StreamErr { index, line, err }),
+        }
+    }
+    Ok(docs)
+}
+
+fn is_marker(line: &str) -> bool {
+    match line.strip_prefix("---") {
+        Some(rest) => rest.is_empty() || rest.starts_with(char::is_whitespace),
+        None => false,
+    }
+}
+
+fn has_content(lines: &[&str]) -> bool {
+    lines.iter()
+        .map(|line| line.trim())
+        .map(|line| line.strip_prefix("---").unwrap_or(line).trim_start())
+        .any(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectionErr<'a> {
+    /// the document doesn't have the discriminator key, or it isn't a mapping
+    MissingDiscriminator(&'a Value),
+    UnknownDiscriminator {
+        found: &'a Value,
+        valid: Vec<&'a Value>,
+    },
+}
+
+#[derive(Debug)]
+pub struct DocumentEvaluation<'a> {
+    pub index: usize,
+    pub line: usize,
+    pub eval: Result<Evaluation<'a>, SelectionErr<'a>>,
+}
+
+/// Evaluates every document in the stream against the same spec
+pub fn yamlfmt_stream<'a>(spec: &'a Mapping, docs: &'a [Document], name: &'a Value) -> Vec<DocumentEvaluation<'a>> {
+    docs.iter()
+        .map(|doc| DocumentEvaluation {
+            index: doc.index,
+            line: doc.line,
+            eval: Ok(yamlfmt(spec, &doc.value, name)),
+        })
+        .collect()
+}
+
+/// Evaluates every document in the stream against the spec registered for the
+/// value of its `discriminator` field, e.g. `kind` for Kubernetes manifests.
+pub fn yamlfmt_stream_by<'a>(
+    discriminator: &'a Value,
+    specs: &[(&'a Value, &'a Mapping)],
+    docs: &'a [Document],
+    name: &'a Value,
+) -> Vec<DocumentEvaluation<'a>> {
+    docs.iter()
+        .map(|doc| DocumentEvaluation {
+            index: doc.index,
+            line: doc.line,
+            eval: select(discriminator, specs, &doc.value).map(|spec| yamlfmt(spec, &doc.value, name)),
+        })
+        .collect()
+}
+
+fn select<'a>(discriminator: &'a Value, specs: &[(&'a Value, &'a Mapping)], doc: &'a Value) -> Result<&'a Mapping, SelectionErr<'a>> {
+    let found = match doc {
+        Value::Mapping(m) => m.get(discriminator).ok_or(SelectionErr::MissingDiscriminator(discriminator))?,
+        _ => return Err(SelectionErr::MissingDiscriminator(discriminator)),
+    };
+    specs.iter()
+        .find(|(tag, _)| *tag == found)
+        .map(|(_, spec)| *spec)
+        .ok_or_else(|| SelectionErr::UnknownDiscriminator {
+            found,
+            valid: specs.iter().map(|(tag, _)| *tag).collect(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::valstr;
+
+    #[test]
+    fn split_stream() {
+        let raw = concat!(
+            "# a comment before the first document\n",
+            "---\n",
+            "kind: Service\n",
+            "--- \n",
+            "# nothing but a comment\n",
+            "---\n",
+            "kind: Deployment\n",
+            "name: web\n",
+        );
+        let docs = documents(raw).unwrap();
+        assert_eq!(docs.len(), 2);
+        assert_eq!((docs[0].index, docs[0].line), (0, 2));
+        assert_eq!((docs[1].index, docs[1].line), (1, 6));
+        if let Value::Mapping(m) = &docs[1].value {
+            assert_eq!(m.get(&valstr!("name")), Some(&valstr!("web")));
+        } else {
+            panic!("document should be a mapping")
+        }
+    }
+
+    #[test]
+    fn implicit_first_document() {
+        let raw = concat!(
+            "kind: Service\n",
+            "---\n",
+            "kind: Deployment\n",
+        );
+        let docs = documents(raw).unwrap();
+        assert_eq!(docs.len(), 2);
+        assert_eq!((docs[0].index, docs[0].line), (0, 1));
+        assert_eq!((docs[1].index, docs[1].line), (1, 2));
+    }
+
+    #[test]
+    fn parse_error_names_document() {
+        let raw = concat!(
+            "kind: Service\n",
+            "---\n",
+            "kind: [Deployment\n",
+        );
+        let err = documents(raw).unwrap_err();
+        assert_eq!((err.index, err.line), (1, 2));
+    }
+}
@@ -0,0 +1,428 @@
+use lazy_static::lazy_static;
+use serde_yaml::{Mapping, Value};
+use std::fmt;
+
+use crate::{constraint::Constraint, options::FieldOptions, parse::{PEType, ParseErr}, rule::{Reason, Rule, RuleErrType, RuleEvalErr, RuleEvalResult, RuleEvalSuccess}, valstr, value_ref::{ValueRef, ValueResolutionErr}};
+
+/// RFC 3339 dates and times. YAML timestamps come through as strings, so both
+/// spellings are handled the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// `2024-03-01T12:00:00Z`
+    DateTime,
+    /// `2024-03-01`
+    Date,
+    /// `12:00:00` or `12:00:00+02:00`
+    Time,
+}
+
+/// Whether a value has to spell out its UTC offset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timezone {
+    Any,
+    Required,
+    Forbidden,
+    Utc,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemporalErr {
+    Syntax(&'static str),
+    Month(u32),
+    Day { day: u32, month: u32 },
+    Hour(u32),
+    Minute(u32),
+    Second(u32),
+    Offset(String),
+    MissingTimezone,
+    UnexpectedTimezone,
+    NotUtc,
+    NotAString,
+}
+
+impl fmt::Display for TemporalErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemporalErr::Syntax(expected) => write!(f, "expected {}", expected),
+            TemporalErr::Month(m) => write!(f, "month {} is not between 1 and 12", m),
+            TemporalErr::Day { day, month } => write!(f, "day {} doesn't exist in month {}", day, month),
+            TemporalErr::Hour(h) => write!(f, "hour {} is not between 0 and 23", h),
+            TemporalErr::Minute(m) => write!(f, "minute {} is not between 0 and 59", m),
+            TemporalErr::Second(s) => write!(f, "second {} is not between 0 and 60", s),
+            TemporalErr::Offset(o) => write!(f, "{:?} is not a valid UTC offset", o),
+            TemporalErr::MissingTimezone => write!(f, "missing a timezone offset like `Z` or `+02:00`"),
+            TemporalErr::UnexpectedTimezone => write!(f, "timezone offsets aren't allowed"),
+            TemporalErr::NotUtc => write!(f, "has to be in UTC (`Z` or `+00:00`)"),
+            TemporalErr::NotAString => write!(f, "expected a string"),
+        }
+    }
+}
+
+/// A parsed value, as nanoseconds since the epoch (`DateTime`), days since the
+/// epoch (`Date`), or nanoseconds since midnight (`Time`). Anything with an
+/// offset is shifted to UTC first; values without one are compared as if they
+/// were already in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant(i128);
+
+struct Parsed {
+    instant: Instant,
+    /// the offset in minutes, if there was one
+    offset: Option<i32>,
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::DateTime => "datetime",
+            Kind::Date => "date",
+            Kind::Time => "time",
+        }
+    }
+
+    pub fn parse(&self, s: &str, timezone: Timezone) -> Result<Instant, TemporalErr> {
+        let parsed = match self {
+            Kind::DateTime => datetime(s)?,
+            Kind::Date => Parsed { instant: Instant(date(s)? as i128), offset: None },
+            Kind::Time => time(s)?,
+        };
+        match (timezone, parsed.offset) {
+            (Timezone::Required, None) | (Timezone::Utc, None) => Err(TemporalErr::MissingTimezone),
+            (Timezone::Forbidden, Some(_)) => Err(TemporalErr::UnexpectedTimezone),
+            (Timezone::Utc, Some(offset)) if offset != 0 => Err(TemporalErr::NotUtc),
+            _ => Ok(parsed.instant),
+        }
+    }
+
+    pub fn value(&self, value: &Value, timezone: Timezone) -> Result<Instant, TemporalErr> {
+        match value {
+            Value::String(s) => self.parse(s, timezone),
+            _ => Err(TemporalErr::NotAString),
+        }
+    }
+}
+
+/// Reads exactly `digits` ASCII digits off the front of `s`
+fn number<'s>(s: &'s str, digits: usize, expected: &'static str) -> Result<(u32, &'s str), TemporalErr> {
+    match s.get(..digits) {
+        Some(n) if n.chars().all(|c| c.is_ascii_digit()) => Ok((n.parse().unwrap(), &s[digits..])),
+        _ => Err(TemporalErr::Syntax(expected)),
+    }
+}
+
+fn separator<'s>(s: &'s str, sep: char, expected: &'static str) -> Result<&'s str, TemporalErr> {
+    s.strip_prefix(sep).ok_or(TemporalErr::Syntax(expected))
+}
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn full_date(s: &str) -> Result<(i64, &str), TemporalErr> {
+    const EXPECTED: &str = "a date like `2024-03-01`";
+    let (year, rest) = number(s, 4, EXPECTED)?;
+    let (month, rest) = number(separator(rest, '-', EXPECTED)?, 2, EXPECTED)?;
+    let (day, rest) = number(separator(rest, '-', EXPECTED)?, 2, EXPECTED)?;
+    if !(1..=12).contains(&month) {
+        return Err(TemporalErr::Month(month));
+    }
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if day == 0 || day > days_in_month {
+        return Err(TemporalErr::Day { day, month });
+    }
+    Ok((days_from_civil(year as i64, month, day), rest))
+}
+
+fn date(s: &str) -> Result<i64, TemporalErr> {
+    match full_date(s)? {
+        (days, "") => Ok(days),
+        _ => Err(TemporalErr::Syntax("nothing after the date")),
+    }
+}
+
+fn time(s: &str) -> Result<Parsed, TemporalErr> {
+    const EXPECTED: &str = "a time like `12:30:00`";
+    let (hour, rest) = number(s, 2, EXPECTED)?;
+    let (minute, rest) = number(separator(rest, ':', EXPECTED)?, 2, EXPECTED)?;
+    let (second, mut rest) = number(separator(rest, ':', EXPECTED)?, 2, EXPECTED)?;
+    if hour > 23 {
+        return Err(TemporalErr::Hour(hour));
+    }
+    if minute > 59 {
+        return Err(TemporalErr::Minute(minute));
+    }
+    // 60 is a leap second
+    if second > 60 {
+        return Err(TemporalErr::Second(second));
+    }
+    let mut nanos = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let end = fraction.find(|c: char| !c.is_ascii_digit()).unwrap_or(fraction.len());
+        if end == 0 {
+            return Err(TemporalErr::Syntax("digits after the decimal point"));
+        }
+        // anything past nanoseconds is dropped
+        let digits = &fraction[..end.min(9)];
+        nanos = digits.parse::<i128>().unwrap() * 10i128.pow(9 - digits.len() as u32);
+        rest = &fraction[end..];
+    }
+    let offset = offset(rest)?;
+    let seconds = (hour * 3600 + minute * 60 + second) as i128 - offset.unwrap_or(0) as i128 * 60;
+    Ok(Parsed { instant: Instant(seconds * 1_000_000_000 + nanos), offset })
+}
+
+fn offset(s: &str) -> Result<Option<i32>, TemporalErr> {
+    let invalid = || TemporalErr::Offset(s.to_string());
+    match s {
+        "" => Ok(None),
+        "Z" | "z" => Ok(Some(0)),
+        _ => {
+            let sign = match s.chars().next() {
+                Some('+') => 1,
+                Some('-') => -1,
+                _ => return Err(invalid()),
+            };
+            let (hours, rest) = number(&s[1..], 2, "").map_err(|_| invalid())?;
+            let (minutes, rest) = number(separator(rest, ':', "").map_err(|_| invalid())?, 2, "").map_err(|_| invalid())?;
+            if !rest.is_empty() || hours > 23 || minutes > 59 {
+                return Err(invalid());
+            }
+            Ok(Some(sign * (hours * 60 + minutes) as i32))
+        }
+    }
+}
+
+fn datetime(s: &str) -> Result<Parsed, TemporalErr> {
+    let (days, rest) = full_date(s)?;
+    let rest = match rest.chars().next() {
+        Some('T') | Some('t') | Some(' ') => &rest[1..],
+        _ => return Err(TemporalErr::Syntax("a `T` between the date and the time")),
+    };
+    let time = time(rest)?;
+    let instant = Instant(days as i128 * 86_400_000_000_000 + time.instant.0);
+    Ok(Parsed { instant, offset: time.offset })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemporalConstraint<'a> {
+    pub field_name: &'a Value,
+    pub kind: Kind,
+    pub timezone: Timezone,
+    pub after: Option<ValueRef<'a, Value>>,
+    pub before: Option<ValueRef<'a, Value>>,
+    pub default: Option<&'a Value>,
+    pub options: FieldOptions,
+}
+
+impl<'a> TemporalConstraint<'a> {
+    pub fn default(field_name: &Value, kind: Kind) -> TemporalConstraint<'_> {
+        TemporalConstraint {
+            field_name,
+            kind,
+            timezone: Timezone::Any,
+            after: None,
+            before: None,
+            default: None,
+            options: FieldOptions::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TemporalConstraintBuilder<'a, 'b> {
+    field_name: &'a Value,
+    kind: Kind,
+    config: &'a Mapping,
+    path: &'b [&'a Value],
+}
+
+impl<'a, 'b> TemporalConstraintBuilder<'a, 'b> {
+    fn new(field_name: &'a Value, kind: Kind, config: &'a Mapping, path: &'b [&'a Value]) -> Self {
+        Self { field_name, kind, config, path }
+    }
+
+    fn timezone(&self) -> Result<Timezone, ParseErr<'a>> {
+        lazy_static! {
+            static ref TIMEZONE: Value = valstr!("timezone");
+        }
+        let value = self.config.get(&TIMEZONE);
+        let timezone = match value {
+            None => Timezone::Any,
+            Some(Value::String(tz)) if tz == "required" => Timezone::Required,
+            Some(Value::String(tz)) if tz == "forbidden" => Timezone::Forbidden,
+            Some(Value::String(tz)) if tz == "utc" => Timezone::Utc,
+            Some(other) => return Err(ParseErr::new(self.path, PEType::IncorrectType(other))),
+        };
+        // a date can't carry an offset, so nothing would ever pass
+        if let (Kind::Date, Some(Value::String(tz))) = (self.kind, value) {
+            if timezone == Timezone::Required || timezone == Timezone::Utc {
+                let mut path = self.path.to_vec();
+                path.push(&TIMEZONE);
+                return Err(ParseErr::new(&path, PEType::Temporal { source: tz, err: TemporalErr::UnexpectedTimezone }));
+            }
+        }
+        Ok(timezone)
+    }
+
+    /// Bounds don't have to meet the field's own timezone requirement
+    fn bound(&self, val: Option<&'a Value>) -> Result<Option<ValueRef<'a, Value>>, ParseErr<'a>> {
+        match val {
+            None => Ok(None),
+            Some(Value::Sequence(path)) => ValueRef::abs_path(path).map(Some).map_err(|e| ParseErr::new(self.path, e)),
+            Some(val @ Value::String(s)) => match self.kind.parse(s, Timezone::Any) {
+                Ok(_) => Ok(Some(ValueRef::Literal(val))),
+                Err(err) => Err(ParseErr::new(self.path, PEType::Temporal { source: s, err })),
+            },
+            Some(other) => Err(ParseErr::new(self.path, PEType::IncorrectType(other))),
+        }
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    fn from_mapping(&self) -> Result<TemporalConstraint<'a>, ParseErr<'a>> {
+        lazy_static! {
+            static ref DEFAULT: Value = valstr!("default");
+            static ref AFTER: Value = valstr!("after");
+            static ref BEFORE: Value = valstr!("before");
+        }
+        let timezone = self.timezone()?;
+        let default = match self.config.get(&DEFAULT) {
+            Some(val) if self.kind.value(val, timezone).is_ok() => Some(val),
+            Some(val) => return Err(ParseErr::new(self.path, PEType::InvalidDefault { default: val, failures: vec![] })),
+            None => None,
+        };
+        Ok(TemporalConstraint {
+            field_name: self.field_name,
+            kind: self.kind,
+            timezone,
+            after: self.bound(self.config.get(&AFTER))?,
+            before: self.bound(self.config.get(&BEFORE))?,
+            default,
+            options: FieldOptions::parse(self.config, self.path)?,
+        })
+    }
+}
+
+pub fn build<'a>(field_name: &'a Value, kind: Kind, map: &'a Mapping, path: &[&'a Value]) -> Result<TemporalConstraint<'a>, ParseErr<'a>> {
+    TemporalConstraintBuilder::new(field_name, kind, map, path).from_mapping()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemporalRule<'a> {
+    pub field_name: &'a Value,
+    kind: Kind,
+    timezone: Timezone,
+    after: Option<(&'a Value, Instant)>,
+    before: Option<(&'a Value, Instant)>,
+    pub default: Option<&'a Value>,
+    pub options: FieldOptions,
+}
+
+impl<'a> From<TemporalRule<'a>> for Rule<'a> {
+    fn from(tr: TemporalRule<'a>) -> Self {
+        Rule::Time(tr)
+    }
+}
+
+impl<'a> TemporalRule<'a> {
+    pub fn new(constraint: TemporalConstraint<'a>, root: &'a Value, context: &Constraint<'a>) -> Result<TemporalRule<'a>, ValueResolutionErr<'a>> {
+        let kind = constraint.kind;
+        let resolve = |vr: ValueRef<'a, Value>| {
+            let value = vr.resolve(root, context)?;
+            match kind.value(value, Timezone::Any) {
+                Ok(instant) => Ok((value, instant)),
+                Err(_) => Err(ValueResolutionErr::IncorrectType(value)),
+            }
+        };
+        Ok(TemporalRule {
+            field_name: constraint.field_name,
+            kind,
+            timezone: constraint.timezone,
+            after: constraint.after.map(resolve).transpose()?,
+            before: constraint.before.map(resolve).transpose()?,
+            default: constraint.default,
+            options: constraint.options,
+        })
+    }
+
+    pub fn eval(&self, value: &'a Value, path: &[&'a Value]) -> RuleEvalResult<'a> {
+        let x = match value {
+            Value::String(s) => match self.kind.parse(s, self.timezone) {
+                Ok(x) => x,
+                Err(err) => {
+                    let reason = Reason::InvalidTemporal { kind: self.kind.name(), err };
+                    return RuleEvalSuccess::failed(path, reason).into();
+                }
+            },
+            _ => return RuleEvalErr::new(path, RuleErrType::IncorrectType(value)).into(),
+        };
+        match (self.after, self.before) {
+            (Some((bound, after)), _) if x <= after => {
+                RuleEvalSuccess::failed(path, Reason::Comparison { op: "after", bound }).into()
+            }
+            (_, Some((bound, before))) if x >= before => {
+                RuleEvalSuccess::failed(path, Reason::Comparison { op: "before", bound }).into()
+            }
+            _ => RuleEvalSuccess::new(true, path).into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        assert_eq!(Kind::Date.parse("1970-01-02", Timezone::Any), Ok(Instant(1)));
+        assert_eq!(Kind::Date.parse("2024-02-29", Timezone::Any), Ok(Instant(19782)));
+        assert_eq!(Kind::Date.parse("2023-02-29", Timezone::Any), Err(TemporalErr::Day { day: 29, month: 2 }));
+        assert_eq!(Kind::Date.parse("2023-13-01", Timezone::Any), Err(TemporalErr::Month(13)));
+        assert!(Kind::Date.parse("2023-1-01", Timezone::Any).is_err());
+    }
+
+    #[test]
+    fn offsets_are_normalized() {
+        let utc = Kind::DateTime.parse("2024-03-01T10:00:00Z", Timezone::Any);
+        let cet = Kind::DateTime.parse("2024-03-01T11:00:00+01:00", Timezone::Any);
+        assert_eq!(utc, cet);
+        let early = Kind::Time.parse("09:59:59.999", Timezone::Any).unwrap();
+        let late = Kind::Time.parse("10:00:00", Timezone::Any).unwrap();
+        assert!(early < late);
+    }
+
+    #[test]
+    fn timezone_requirements() {
+        assert_eq!(Kind::DateTime.parse("2024-03-01T10:00:00", Timezone::Required), Err(TemporalErr::MissingTimezone));
+        assert_eq!(Kind::DateTime.parse("2024-03-01T10:00:00+02:00", Timezone::Utc), Err(TemporalErr::NotUtc));
+        assert_eq!(Kind::Time.parse("10:00:00Z", Timezone::Forbidden), Err(TemporalErr::UnexpectedTimezone));
+        assert_eq!(Kind::Time.parse("10:00:00+25:00", Timezone::Any), Err(TemporalErr::Offset(String::from("+25:00"))));
+    }
+
+    #[test]
+    fn dates_cant_require_a_timezone() {
+        let name = valstr!("day");
+        let timezone = valstr!("timezone");
+        for tz in &["required", "utc"] {
+            let config: Mapping = serde_yaml::from_str(&format!("type: date\ntimezone: {}\n", tz)).unwrap();
+            let expected = ParseErr::new(&[&name, &timezone], PEType::Temporal { source: tz, err: TemporalErr::UnexpectedTimezone });
+            assert_eq!(build(&name, Kind::Date, &config, &[&name]), Err(expected));
+        }
+        let config: Mapping = serde_yaml::from_str("type: date\ntimezone: forbidden\n").unwrap();
+        assert!(build(&name, Kind::Date, &config, &[&name]).is_ok());
+        let config: Mapping = serde_yaml::from_str("type: datetime\ntimezone: utc\n").unwrap();
+        assert!(build(&name, Kind::DateTime, &config, &[&name]).is_ok());
+    }
+}
@@ -1,6 +1,8 @@
+use std::fmt;
+
 use serde_yaml::{Mapping, Number, Sequence, Value};
 
-use crate::{constraint::Constraint, parse::PEType};
+use crate::{constraint::Constraint, options::{display_value, join}, parse::PEType, str::StrConstr};
 
 #[macro_export]
 macro_rules! lit {
@@ -21,7 +23,7 @@ macro_rules! lit {
 pub enum DefaultFetchErr<'a> {
     IncorrectType {
         residual_path: Vec<&'a Value>,
-        constr: Constraint<'a>
+        constr: Box<Constraint<'a>>
     },
     KeyNotFound(Vec<&'a Value>),
     ConstraintIsAny(Vec<&'a Value>),
@@ -40,15 +42,75 @@ pub enum ValueResolutionErr<'a> {
         err: DefaultFetchErr<'a>
     },
     Unimplemented,
-    MissingRequired
+    MissingRequired,
+    NotARegex(Vec<&'a Value>),
+    UnknownCaptureGroup {
+        path: Vec<&'a Value>,
+        group: &'a str,
+    },
+    NoCapture {
+        path: Vec<&'a Value>,
+        group: &'a str,
+    },
+}
+
+impl fmt::Display for DefaultFetchErr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DefaultFetchErr::IncorrectType { residual_path, constr } => {
+                write!(f, "{} is inside a {} field, which has no fields", join(residual_path, "."), constr.type_name())
+            }
+            DefaultFetchErr::KeyNotFound(path) => write!(f, "{} isn't in the spec", join(path, ".")),
+            DefaultFetchErr::ConstraintIsAny(path) => write!(f, "{} is inside an `any` field, which has no defaults", join(path, ".")),
+            DefaultFetchErr::PathIsTooShort(_) => write!(f, "the path stops at an object"),
+        }
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl fmt::Display for ValueResolutionErr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueResolutionErr::TooShort | ValueResolutionErr::TooLong => {
+                write!(f, "the reference goes through a value that isn't an object")
+            }
+            ValueResolutionErr::NotFound(path) => write!(f, "{} isn't in the document", join(path, ".")),
+            ValueResolutionErr::NonTerminalType(v) => write!(f, "{} isn't a single value", display_value(v)),
+            ValueResolutionErr::IncorrectType(v) => write!(f, "{} is the wrong type for the reference", display_value(v)),
+            ValueResolutionErr::DefaultFetch { path, err } => write!(f, "{}: no default: {}", join(path, "."), err),
+            ValueResolutionErr::Unimplemented => write!(f, "this kind of reference isn't supported"),
+            ValueResolutionErr::MissingRequired => write!(f, "the referenced field isn't set and has no default"),
+            ValueResolutionErr::NotARegex(path) => write!(f, "{} isn't a field with a regex", join(path, ".")),
+            ValueResolutionErr::UnknownCaptureGroup { path, group } => {
+                write!(f, "the regex of {} has no capture group named {}", join(path, "."), group)
+            }
+            ValueResolutionErr::NoCapture { path, group } => write!(f, "capture group {} of {} didn't match", group, join(path, ".")),
+        }
+    }
+}
+
+/// Looks a path up in the input, and falls back on the default from the spec if
+/// the input doesn't have it. Unlike `ValueRef`, the path doesn't have to live
+/// as long as the spec, which lets it be built on the fly.
+pub fn lookup<'a>(path: &[&Value], root: &'a Value, context: &Constraint<'a>) -> Option<&'a Value> {
+    let found = path.iter().try_fold(root, |curr, key| match curr {
+        Value::Mapping(m) => m.get(key),
+        _ => None,
+    });
+    found.or_else(|| context.find(path).and_then(|c| c.unconditional().default()))
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ValueRef<'a, T> {
     Literal(&'a T),
     AbsolutePath(Vec<&'a Value>)
 }
 
+impl Eq for ValueRef<'_, String> {}
+impl Eq for ValueRef<'_, Value> {}
+// serde_yaml's `Number` only implements `PartialEq`, but it's reflexive (YAML
+// only has the one NaN) in the same way that `Value` is
+impl Eq for ValueRef<'_, Number> {}
+
 impl<'a, T> ValueRef<'a, T> {
     pub fn abs_path(path: &'a [Value]) -> Result<ValueRef<'a, T>, PEType<'a>> {
         let res = path.iter()
@@ -76,25 +138,24 @@ impl<'a, T> ValueRef<'a, T> {
             ValueRef::Literal(literal) => Ok(*literal),
             ValueRef::AbsolutePath(abs_path) => {
                 let mut iter = abs_path.iter().peekable();
-                let mut curr = root;
+                let mut curr = Some(root);
                 while let Some(next) = iter.next() {
-                    if let Value::Mapping(m) = curr {
-                        if let Some(val) = m.get(next) {
-                            curr = val;
-                        }
-                    } else {
-                        return if iter.peek().is_none() {
+                    match curr {
+                        Some(Value::Mapping(m)) => curr = m.get(next),
+                        Some(_) => return if iter.peek().is_none() {
                             Err(ValueResolutionErr::TooShort)
                         } else {
                             Err(ValueResolutionErr::TooLong)
-                        };
+                        },
+                        // the value isn't in the input, so look for a default
+                        None => break,
                     }
                 }
-                if let Some(val) = to_type(curr) {
+                if let Some(val) = curr.and_then(to_type) {
                     return Ok(val);
                 }
                 match context.fetch(abs_path) {
-                    Ok(c) => if let Some(default) = from_constr(c) {
+                    Ok(c) => if let Some(default) = from_constr(c.unconditional()) {
                         match to_type(default) {
                             Some(t) => Ok(t),
                             None => Err(ValueResolutionErr::IncorrectType(default))
@@ -127,6 +188,51 @@ impl<'a> ValueRef<'a, String> {
     }
 }
 
+impl<'a> ValueRef<'a, String> {
+    /// Like `resolve`, but a path ending in `#name` picks out the named capture
+    /// group that the referenced field's regex matched, e.g. `[image, "#tag"]`
+    pub fn resolve_str(&self, root: &'a Value, context: &Constraint<'a>) -> Result<&'a str, ValueResolutionErr<'a>> {
+        let (group, field) = match self {
+            ValueRef::AbsolutePath(path) => match path.split_last() {
+                Some((Value::String(last), field)) if last.starts_with('#') => (&last[1..], field),
+                _ => return self.resolve(root, context).map(String::as_str),
+            },
+            ValueRef::Literal(literal) => return Ok(literal.as_str()),
+        };
+        let value = ValueRef::<String>::AbsolutePath(field.to_vec()).resolve(root, context)?;
+        let regex = match context.fetch(field).map(Constraint::unconditional) {
+            Ok(Constraint::Str(s)) => match &s.constr {
+                StrConstr::Regex(re) => re,
+                _ => return Err(ValueResolutionErr::NotARegex(field.to_vec())),
+            },
+            _ => return Err(ValueResolutionErr::NotARegex(field.to_vec())),
+        };
+        if !regex.capture_names().any(|name| name == Some(group)) {
+            return Err(ValueResolutionErr::UnknownCaptureGroup { path: field.to_vec(), group });
+        }
+        // the match is against the raw value, so that the capture can borrow from it
+        regex.captures(value)
+            .and_then(|c| c.name(group))
+            .map(|m| m.as_str())
+            .ok_or(ValueResolutionErr::NoCapture { path: field.to_vec(), group })
+    }
+}
+
+impl<'a> ValueRef<'a, Value> {
+    pub fn resolve(&self, root: &'a Value, context: &Constraint<'a>) -> Result<&'a Value, ValueResolutionErr<'a>> {
+        self.resolve_with(root, context, Some,
+            |c| match c {
+                Constraint::Str(s) => s.default,
+                Constraint::Obj(o) => o.default,
+                Constraint::Qty(q) => q.default,
+                Constraint::Time(t) => t.default,
+                Constraint::Any(a) => a.default,
+                _ => None,
+            }
+        )
+    }
+}
+
 impl<'a> ValueRef<'a, Number> {
     pub fn resolve(&self, root: &'a Value, context: &Constraint<'a>) -> Result<&'a Number, ValueResolutionErr<'a>> {
         self.resolve_with(root, context,
@@ -134,7 +240,8 @@ impl<'a> ValueRef<'a, Number> {
                 Value::Number(n) => Some(n),
                 _ => None,
             },
-            |c| todo!("Need to implement Number Constraint")
+            // there aren't any number fields yet, so there aren't any defaults either
+            |_| None
         )
     }
 }
@@ -146,7 +253,7 @@ impl<'a> ValueRef<'a, bool> {
                 Value::Bool(b) => Some(b),
                 _ => None,
             },
-            |c| todo!("Need to implement Boolean Constraint")
+            |_| todo!("Need to implement Boolean Constraint")
         )
     }
 }
@@ -173,7 +280,7 @@ impl<'a> ValueRef<'a, Sequence> {
                 Value::Sequence(seq) => Some(seq),
                 _ => None,
             },
-            |c| todo!("Need to implement Sequence Constraint")
+            |_| todo!("Need to implement Sequence Constraint")
         )
     }
 }
\ No newline at end of file
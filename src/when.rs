@@ -0,0 +1,213 @@
+use lazy_static::lazy_static;
+use serde_yaml::{Mapping, Value};
+
+use crate::{constraint::Constraint, parse::{PEType, ParseErr, YamlParseResult}, valstr, value_ref::ValueRef};
+
+/// A field whose constraint depends on the value of some other field. The
+/// clauses are tried in order, and the first one whose condition holds swaps in
+/// its `then` constraint. Only the last clause can have an `else`, which is
+/// used when none of them hold. Otherwise the base constraint is used.
+///
+/// A branch replaces the whole constraint (including `required` and `default`)
+/// and only inherits the field's type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConditionalConstraint<'a> {
+    pub base: Box<Constraint<'a>>,
+    pub clauses: Vec<Clause<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clause<'a> {
+    pub condition: Condition<'a>,
+    pub then: Constraint<'a>,
+    pub otherwise: Option<Constraint<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition<'a> {
+    pub field: ValueRef<'a, Value>,
+    pub test: Test<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Test<'a> {
+    Eq(&'a Value),
+    Neq(&'a Value),
+    In(Vec<&'a Value>),
+    Present(bool),
+}
+
+impl<'a> Condition<'a> {
+    /// A field that can't be resolved (because it's missing and has no
+    /// default) counts as absent rather than as an error
+    pub fn holds(&self, root: &'a Value, context: &Constraint<'a>) -> bool {
+        let found = self.field.resolve(root, context).ok();
+        match (&self.test, found) {
+            (Test::Eq(expected), Some(found)) => found == *expected,
+            (Test::Neq(expected), found) => found != Some(*expected),
+            (Test::In(expected), Some(found)) => expected.contains(&found),
+            (Test::Present(present), found) => found.is_some() == *present,
+            _ => false,
+        }
+    }
+}
+
+impl<'a> ConditionalConstraint<'a> {
+    pub fn field_name(&self) -> &'a Value {
+        self.base.field_name()
+    }
+
+    pub fn select(self, root: &'a Value, context: &Constraint<'a>) -> Constraint<'a> {
+        // only the last clause can have an `else`
+        let mut otherwise = None;
+        for clause in self.clauses {
+            if clause.condition.holds(root, context) {
+                return clause.then;
+            }
+            otherwise = clause.otherwise;
+        }
+        otherwise.unwrap_or(*self.base)
+    }
+}
+
+/// `when` takes either a single clause or a list of them
+pub fn build<'a>(
+    field_type: &'a str,
+    when: &'a Value,
+    base: YamlParseResult<'a>,
+    field_name: &'a Value,
+    parent_path: &[&'a Value],
+) -> YamlParseResult<'a> {
+    let mut path = parent_path.to_vec();
+    path.push(field_name);
+    let clauses: Vec<_> = match when {
+        Value::Mapping(_) => vec![clause(field_type, when, field_name, parent_path, &path)],
+        Value::Sequence(seq) => seq.iter()
+            .enumerate()
+            .map(|(i, c)| match clause(field_type, c, field_name, parent_path, &path) {
+                Ok(Clause { otherwise: Some(_), .. }) if i + 1 < seq.len() => Err(vec![ParseErr::new(&path, PEType::MisplacedElse)]),
+                other => other,
+            })
+            .collect(),
+        _ => return ParseErr::new(&path, PEType::IncorrectType(when)).into(),
+    };
+    match base {
+        YamlParseResult::Single(Ok(base)) if clauses.iter().all(Result::is_ok) => {
+            let clauses = clauses.into_iter().map(Result::unwrap).collect();
+            let conditional = ConditionalConstraint { base: Box::new(base), clauses };
+            Constraint::When(conditional).into()
+        }
+        base => {
+            let errors = clauses.into_iter()
+                .filter_map(Result::err)
+                .flatten()
+                .map(Err)
+                .collect::<Vec<_>>();
+            let base = base.into_iter().filter(Result::is_err).collect::<Vec<_>>();
+            YamlParseResult::Multi(base.into_iter().chain(errors).collect())
+        }
+    }
+}
+
+fn clause<'a>(
+    field_type: &'a str,
+    clause: &'a Value,
+    field_name: &'a Value,
+    parent_path: &[&'a Value],
+    path: &[&'a Value],
+) -> Result<Clause<'a>, Vec<ParseErr<'a>>> {
+    lazy_static! {
+        static ref IF: Value = valstr!("if");
+        static ref THEN: Value = valstr!("then");
+        static ref ELSE: Value = valstr!("else");
+    }
+    let clause = match clause {
+        Value::Mapping(m) => m,
+        _ => return Err(vec![ParseErr::new(path, PEType::IncorrectType(clause))]),
+    };
+    let condition = match clause.get(&IF) {
+        Some(Value::Mapping(m)) => condition(m, path).map_err(|e| vec![e])?,
+        Some(other) => return Err(vec![ParseErr::new(path, PEType::IncorrectType(other))]),
+        None => return Err(vec![ParseErr::new(path, PEType::MissingKey("when.if"))]),
+    };
+    let branch = |key: &Value| match clause.get(key) {
+        Some(Value::Mapping(m)) => match Constraint::for_type(field_type, m, field_name, parent_path) {
+            YamlParseResult::Single(Ok(c)) => Ok(Some(c)),
+            errors => Err(errors.into_iter().filter_map(Result::err).collect()),
+        },
+        Some(other) => Err(vec![ParseErr::new(path, PEType::IncorrectType(other))]),
+        None => Ok(None),
+    };
+    let then = branch(&THEN)?.ok_or_else(|| vec![ParseErr::new(path, PEType::MissingKey("when.then"))])?;
+    let otherwise = branch(&ELSE)?;
+    Ok(Clause { condition, then, otherwise })
+}
+
+fn condition<'a>(config: &'a Mapping, path: &[&'a Value]) -> Result<Condition<'a>, ParseErr<'a>> {
+    lazy_static! {
+        static ref FIELD: Value = valstr!("field");
+        static ref EQ: Value = valstr!("eq");
+        static ref NEQ: Value = valstr!("neq");
+        static ref IN: Value = valstr!("in");
+        static ref PRESENT: Value = valstr!("present");
+    }
+    let field = match config.get(&FIELD) {
+        Some(Value::Sequence(seq)) => ValueRef::abs_path(seq).map_err(|e| ParseErr::new(path, e))?,
+        Some(other) => return Err(ParseErr::new(path, PEType::IncorrectType(other))),
+        None => return Err(ParseErr::new(path, PEType::MissingKey("when.if.field"))),
+    };
+    let test = if let Some(val) = config.get(&EQ) {
+        Test::Eq(val)
+    } else if let Some(val) = config.get(&NEQ) {
+        Test::Neq(val)
+    } else if let Some(val) = config.get(&IN) {
+        match val {
+            Value::Sequence(seq) => Test::In(seq.iter().collect()),
+            _ => return Err(ParseErr::new(path, PEType::IncorrectType(val))),
+        }
+    } else if let Some(val) = config.get(&PRESENT) {
+        match val {
+            Value::Bool(b) => Test::Present(*b),
+            _ => return Err(ParseErr::new(path, PEType::IncorrectType(val))),
+        }
+    } else {
+        return Err(ParseErr::new(path, PEType::MissingKey("when.if.eq/neq/in/present")));
+    };
+    Ok(Condition { field, test })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(raw: &str) -> Vec<PEType<'static>> {
+        let spec: &'static Mapping = Box::leak(Box::new(serde_yaml::from_str(raw).unwrap()));
+        spec.iter()
+            .flat_map(Constraint::from_spec)
+            .filter_map(Result::err)
+            .map(|e| e.err)
+            .collect()
+    }
+
+    #[test]
+    fn missing_keys_are_named() {
+        assert_eq!(vec![PEType::MissingKey("when.then")], errors("mode:\n  type: string\n  when:\n    if: { field: [a], eq: b }\n"));
+        assert_eq!(vec![PEType::MissingKey("when.if.field")], errors("mode:\n  type: string\n  when:\n    if: { eq: b }\n    then: {}\n"));
+        assert_eq!(vec![PEType::MissingKey("when.if.eq/neq/in/present")], errors("mode:\n  type: string\n  when:\n    if: { field: [a] }\n    then: {}\n"));
+    }
+
+    #[test]
+    fn else_only_on_the_last_clause() {
+        let raw = concat!(
+            "mode:\n",
+            "  type: string\n",
+            "  when:\n",
+            "    - if: { field: [a], eq: x }\n",
+            "      then: { eq: one }\n",
+            "      else: { eq: two }\n",
+            "    - if: { field: [a], eq: y }\n",
+            "      then: { eq: three }\n",
+        );
+        assert_eq!(vec![PEType::MisplacedElse], errors(raw));
+    }
+}
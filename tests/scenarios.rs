@@ -1,5 +1,7 @@
 use serde_yaml::{Mapping, Number, Value};
-use yaml_grammar::{Evaluation, rule::{RuleErrType, RuleEvalErr, RuleEvalSuccess}, valstr, yamlfmt};
+use std::path::Path;
+
+use yaml_grammar::{Evaluation, Summary, diff::{self, Change, ChangeKind}, docs::{self, DocFormat}, example::{self, Fill}, expr::ExprErr, fix, imports::{self, ImportErrKind}, lint::{self, Lint, LintKind}, meta, overlay::{self, EnvErr, EnvErrKind, Layer}, format::FormatErr, options::{Diagnostic, Severity}, parse::{PEType, ParseErr}, quantity::QuantityErr, rule::{Reason, RuleErrType, RuleEvalErr, RuleEvalSuccess}, stream::{self, SelectionErr}, str::RegexOptions, temporal::TemporalErr, value_ref::ValueResolutionErr, valstr, yamlfmt};
 
 mod utils;
 
@@ -64,4 +66,943 @@ pub fn compare_against_default() {
     } else {
         panic!("Result was not `Evaluation::Completed`");
     }
-}
\ No newline at end of file
+}
+
+#[test]
+pub fn every_document_in_stream() {
+    let spec: Mapping = utils::spec("multi-document");
+    let docs = stream::documents(&utils::raw("multi-document", "stream.yaml")).unwrap();
+    let name = valstr!(".");
+    let evals = stream::yamlfmt_stream(&spec, &docs, &name);
+
+    assert_eq!(3, evals.len());
+    let positions: Vec<_> = evals.iter().map(|e| (e.index, e.line)).collect();
+    assert_eq!(positions, vec![(0, 2), (1, 8), (2, 14)]);
+    for eval in evals {
+        if let Ok(Evaluation::Completed { ok, err }) = eval.eval {
+            assert_eq!(2, ok.len());
+            assert!(err.is_empty());
+        } else {
+            panic!("Result was not `Evaluation::Completed`")
+        }
+    }
+}
+
+#[test]
+pub fn stream_selected_by_kind() {
+    let service: Mapping = utils::input("multi-document", "service.yamlfmt");
+    let deployment: Mapping = utils::input("multi-document", "deployment.yamlfmt");
+    let (svc, deploy) = (valstr!("Service"), valstr!("Deployment"));
+    let specs = [(&svc, &service), (&deploy, &deployment)];
+    let docs = stream::documents(&utils::raw("multi-document", "stream.yaml")).unwrap();
+    let (kind, name) = (valstr!("kind"), valstr!("."));
+    let mut evals = stream::yamlfmt_stream_by(&kind, &specs, &docs, &name).into_iter();
+
+    // the service is valid
+    let eval = evals.next().unwrap();
+    if let Ok(Evaluation::Completed { ok, err }) = eval.eval {
+        assert!(ok.iter().all(|s| s.result));
+        assert!(err.is_empty());
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+    // the deployment image is missing a tag
+    let eval = evals.next().unwrap();
+    assert_eq!((eval.index, eval.line), (1, 8));
+    if let Ok(Evaluation::Completed { ok, .. }) = eval.eval {
+        let no_match = Reason::NoMatch { pattern: String::from(":"), options: Default::default() };
+        assert!(ok.contains(&RuleEvalSuccess::failed(&valpath![".", "spec", "image"], no_match)));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+    // nobody registered a spec for config maps
+    let eval = evals.next().unwrap();
+    assert_eq!(eval.eval.unwrap_err(), SelectionErr::UnknownDiscriminator {
+        found: &valstr!("ConfigMap"),
+        valid: vec![&svc, &deploy],
+    });
+}
+
+#[test]
+pub fn variant_selected_by_tag() {
+    let spec: Mapping = utils::spec("tagged-union");
+    let input: Value = utils::input("tagged-union", "s3.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { ok, err } = eval {
+        // successes
+        assert_eq!(2, ok.len());
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "storage", "name"])));
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "storage", "bucket"])));
+        // errors
+        assert_eq!(1, err.len());
+        let (storage, region, s3) = (valpath![".", "storage"], valstr!("region"), valstr!("s3"));
+        let missing = RuleEvalErr::new(&storage, RuleErrType::KeyNotFound(&region));
+        assert!(err.contains(&missing.in_variant(&s3)));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn unknown_variant_lists_valid_tags() {
+    let spec: Mapping = utils::spec("tagged-union");
+    let input: Value = utils::input("tagged-union", "unknown.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { ok, err } = eval {
+        assert!(ok.is_empty());
+        assert_eq!(err, vec![RuleEvalErr::new(&valpath![".", "storage"], RuleErrType::UnknownVariant {
+            key: &valstr!("type"),
+            found: &valstr!("azure"),
+            valid: valpath!["s3", "gcs", "local"].to_vec(),
+        })]);
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn conditional_constraint_applies() {
+    let spec: Mapping = utils::spec("conditional-fields");
+    let input: Value = utils::input("conditional-fields", "wrong-extension.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { ok, err } = eval {
+        assert_eq!(3, ok.len());
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "tls", "enabled"])));
+        let no_match = Reason::NoMatch { pattern: String::from("\\.pem$"), options: Default::default() };
+        assert!(ok.contains(&RuleEvalSuccess::failed(&valpath![".", "tls", "cert_path"], no_match)));
+        // the cert path is present, so only servers are allowed
+        assert!(ok.contains(&RuleEvalSuccess::new(false, &valpath![".", "mode"])));
+        assert!(err.is_empty());
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn conditional_required_field() {
+    let spec: Mapping = utils::spec("conditional-fields");
+    let input: Value = utils::input("conditional-fields", "missing-cert.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { ok, err } = eval {
+        assert_eq!(2, ok.len());
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "mode"])));
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "tls", "enabled"])));
+        assert_eq!(vec![RuleEvalErr::new(&valpath![".", "tls"], RuleErrType::KeyNotFound(&valstr!("cert_path")))], err);
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+
+    // when tls is disabled, the certificate is optional again
+    let input: Value = utils::input("conditional-fields", "disabled.yaml");
+    let eval = yamlfmt(&spec, &input, &name);
+    if let Evaluation::Completed { ok, err } = eval {
+        assert_eq!(2, ok.len());
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "mode"])));
+        assert!(err.is_empty());
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn conflicting_and_missing_keys() {
+    let spec: Mapping = utils::spec("key-relations");
+    let input: Value = utils::input("key-relations", "conflicting.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { err, .. } = eval {
+        assert_eq!(2, err.len());
+        assert!(err.contains(&RuleEvalErr::new(&valpath![".", "auth"], RuleErrType::ExclusiveKeys {
+            keys: valpath!["password", "key_file", "token"].to_vec(),
+            present: valpath!["password", "token"].to_vec(),
+        })));
+        assert!(err.contains(&RuleEvalErr::new(&valpath![".", "limits"], RuleErrType::MissingDependency {
+            key: &valstr!("min"),
+            requires: valpath!["max"].to_vec(),
+            missing: valpath!["max"].to_vec(),
+        })));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+
+    let input: Value = utils::input("key-relations", "empty.yaml");
+    let eval = yamlfmt(&spec, &input, &name);
+    if let Evaluation::Completed { err, .. } = eval {
+        let keys = valpath!["password", "key_file", "token"];
+        let one_of = RuleErrType::OneRequired(keys.to_vec());
+        assert_eq!(vec![RuleEvalErr::new(&valpath![".", "auth"], one_of)], err);
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn cross_field_assertions() {
+    let spec: Mapping = utils::spec("assertions");
+    let input: Value = utils::input("assertions", "input.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { err, .. } = eval {
+        // `namespace` falls back on its default, which pushes the length over
+        assert_eq!(2, err.len());
+        let path = valpath![".", "deployment"];
+        assert!(err.contains(&RuleEvalErr::new(&path, RuleErrType::AssertionFailed("len(name) + len(namespace) < 20"))));
+        assert!(err.contains(&RuleEvalErr::new(&path, RuleErrType::AssertionFailed("port != admin.port"))));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn assertion_syntax_error() {
+    let spec: Mapping = serde_yaml::from_str(concat!(
+        "limits:\n",
+        "  type: object\n",
+        "  assert: min =< max\n",
+    )).unwrap();
+    let input: Value = serde_yaml::from_str("limits: {}").unwrap();
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::GrammarParseErr(err) = eval {
+        assert_eq!(err, vec![ParseErr::new(&valpath!["limits"], PEType::Expression {
+            source: "min =< max",
+            err: ExprErr::UnexpectedChar(4, '='),
+        })]);
+    } else {
+        panic!("Result was not `Evaluation::GrammarParseErr`")
+    }
+}
+
+#[test]
+pub fn string_length_and_affixes() {
+    let spec: Mapping = utils::spec("string-checks");
+    let input: Value = utils::input("string-checks", "input.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { ok, err } = eval {
+        assert!(err.is_empty());
+        assert_eq!(6, ok.len());
+        let (eight, three) = (Number::from(8), Number::from(3));
+        let path = valpath![".", "service", "name"];
+        let too_long = RuleEvalSuccess::failed(&path, Reason::TooLong { length: 10, max: &eight });
+        assert_eq!("length 10 exceeds 8", too_long.reason.as_ref().unwrap().to_string());
+        assert!(ok.contains(&too_long));
+        assert!(ok.contains(&RuleEvalSuccess::failed(&valpath![".", "service", "label"], Reason::TooShort { length: 2, min: &three })));
+        assert!(ok.contains(&RuleEvalSuccess::failed(&valpath![".", "service", "image"], Reason::MissingPrefix("registry.example.com/"))));
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "service", "host"])));
+        assert!(ok.contains(&RuleEvalSuccess::failed(&valpath![".", "service", "motd"], Reason::MissingSubstring("e\u{301}e\u{301}"))));
+        // the regex matches, but the length is still checked
+        assert!(ok.contains(&RuleEvalSuccess::failed(&valpath![".", "service", "code"], Reason::TooLong { length: 8, max: &three })));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn normalized_comparisons() {
+    let spec: Mapping = utils::spec("normalized-strings");
+    let input: Value = utils::input("normalized-strings", "input.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { ok, err } = eval {
+        assert!(err.is_empty());
+        assert_eq!(5, ok.len());
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "env"])));
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "display_name"])));
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "code"])));
+        // without any options, comparisons are exact
+        assert!(ok.contains(&RuleEvalSuccess::new(false, &valpath![".", "strict"])));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn string_formats() {
+    let spec: Mapping = utils::spec("string-formats");
+    let input: Value = utils::input("string-formats", "input.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { ok, err } = eval {
+        assert!(err.is_empty());
+        assert_eq!(7, ok.len());
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "contact"])));
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "subnet"])));
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "request_id"])));
+        let path = valpath![".", "homepage"];
+        let scheme = RuleEvalSuccess::failed(&path, Reason::Format {
+            format: "url",
+            err: FormatErr::SchemeNotAllowed { scheme: String::from("http"), allowed: vec![String::from("https")] },
+        });
+        assert_eq!("not a valid url: scheme \"http\" is not one of https", scheme.reason.as_ref().unwrap().to_string());
+        assert!(ok.contains(&scheme));
+        assert!(ok.contains(&RuleEvalSuccess::failed(&valpath![".", "listen"], Reason::Format {
+            format: "ipv4",
+            err: FormatErr::InvalidOctet(String::from("300")),
+        })));
+        assert!(ok.contains(&RuleEvalSuccess::failed(&valpath![".", "version"], Reason::Format {
+            format: "semver",
+            err: FormatErr::SemverParts(2),
+        })));
+        // allowed, but the format is still checked
+        assert!(ok.contains(&RuleEvalSuccess::failed(&valpath![".", "release"], Reason::Format {
+            format: "semver",
+            err: FormatErr::SemverParts(2),
+        })));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn unknown_string_format() {
+    let spec: Mapping = serde_yaml::from_str(concat!(
+        "isbn:\n",
+        "  type: string\n",
+        "  format: isbn\n",
+    )).unwrap();
+    let input: Value = serde_yaml::from_str("isbn: 978-3-16-148410-0").unwrap();
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::GrammarParseErr(err) = eval {
+        assert_eq!(err, vec![ParseErr::new(&valpath!["isbn"], PEType::UnknownFormat("isbn"))]);
+    } else {
+        panic!("Result was not `Evaluation::GrammarParseErr`")
+    }
+}
+
+#[test]
+pub fn duration_and_bytesize_comparisons() {
+    let spec: Mapping = utils::spec("quantities");
+    let input: Value = utils::input("quantities", "input.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { ok, err } = eval {
+        assert!(err.is_empty());
+        assert_eq!(5, ok.len());
+        // the idle timeout isn't set, so the timeout is compared against its default
+        let (path, idle) = (valpath![".", "server", "timeout"], valstr!("1m"));
+        let timeout = RuleEvalSuccess::failed(&path, Reason::Comparison { op: "<", bound: &idle });
+        assert_eq!("is not < 1m", timeout.reason.as_ref().unwrap().to_string());
+        assert!(ok.contains(&timeout));
+        assert!(ok.contains(&RuleEvalSuccess::failed(&valpath![".", "server", "grace_period"], Reason::Comparison {
+            op: ">=",
+            bound: &valstr!("5s"),
+        })));
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "server", "max_body"])));
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "server", "buffer"])));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn invalid_quantities() {
+    let spec: Mapping = utils::spec("quantities");
+    let input: Value = utils::input("quantities", "invalid.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { ok, err } = eval {
+        assert!(err.is_empty());
+        assert_eq!(5, ok.len());
+        assert!(ok.contains(&RuleEvalSuccess::failed(&valpath![".", "server", "timeout"], Reason::InvalidQuantity {
+            unit: "duration",
+            err: QuantityErr::UnknownUnit(String::from(" seconds")),
+        })));
+        // plain numbers are already in seconds
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "server", "grace_period"])));
+        assert!(ok.contains(&RuleEvalSuccess::failed(&valpath![".", "server", "max_body"], Reason::Comparison {
+            op: "<=",
+            bound: &valstr!("1GiB"),
+        })));
+        assert!(ok.contains(&RuleEvalSuccess::failed(&valpath![".", "server", "buffer"], Reason::Comparison {
+            op: ">=",
+            bound: &valstr!("2GB"),
+        })));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn invalid_quantity_literal() {
+    let spec: Mapping = serde_yaml::from_str(concat!(
+        "timeout:\n",
+        "  type: duration\n",
+        "  lt: 5 minutes\n",
+    )).unwrap();
+    let input: Value = serde_yaml::from_str("timeout: 30s").unwrap();
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::GrammarParseErr(err) = eval {
+        assert_eq!(err, vec![ParseErr::new(&valpath!["timeout"], PEType::Quantity {
+            source: "5 minutes",
+            err: QuantityErr::UnknownUnit(String::from(" minutes")),
+        })]);
+    } else {
+        panic!("Result was not `Evaluation::GrammarParseErr`")
+    }
+}
+
+#[test]
+pub fn dates_and_times_in_order() {
+    let spec: Mapping = utils::spec("date-times");
+    let input: Value = utils::input("date-times", "valid.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { ok, err } = eval {
+        assert!(err.is_empty());
+        assert_eq!(4, ok.len());
+        assert!(ok.iter().all(|s| s.result));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn dates_and_times_out_of_order() {
+    let spec: Mapping = utils::spec("date-times");
+    let input: Value = utils::input("date-times", "input.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { ok, err } = eval {
+        assert!(err.is_empty());
+        assert_eq!(4, ok.len());
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "window", "start"])));
+        let (path, start) = (valpath![".", "window", "end"], valstr!("2024-03-01T22:00:00Z"));
+        let end = RuleEvalSuccess::failed(&path, Reason::Comparison { op: "after", bound: &start });
+        assert_eq!("is not after 2024-03-01T22:00:00Z", end.reason.as_ref().unwrap().to_string());
+        assert!(ok.contains(&end));
+        assert!(ok.contains(&RuleEvalSuccess::failed(&valpath![".", "window", "daily_at"], Reason::Comparison {
+            op: "after",
+            bound: &valstr!("06:00:00"),
+        })));
+        assert!(ok.contains(&RuleEvalSuccess::failed(&valpath![".", "certificate", "expires"], Reason::InvalidTemporal {
+            kind: "date",
+            err: TemporalErr::Day { day: 30, month: 2 },
+        })));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn capture_groups_as_values() {
+    let spec: Mapping = utils::spec("capture-groups");
+    let input: Value = utils::input("capture-groups", "input.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { ok, err } = eval {
+        assert!(err.is_empty());
+        assert_eq!(3, ok.len());
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "deployment", "image"])));
+        // the tag is 1.4.2, not 1.4.1
+        assert!(ok.contains(&RuleEvalSuccess::new(false, &valpath![".", "deployment", "version"])));
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "deployment", "registry"])));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn capture_group_without_match() {
+    let spec: Mapping = utils::spec("capture-groups");
+    let input: Value = utils::input("capture-groups", "untagged.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::ValueResolutionErr(err) = eval {
+        let path = valpath!["deployment", "image"];
+        assert_eq!(2, err.len());
+        assert!(err.contains(&ValueResolutionErr::NoCapture { path: path.to_vec(), group: "tag" }));
+        assert!(err.contains(&ValueResolutionErr::NoCapture { path: path.to_vec(), group: "repo" }));
+    } else {
+        panic!("Result was not `Evaluation::ValueResolutionErr`")
+    }
+}
+
+#[test]
+pub fn regex_options() {
+    let spec: Mapping = utils::spec("regex-options");
+    let input: Value = utils::input("regex-options", "input.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { ok, err } = eval {
+        assert!(err.is_empty());
+        assert_eq!(4, ok.len());
+        let path = valpath![".", "port"];
+        let options = RegexOptions { full: true, ..Default::default() };
+        let port = RuleEvalSuccess::failed(&path, Reason::NoMatch { pattern: String::from("\\d+"), options });
+        assert_eq!("does not match \"\\\\d+\" (full match)", port.reason.as_ref().unwrap().to_string());
+        assert!(ok.contains(&port));
+        // the same pattern only has to match part of the value by default
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "loose_port"])));
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "banner"])));
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "greeting"])));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn regex_size_limit() {
+    let spec: Mapping = serde_yaml::from_str(concat!(
+        "id:\n",
+        "  type: string\n",
+        "  regex: \\w{100}\n",
+        "  size_limit: 1000\n",
+    )).unwrap();
+    let input: Value = serde_yaml::from_str("id: abc").unwrap();
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::GrammarParseErr(err) = eval {
+        assert_eq!(err, vec![ParseErr::new(&valpath!["id"], PEType::RegexSizeLimit { source: "\\w{100}", limit: 1000 })]);
+    } else {
+        panic!("Result was not `Evaluation::GrammarParseErr`")
+    }
+}
+
+#[test]
+pub fn custom_messages_and_codes() {
+    let spec: Mapping = utils::spec("custom-messages");
+    let input: Value = utils::input("custom-messages", "input.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    if let Evaluation::Completed { ok, err } = eval {
+        assert_eq!(2, ok.len());
+        let name = ok.iter().find(|s| s.path == valpath![".", "service", "name"]).unwrap();
+        assert_eq!(name.diagnostic, Some(Diagnostic {
+            code: Some(String::from("E1001")),
+            message: Some(String::from("service.name must be a DNS label, but Storefront_API isn't")),
+        }));
+        // fields without a message keep the built-in result
+        assert!(ok.contains(&RuleEvalSuccess::new(false, &valpath![".", "service", "tier"])));
+        let (service, owner) = (valpath![".", "service"], valstr!("owner"));
+        let missing = RuleEvalErr::new(&service, RuleErrType::KeyNotFound(&owner));
+        assert_eq!(err, vec![missing.with_diagnostic(Some(Diagnostic {
+            code: Some(String::from("E1002")),
+            message: Some(String::from("every service needs an owner for paging")),
+        }))]);
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn warnings_do_not_fail_evaluation() {
+    let spec: Mapping = utils::spec("severities");
+    let input: Value = utils::input("severities", "input.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    let summary = eval.summary();
+    assert_eq!(summary, Summary { passed: 1, errors: 0, warnings: 2, infos: 1 });
+    assert!(summary.is_ok());
+    if let Evaluation::Completed { ok, err } = eval {
+        let (service, team) = (valpath![".", "service"], valstr!("team"));
+        let missing = RuleEvalErr::new(&service, RuleErrType::KeyNotFound(&team));
+        assert_eq!(err, vec![missing.with_severity(Severity::Warning)]);
+        let description = ok.iter().find(|s| s.path == valpath![".", "service", "description"]).unwrap();
+        assert_eq!(description.severity, Severity::Info);
+        let image = ok.iter().find(|s| s.path == valpath![".", "service", "image"]).unwrap();
+        assert_eq!(image.severity, Severity::Warning);
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn errors_fail_evaluation() {
+    let spec: Mapping = utils::spec("nested-strings");
+    let input: Value = utils::input("nested-strings", "input.yaml");
+    let name = valstr!(".");
+    let summary = yamlfmt(&spec, &input, &name).summary();
+
+    assert_eq!(summary, Summary { passed: 2, errors: 2, warnings: 0, infos: 0 });
+    assert!(!summary.is_ok());
+}
+
+#[test]
+pub fn renamed_and_deprecated_fields() {
+    let spec: Mapping = utils::spec("migrations");
+    let input: Value = utils::input("migrations", "input.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&spec, &input, &name);
+
+    assert_eq!(eval.summary(), Summary { passed: 2, errors: 1, warnings: 2, infos: 0 });
+    if let Evaluation::Completed { ok, err } = eval {
+        assert!(err.is_empty());
+        // the old key's value is still checked under the new name
+        assert!(ok.contains(&RuleEvalSuccess::new(false, &valpath![".", "deployment", "replicas"])));
+        let (path, from, to) = (valpath![".", "deployment", "instances"], valstr!("instances"), valstr!("replicas"));
+        let renamed = RuleEvalSuccess::failed(&path, Reason::Renamed { from: &from, to: &to });
+        assert_eq!("has been renamed to \"replicas\"", renamed.reason.as_ref().unwrap().to_string());
+        assert!(ok.contains(&renamed.with_severity(Severity::Warning)));
+        let hint = String::from("rollouts are always rolling now, remove `strategy`");
+        let strategy = valpath![".", "deployment", "strategy"];
+        let deprecated = RuleEvalSuccess::failed(&strategy, Reason::Deprecated(hint));
+        assert!(ok.contains(&deprecated.with_severity(Severity::Warning)));
+        // the deprecated field is still validated as usual
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "deployment", "strategy"])));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn autofix_rewrites_input() {
+    let spec: Mapping = utils::spec("autofix");
+    let raw = utils::raw("autofix", "input.yaml");
+    let name = valstr!(".");
+
+    // deprecated values are reported before they're fixed
+    let input: Value = serde_yaml::from_str(&raw).unwrap();
+    let (found, strategy) = (valstr!("blue-green"), valpath![".", "service", "strategy"]);
+    let deprecated = RuleEvalSuccess::failed(&strategy, Reason::DeprecatedValue { found: &found, replacement: valstr!("Rolling") });
+    assert_eq!("blue-green is deprecated, use Rolling instead", deprecated.reason.as_ref().unwrap().to_string());
+    if let Evaluation::Completed { ok, .. } = yamlfmt(&spec, &input, &name) {
+        assert!(ok.contains(&deprecated.with_severity(Severity::Warning)));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+
+    let fixed = fix::fix(&spec, &raw, &name).unwrap();
+    assert_eq!(utils::raw("autofix", "fixed.yaml"), fixed.text);
+    assert!(fixed.skipped.is_empty());
+    let applied: Vec<_> = fixed.applied.iter().map(ToString::to_string).collect();
+    assert_eq!(vec![
+        "service.port: added with default 8080",
+        "service.region: replaced EU-West with eu-west",
+        "service.instances: renamed to replicas",
+        "service.strategy: replaced blue-green with Rolling",
+        "service.tier: added with default web",
+    ], applied);
+
+    // Windows line endings are kept
+    let crlf = fix::fix(&spec, &raw.replace('\n', "\r\n"), &name).unwrap();
+    assert_eq!(utils::raw("autofix", "fixed.yaml").replace('\n', "\r\n"), crlf.text);
+
+    // the fixed input passes without any warnings
+    let input: Value = serde_yaml::from_str(&fixed.text).unwrap();
+    assert_eq!(yamlfmt(&spec, &input, &name).summary(), Summary { passed: 5, errors: 0, warnings: 0, infos: 0 });
+}
+
+#[test]
+pub fn fixture_specs_follow_meta_grammar() {
+    let name = valstr!(".");
+    for entry in std::fs::read_dir("tests/res").unwrap() {
+        let dir = entry.unwrap().path();
+        let path = dir.join("spec.yamlfmt");
+        if path.exists() && !dir.ends_with("import-errors") {
+            let composed = imports::load(&path, &utils::registry()).unwrap();
+            let spec = overlay::environment(&composed.spec, None).unwrap();
+            let eval = meta::check(&spec, &name);
+            assert!(eval.summary().is_ok(), "{}: {:?}", path.display(), eval);
+        }
+    }
+}
+
+#[test]
+pub fn meta_grammar_flags_spec_mistakes() {
+    let spec: Mapping = utils::input("meta-spec", "invalid.yamlfmt");
+    let name = valstr!(".");
+    let eval = meta::check(&spec, &name);
+
+    assert_eq!(5, eval.summary().errors);
+    if let Evaluation::Completed { ok, err } = eval {
+        let (regx, string, sequence) = (valstr!("regx"), valstr!("string"), valstr!("sequence"));
+        let unknown = err.iter().find(|e| e.path == valpath![".", "service", "fields", "name"]).unwrap();
+        assert!(matches!(&unknown.err, RuleErrType::UnknownKey { key, valid } if *key == &regx && valid.contains(&&valstr!("regex"))));
+        // branches are checked against the settings of their field's type
+        let branch = err.iter().find(|e| e.path == valpath![".", "service", "fields", "region", "when", "then"]).unwrap();
+        assert!(matches!(&branch.err, RuleErrType::UnknownKey { key, valid } if *key == &regx && valid.contains(&&valstr!("regex"))));
+        let port = ok.iter().find(|s| s.path == valpath![".", "service", "fields", "port"]).unwrap();
+        assert!(!port.result);
+        assert_eq!(Some(String::from("integr is not a known type")), port.diagnostic.as_ref().unwrap().message);
+        assert!(ok.contains(&RuleEvalSuccess::new(false, &valpath![".", "service", "fields", "tier", "severity"])));
+        let tags = err.iter().find(|e| e.path == valpath![".", "service", "fields", "tags"]).unwrap();
+        assert!(matches!(&tags.err, RuleErrType::UnknownVariant { found, valid, .. } if *found == &sequence && valid.contains(&&string)));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+}
+
+#[test]
+pub fn lint_spec_mistakes() {
+    let spec: Mapping = utils::spec("lint");
+    let lints = lint::lint(&spec).unwrap();
+
+    let (service, name, tier, version, timeout) = (valstr!("service"), valstr!("name"), valstr!("tier"), valstr!("version"), valstr!("timeout"));
+    let (regex, allowed, web) = (valstr!("regex"), valstr!("allowed"), valstr!("web"));
+    let (max_length, limits, min, gt) = (valstr!("max_length"), valstr!("limits"), valstr!("min"), valstr!("gt"));
+    assert_eq!(4, lints.len());
+    assert!(lints.contains(&Lint { path: vec![&service, &name], kind: LintKind::Conflicting { used: &regex, ignored: vec![&allowed] } }));
+    assert!(lints.contains(&Lint { path: vec![&service, &tier], kind: LintKind::DuplicateValue { key: &allowed, value: &web } }));
+    let dangling = Lint { path: vec![&service, &version], kind: LintKind::DanglingRef { key: &max_length, target: vec![&service, &limits, &version] } };
+    assert_eq!("service.version: max_length refers to service.limits.version, which isn't in the spec", dangling.to_string());
+    assert!(lints.contains(&dangling));
+    assert!(lints.contains(&Lint { path: vec![&service, &timeout], kind: LintKind::Conflicting { used: &min, ignored: vec![&gt] } }));
+}
+
+#[test]
+pub fn fixture_specs_lint_clean() {
+    for entry in std::fs::read_dir("tests/res").unwrap() {
+        let dir = entry.unwrap().path();
+        let path = dir.join("spec.yamlfmt");
+        // string-checks refers to a number, which specs can't describe yet, and
+        // invalid-defaults, reference-graph and import-errors don't parse at all
+        let exempt = ["lint", "string-checks", "invalid-defaults", "reference-graph", "import-errors"];
+        if path.exists() && !exempt.iter().any(|e| dir.ends_with(e)) {
+            let composed = imports::load(&path, &utils::registry()).unwrap();
+            let spec = overlay::environment(&composed.spec, None).unwrap();
+            assert_eq!(Ok(vec![]), lint::lint(&spec), "{}", path.display());
+        }
+    }
+}
+
+#[test]
+pub fn defaults_must_pass_their_own_constraint() {
+    let spec: Mapping = utils::spec("invalid-defaults");
+    let input: Value = Value::Mapping(Mapping::new());
+    let name = valstr!(".");
+
+    if let Evaluation::GrammarParseErr(err) = yamlfmt(&spec, &input, &name) {
+        assert_eq!(3, err.len());
+        let failures = |field: &str| {
+            let e = err.iter().find(|e| e.path == valpath!["service", field]).unwrap();
+            match &e.err {
+                PEType::InvalidDefault { failures, .. } => failures.clone(),
+                other => panic!("{:?}", other),
+            }
+        };
+        let name = failures("name");
+        assert!(matches!(&name[..], [Ok(RuleEvalSuccess { reason: Some(Reason::NoMatch { .. }), .. })]));
+        let memory = valstr!("memory");
+        let limits = failures("limits");
+        assert!(matches!(&limits[..], [Err(RuleEvalErr { err: RuleErrType::KeyNotFound(key), .. })] if *key == &memory));
+        let timeout = failures("timeout");
+        assert!(matches!(&timeout[..], [Ok(RuleEvalSuccess { reason: Some(Reason::Comparison { .. }), .. })]));
+    } else {
+        panic!("Result was not `Evaluation::GrammarParseErr`")
+    }
+}
+
+#[test]
+pub fn reference_graph_errors() {
+    let spec: Mapping = utils::spec("reference-graph");
+    let input: Value = Value::Mapping(Mapping::new());
+    let name = valstr!(".");
+    let (owner, team) = (valpath!["service", "owner"], valpath!["service", "team"]);
+    let (selector, labels) = (valpath!["service", "selector"], valpath!["service", "labels"]);
+    let (timeout, max_body) = (valpath!["service", "timeout"], valpath!["service", "max_body"]);
+    let version = valpath!["service", "version"];
+    let (primary, secondary) = (valpath!["service", "primary"], valpath!["service", "secondary"]);
+    let (region, zone) = (valpath!["service", "region"], valpath!["service", "zone"]);
+
+    if let Evaluation::GrammarParseErr(err) = yamlfmt(&spec, &input, &name) {
+        assert_eq!(6, err.len());
+        let dangling = PEType::DanglingRef(team.to_vec());
+        assert!(err.contains(&ParseErr::new(&owner, dangling)));
+        let object = PEType::RefTypeMismatch { target: labels.to_vec(), expected: String::from("string"), found: "object" };
+        assert!(err.contains(&ParseErr::new(&selector, object)));
+        let unit = PEType::RefTypeMismatch { target: max_body.to_vec(), expected: String::from("duration"), found: "bytesize" };
+        assert!(err.contains(&ParseErr::new(&timeout, unit)));
+        let expected = String::from("string with a regex capture group named \"tag\"");
+        let group = PEType::RefTypeMismatch { target: selector.to_vec(), expected, found: "string" };
+        assert!(err.contains(&ParseErr::new(&version, group)));
+        let cycle = PEType::RefCycle(vec![primary.to_vec(), secondary.to_vec()]);
+        assert!(err.contains(&ParseErr::new(&primary, cycle)));
+        // a condition has to test a field the spec knows about
+        assert!(err.contains(&ParseErr::new(&region, PEType::DanglingRef(zone.to_vec()))));
+    } else {
+        panic!("Result was not `Evaluation::GrammarParseErr`")
+    }
+}
+
+#[test]
+pub fn imports_compose_spec_files() {
+    let composed = imports::load(Path::new("tests/res/imports/spec.yamlfmt"), &utils::registry()).unwrap();
+    let input: Value = utils::input("imports", "input.yaml");
+    let name = valstr!(".");
+    let eval = yamlfmt(&composed.spec, &input, &name);
+
+    assert_eq!(2, eval.summary().errors);
+    if let Evaluation::Completed { ok, err } = eval {
+        assert!(err.is_empty());
+        assert!(ok.contains(&RuleEvalSuccess::new(false, &valpath![".", "logging", "level"])));
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "server", "tls", "min_version"])));
+        assert!(ok.contains(&RuleEvalSuccess::new(false, &valpath![".", "replica", "tls", "min_version"])));
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &valpath![".", "replica", "primary"])));
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+
+    let file = |path: &[&Value]| composed.file_of(path).to_path_buf();
+    assert_eq!(Path::new("tests/res/imports/common/logging.yamlfmt"), file(&valpath!["logging", "level"]));
+    assert_eq!(Path::new("tests/res/imports/common/database.yamlfmt"), file(&valpath!["database"]));
+    assert_eq!(Path::new("tests/res/imports/common/tls.yamlfmt"), file(&valpath!["server", "tls", "cert"]));
+    assert_eq!(Path::new("tests/res/imports/spec.yamlfmt"), file(&valpath!["server", "tls", "min_version"]));
+    assert_eq!(Path::new("tests/res/imports/common/tls.yamlfmt"), file(&valpath!["replica", "tls", "key"]));
+    assert_eq!(Path::new("tests/res/imports/spec.yamlfmt"), file(&valpath!["replica", "host"]));
+}
+
+#[test]
+pub fn import_errors_point_at_their_files() {
+    let err = imports::load(Path::new("tests/res/import-errors/spec.yamlfmt"), &utils::registry()).unwrap_err();
+    let (spec, cycle_a, cycle_b) = (
+        Path::new("tests/res/import-errors/spec.yamlfmt"),
+        Path::new("tests/res/import-errors/cycle-a.yamlfmt"),
+        Path::new("tests/res/import-errors/cycle-b.yamlfmt"),
+    );
+
+    assert_eq!(5, err.len());
+    assert!(err.iter().any(|e| e.file == cycle_b && matches!(&e.kind, ImportErrKind::ImportCycle(files) if *files == [cycle_a, cycle_b, cycle_a])));
+    assert!(err.iter().any(|e| e.file == spec && matches!(&e.kind, ImportErrKind::Remote(source) if source == "https://example.com/shared.yamlfmt")));
+    assert!(err.iter().any(|e| e.file == spec && matches!(&e.kind, ImportErrKind::UnknownName(name) if name == "unregistered")));
+    let missing = vec![valstr!("missing")];
+    assert!(err.iter().any(|e| e.file == spec && e.path == [valstr!("service")] && matches!(&e.kind, ImportErrKind::DanglingExtends(target) if *target == missing)));
+    // the conflict is reported where the second definition was written
+    let region_a = Path::new("tests/res/import-errors/region-a.yamlfmt");
+    let region_b = Path::new("tests/res/import-errors/region-b.yamlfmt");
+    assert!(err.iter().any(|e| e.file == region_b && e.path == [valstr!("region")] && matches!(&e.kind, ImportErrKind::Conflict(first) if first == region_a)));
+}
+
+#[test]
+pub fn diamond_imports_share_a_definition() {
+    let composed = imports::load(Path::new("tests/res/import-diamond/spec.yamlfmt"), &utils::registry()).unwrap();
+    let common = Path::new("tests/res/import-diamond/common.yamlfmt");
+    assert_eq!(common, composed.file_of(&valpath!["tls"]));
+    assert_eq!(common, composed.file_of(&valpath!["server", "tls", "cert"]));
+    assert_eq!(Path::new("tests/res/import-diamond/server.yamlfmt"), composed.file_of(&valpath!["server", "host"]));
+}
+
+#[test]
+pub fn environment_overlays() {
+    let spec: Mapping = utils::spec("environments");
+    let layers = [
+        Layer { name: String::from("base.yaml"), value: utils::input("environments", "base.yaml") },
+        Layer { name: String::from("prod.yaml"), value: utils::input("environments", "prod.yaml") },
+    ];
+    let layered = overlay::merge(&layers);
+    let name = valstr!(".");
+
+    // the base grammar accepts the merged input
+    let base = overlay::environment(&spec, None).unwrap();
+    assert_eq!(Summary { passed: 2, ..Summary::default() }, yamlfmt(&base, &layered.value, &name).summary());
+
+    // prod narrows the log levels and requires tls
+    let prod = overlay::environment(&spec, Some("prod")).unwrap();
+    if let Evaluation::Completed { ok, err } = yamlfmt(&prod, &layered.value, &name) {
+        let (level, timeout) = (valpath![".", "service", "log_level"], valpath![".", "service", "timeout"]);
+        assert!(ok.contains(&RuleEvalSuccess::new(false, &level)));
+        assert!(ok.contains(&RuleEvalSuccess::new(true, &timeout)));
+        assert_eq!(Some("base.yaml"), layered.layer_of(&level));
+        assert_eq!(Some("prod.yaml"), layered.layer_of(&timeout));
+        let tls = valstr!("tls");
+        assert_eq!(vec![RuleEvalErr::new(&valpath![".", "service"], RuleErrType::KeyNotFound(&tls))], err);
+    } else {
+        panic!("Result was not `Evaluation::Completed`")
+    }
+
+    let unknown = EnvErrKind::UnknownEnvironment { name: String::from("qa"), known: vec![String::from("prod"), String::from("staging")] };
+    assert_eq!(Err(vec![EnvErr { path: vec![], kind: unknown }]), overlay::environment(&spec, Some("qa")));
+    let widened: Mapping = utils::input("environments", "widened.yamlfmt");
+    let err = EnvErr { path: vec![valstr!("log_level")], kind: EnvErrKind::Widened(vec![valstr!("debug")]) };
+    assert_eq!(Err(vec![err]), overlay::environment(&widened, Some("dev")));
+}
+
+#[test]
+pub fn spec_diff_classifies_changes() {
+    let old: Mapping = utils::input("spec-diff", "old.yamlfmt");
+    let new: Mapping = utils::input("spec-diff", "new.yamlfmt");
+    let diff = diff::diff(&old, &new).unwrap();
+
+    let service = valstr!("service");
+    let field = |name: &str| valstr!(name);
+    let (env, legacy, log_format, name, owner, region, zone) = (field("env"), field("legacy_flag"), field("log_format"), field("name"), field("owner"), field("region"), field("zone"));
+    let expected = vec![
+        Change { path: vec![&service, &env], kind: ChangeKind::AllowedNarrowed(vec![String::from("dev")]) },
+        Change { path: vec![&service, &env], kind: ChangeKind::AllowedWidened(vec![String::from("qa")]) },
+        Change { path: vec![&service, &legacy], kind: ChangeKind::Removed { closed: false } },
+        Change { path: vec![&service, &log_format], kind: ChangeKind::DefaultRemoved { required: true } },
+        Change { path: vec![&service, &name], kind: ChangeKind::RegexChanged { from: String::from("[a-z]+"), to: String::from("^[a-z]+$"), tighter: Some(true) } },
+        Change { path: vec![&service, &owner], kind: ChangeKind::Renamed { from: valstr!("team") } },
+        Change { path: vec![&service, &region], kind: ChangeKind::Added { required: true } },
+        Change { path: vec![&service, &zone], kind: ChangeKind::Added { required: false } },
+    ];
+    assert_eq!(expected, diff.changes);
+    let breaking: Vec<_> = diff.changes.iter().filter(|c| c.is_breaking()).map(ToString::to_string).collect();
+    assert_eq!(vec![
+        "service.env: no longer allows dev (breaking)",
+        "service.log_format: default removed (breaking)",
+        "service.name: regex changed from [a-z]+ to ^[a-z]+$ (probably tighter) (breaking)",
+        "service.region: added as a required field (breaking)",
+    ], breaking);
+    // both specs say they're version 1
+    assert_eq!(Some(&Value::Number(Number::from(1))), diff.new_version);
+    assert!(diff.needs_major_version());
+}
+
+#[test]
+pub fn docs_render_a_field_reference() {
+    let spec: Mapping = utils::spec("docs");
+    assert_eq!(utils::raw("docs", "reference.md"), docs::render(&spec, DocFormat::Markdown).unwrap());
+
+    let html = docs::render(&spec, DocFormat::Html).unwrap();
+    let read = "<tr><td><code id=\"timeouts-read\">timeouts.read</code></td><td>duration</td><td>yes</td><td></td>\
+        <td>at least <a href=\"#timeouts-connect\"><code>timeouts.connect</code></a></td>\
+        <td>How long to wait for a response. It can't be shorter than the connect timeout.</td></tr>";
+    assert!(html.contains(read), "{}", html);
+}
+
+#[test]
+pub fn examples_are_valid_documents() {
+    let spec: Mapping = utils::spec("examples");
+    let name = valstr!(".");
+    for (fill, file) in [(Fill::Minimal, "minimal.yaml"), (Fill::Full, "full.yaml")] {
+        let example = example::example(&spec, fill).unwrap();
+        assert_eq!(utils::raw("examples", file), example.text);
+        assert_eq!(example.value, serde_yaml::from_str::<Value>(&example.text).unwrap());
+        // no deprecated fields or values either, which would be warnings
+        let summary = yamlfmt(&spec, &example.value, &name).summary();
+        assert_eq!(Summary { passed: summary.passed, ..Summary::default() }, summary, "{}", example.text);
+    }
+}
+
+#[test]
+pub fn examples_satisfy_other_specs() {
+    let name = valstr!(".");
+    let cases = ["capture-groups", "conditional-fields", "date-times", "default-values", "key-relations", "quantities", "regex-options", "string-formats", "tagged-union"];
+    for case in cases {
+        let spec: Mapping = utils::spec(case);
+        for fill in [Fill::Minimal, Fill::Full] {
+            let example = example::example(&spec, fill).unwrap();
+            let summary = yamlfmt(&spec, &example.value, &name).summary();
+            assert_eq!(Summary { passed: summary.passed, ..Summary::default() }, summary, "{}: {}", case, example.text);
+        }
+    }
+}
@@ -1,9 +1,10 @@
 use serde::de::DeserializeOwned;
+use yaml_grammar::imports::Registry;
 
 #[macro_export]
 macro_rules! valpath {
-    ($($x:expr,)*) => (vec![$(&valstr!($x)),*]);
-    ($($x:expr),*) => (vec![$(&valstr!($x)),*]);
+    ($($x:expr,)*) => ([$(&valstr!($x)),*]);
+    ($($x:expr),*) => ([$(&valstr!($x)),*]);
 }
 
 #[macro_export]
@@ -23,4 +24,16 @@ pub fn input<T: DeserializeOwned>(case: &str, file: &str) -> T {
     let path = format!("tests/res/{}/{}", case, file);
     let raw = std::fs::read_to_string(path).unwrap();
     serde_yaml::from_str(&raw).unwrap()
-}
\ No newline at end of file
+}
+
+pub fn raw(case: &str, file: &str) -> String {
+    let path = format!("tests/res/{}/{}", case, file);
+    std::fs::read_to_string(path).unwrap()
+}
+
+/// The specs that fixtures import by name
+pub fn registry() -> Registry {
+    let mut registry = Registry::default();
+    registry.register("database", "tests/res/imports/common/database.yamlfmt");
+    registry
+}